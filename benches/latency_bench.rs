@@ -0,0 +1,53 @@
+extern crate threads_pool;
+
+use std::num::NonZeroUsize;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use threads_pool::prelude::*;
+
+const WORKERS: usize = 4;
+const BURSTS: usize = 50;
+const BURST_SIZE: usize = 1000;
+
+/// Measure the time between submitting a no-op job and a worker starting to run it, with bursts of
+/// jobs separated by quiet periods, such that the workers will have to wake up for every burst.
+fn main() {
+    let pool = ThreadPool::new(NonZeroUsize::new(WORKERS).unwrap());
+    let mut latencies = Vec::with_capacity(BURSTS * BURST_SIZE);
+
+    for _ in 0..BURSTS {
+        let (tx, rx) = mpsc::channel();
+
+        for _ in 0..BURST_SIZE {
+            let tx = tx.clone();
+            let submitted = Instant::now();
+
+            pool.exec(
+                move || {
+                    tx.send(submitted.elapsed()).unwrap_or_default();
+                },
+                false,
+            )
+            .expect("Unable to submit the job");
+        }
+
+        drop(tx);
+        latencies.extend(rx.iter());
+
+        // let the workers go back to park before the next burst
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    latencies.sort();
+
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    println!(
+        "job start latency with {} workers, {} bursts of {} no-op jobs: p50 = {:?}, p99 = {:?}",
+        WORKERS,
+        BURSTS,
+        BURST_SIZE,
+        percentile(50),
+        percentile(99)
+    );
+}
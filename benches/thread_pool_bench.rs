@@ -3,17 +3,18 @@ extern crate criterion;
 extern crate threads_pool;
 
 use criterion::{black_box, Criterion};
-use std::sync::atomic;
+use std::hint;
+use std::num::NonZeroUsize;
 use threads_pool::prelude::*;
 
 fn pool_base(size: usize, bound: usize) {
-    let mut pool = ThreadPool::new(size);
+    let mut pool = ThreadPool::new(NonZeroUsize::new(size).unwrap());
 
     for num in 1..bound {
         pool.exec(
             move || {
                 for _ in 1..num % 4 {
-                    atomic::spin_loop_hint();
+                    hint::spin_loop();
                 }
             },
             false,
@@ -31,7 +32,7 @@ fn pool_bench(c: &mut Criterion) {
 }
 
 fn single_bench(c: &mut Criterion) {
-    shared_mode::initialize(black_box(16));
+    shared_mode::initialize(black_box(16)).expect("Unable to spawn the pool workers");
     let bound = black_box(400);
 
     c.bench_function("base single mode measurement", move |b| {
@@ -39,9 +40,10 @@ fn single_bench(c: &mut Criterion) {
             for num in 1..bound {
                 shared_mode::run(move || {
                     for _ in 1..num % 16 {
-                        atomic::spin_loop_hint();
+                        hint::spin_loop();
                     }
-                });
+                })
+                .unwrap_or_default();
             }
         })
     });
@@ -49,5 +51,29 @@ fn single_bench(c: &mut Criterion) {
     shared_mode::close();
 }
 
-criterion_group!(benches, pool_bench, single_bench);
+fn batch_bench(c: &mut Criterion) {
+    let bound = black_box(100_000);
+
+    c.bench_function("exec loop of empty jobs", move |b| {
+        b.iter(|| {
+            let mut pool = ThreadPool::new(NonZeroUsize::new(16).unwrap());
+
+            for _ in 0..bound {
+                pool.exec(|| {}, false).unwrap_or_default();
+            }
+
+            pool.close();
+        })
+    });
+
+    c.bench_function("exec batch of empty jobs", move |b| {
+        b.iter(|| {
+            let mut pool = ThreadPool::new(NonZeroUsize::new(16).unwrap());
+            pool.exec_batch((0..bound).map(|_| || {})).unwrap_or_default();
+            pool.close();
+        })
+    });
+}
+
+criterion_group!(benches, pool_bench, single_bench, batch_bench);
 criterion_main!(benches);
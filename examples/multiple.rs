@@ -14,7 +14,7 @@ fn main() {
     pool_def.insert(key_one.clone(), 2);
     pool_def.insert(key_two.clone(), 2);
 
-    index_mode::initialize(pool_def);
+    index_mode::initialize(pool_def, None).expect("Unable to spawn the pool workers");
 
     let t1 = thread::spawn(move || {
         for num in 0..50 {
@@ -22,7 +22,8 @@ fn main() {
             index_mode::run_with(pool_key, move || {
                 println!("I'm in with key_one: {}", num);
                 thread::sleep(Duration::from_millis(1));
-            });
+            })
+            .expect("Unable to submit the job");
         }
     });
 
@@ -32,14 +33,16 @@ fn main() {
             index_mode::run_with(pool_key, move || {
                 println!("I'm in with key_two: {}", num);
                 thread::sleep(Duration::from_millis(1));
-            });
+            })
+            .expect("Unable to submit the job");
 
             if num > 80 {
                 let pool_key = key_one_clone.clone();
                 index_mode::run_with(pool_key, move || {
                     println!("I'm in with key_one: {}", num);
                     thread::sleep(Duration::from_micros(500));
-                });
+                })
+                .expect("Unable to submit the job");
             }
         }
     });
@@ -1,5 +1,6 @@
 extern crate threads_pool;
 
+use std::num::NonZeroUsize;
 use std::thread;
 use std::time::{Duration, Instant};
 use threads_pool::prelude::*;
@@ -8,7 +9,7 @@ fn main() {
     let size = 16;
     let bound: usize = 400;
 
-    let mut pool = ThreadPool::new(size);
+    let mut pool = ThreadPool::new(NonZeroUsize::new(size).unwrap());
     let now = Instant::now();
 
     for num in 0..bound {
@@ -8,7 +8,7 @@ fn main() {
     let size = 16;
     let bound: usize = 400;
 
-    shared_mode::initialize(size);
+    shared_mode::initialize(size).expect("Unable to spawn the pool workers");
 
     let debug = true;
     let now = Instant::now();
@@ -30,7 +30,8 @@ fn main() {
             }
 
             thread::sleep(Duration::from_millis(100));
-        });
+        })
+        .expect("Unable to submit the job");
     }
 
     println!("All jobs are sent...");
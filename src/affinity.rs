@@ -0,0 +1,58 @@
+/// Pin the current thread to the given CPU core, and return `true` if it's done. This is a no-op
+/// unless the `cpu-affinity` feature is enabled and the platform supports it, i.e. on Linux, or on
+/// macOS where the core is only a scheduling hint.
+pub(crate) fn pin_current(core: usize) -> bool {
+    imp::pin_current(core)
+}
+
+#[cfg(all(feature = "cpu-affinity", target_os = "linux"))]
+mod imp {
+    use std::mem;
+
+    pub(super) fn pin_current(core: usize) -> bool {
+        if core >= libc::CPU_SETSIZE as usize {
+            return false;
+        }
+
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            libc::CPU_ZERO(&mut set);
+            libc::CPU_SET(core, &mut set);
+
+            libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) == 0
+        }
+    }
+}
+
+#[cfg(all(feature = "cpu-affinity", target_os = "macos"))]
+mod imp {
+    use std::convert::TryFrom;
+
+    /// macOS can't pin a thread to a core: the affinity tag only hints the scheduler to keep the
+    /// threads of the same tag on the same L2 cache, and the threads of different tags apart. Each
+    /// core gets a tag of its own, offset by 1 since the tag 0 means no affinity.
+    pub(super) fn pin_current(core: usize) -> bool {
+        let tag = match core.checked_add(1).and_then(|tag| libc::integer_t::try_from(tag).ok()) {
+            Some(tag) => tag,
+            None => return false,
+        };
+
+        let mut policy = libc::thread_affinity_policy { affinity_tag: tag };
+
+        unsafe {
+            libc::thread_policy_set(
+                libc::pthread_mach_thread_np(libc::pthread_self()),
+                libc::THREAD_AFFINITY_POLICY as libc::thread_policy_flavor_t,
+                &mut policy as *mut libc::thread_affinity_policy as libc::thread_policy_t,
+                libc::THREAD_AFFINITY_POLICY_COUNT,
+            ) == libc::KERN_SUCCESS
+        }
+    }
+}
+
+#[cfg(not(all(feature = "cpu-affinity", any(target_os = "linux", target_os = "macos"))))]
+mod imp {
+    pub(super) fn pin_current(_core: usize) -> bool {
+        false
+    }
+}
@@ -0,0 +1,365 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::time::Duration;
+
+use crate::config::{Config, ConfigStatus};
+use crate::manager::StatusBehaviorSetter;
+use crate::model::WorkerUpdate;
+use crate::pool::ThreadPool;
+use crate::scaling::AutoScalePolicy;
+use crate::single;
+
+/// Enumeration of the reasons the `PoolBuilder` refuses to build the pool.
+#[derive(Debug)]
+pub enum BuilderError {
+    /// The pool has no worker to start with, and it's not allowed to auto scale either.
+    ZeroSize,
+
+    /// The pool is not allowed to grow to the size it starts with.
+    MaxBelowSize { size: usize, max_workers: usize },
+
+    /// The OS refuses to spawn any worker thread for the pool.
+    Spawn(io::Error),
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuilderError::ZeroSize => f.write_str("the pool has no worker and can't auto scale"),
+            BuilderError::MaxBelowSize { size, max_workers } => write!(
+                f,
+                "the pool of {} workers is capped at {} workers",
+                size, max_workers
+            ),
+            BuilderError::Spawn(err) => write!(f, "unable to spawn the workers: {}", err),
+        }
+    }
+}
+
+impl Error for BuilderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuilderError::Spawn(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<BuilderError> for io::Error {
+    fn from(err: BuilderError) -> Self {
+        match err {
+            BuilderError::Spawn(err) => err,
+            err => io::Error::new(ErrorKind::InvalidInput, err),
+        }
+    }
+}
+
+/// The chainable way to configure and create a `ThreadPool`, see `ThreadPool::builder`. Knobs not
+/// covered here can still be set through the `Config` and `ThreadPool::new_with_config`.
+pub struct PoolBuilder {
+    size: usize,
+    config: Config,
+    auto_scale: bool,
+}
+
+impl PoolBuilder {
+    pub(crate) fn new() -> Self {
+        PoolBuilder {
+            size: 1,
+            config: Config::new(),
+            auto_scale: false,
+        }
+    }
+
+    /// Set the number of workers the pool starts with, which is 1 by default.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Set the name of the pool, which is also the prefix of the worker thread names.
+    pub fn name(mut self, name: &str) -> Self {
+        self.config.set_pool_name(name.to_string());
+        self
+    }
+
+    /// Set the stack size of the worker threads, in bytes.
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.config.set_worker_stack_size(size);
+        self
+    }
+
+    /// Set the capacity of each job queue, such that `exec` rejects the jobs with the
+    /// `ExecutionError::QueueFull` instead of blocking the caller when the queue is full.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.config.set_queue_capacity(Some(capacity));
+        self
+    }
+
+    /// Set how often the pool shall be auto adjusted, if it's run as the shared pool.
+    pub fn refresh_period(mut self, period: Duration) -> Self {
+        self.config.set_refresh_period(Some(period));
+        self
+    }
+
+    /// Set the behavior to be invoked before each worker is started.
+    pub fn before_start(mut self, behavior: WorkerUpdate) -> Self {
+        self.config.set_before_start(behavior);
+        self
+    }
+
+    /// Set the behavior to be invoked after each worker is dropped.
+    pub fn after_drop(mut self, behavior: WorkerUpdate) -> Self {
+        self.config.set_after_drop(behavior);
+        self
+    }
+
+    /// Set the bounds of the pool size when it's scaled, see `Config::with_worker_bounds`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let policy = AutoScalePolicy {
+    ///     scale_down_idle_threshold: Duration::from_millis(0),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mut pool = ThreadPool::builder()
+    ///     .worker_bounds(2, 3)
+    ///     .queue_capacity(4)
+    ///     .auto_scale(policy)
+    ///     .build();
+    ///
+    /// assert_eq!(pool.get_size(), 2);
+    ///
+    /// // keep the workers busy, then the pool under pressure grows up to the max size only
+    /// let (hold_tx, hold_rx) = crossbeam_channel::unbounded::<()>();
+    /// for busy in 1..=2 {
+    ///     let hold_rx = hold_rx.clone();
+    ///     pool.exec(move || { let _ = hold_rx.recv(); }, false).unwrap();
+    ///     while pool.stats().active_workers < busy {
+    ///         thread::yield_now();
+    ///     }
+    /// }
+    ///
+    /// for _ in 0..10 {
+    ///     let _ = pool.exec(|| thread::sleep(Duration::from_millis(10)), false);
+    /// }
+    ///
+    /// assert_eq!(pool.get_size(), 3);
+    ///
+    /// drop(hold_tx);
+    /// pool.drain();
+    ///
+    /// // the idle pool wants to shrink, but the bounds of the config hold
+    /// pool.auto_adjust();
+    /// pool.auto_adjust();
+    /// assert_eq!(pool.get_size(), 2);
+    /// ```
+    pub fn worker_bounds(mut self, min_workers: usize, max_workers: usize) -> Self {
+        self.config = mem::take(&mut self.config).with_worker_bounds(min_workers, max_workers);
+        self
+    }
+
+    /// Let the pool scale itself per the `policy` when it's under pressure or idle, within the
+    /// `worker_bounds`.
+    pub fn auto_scale(mut self, policy: AutoScalePolicy) -> Self {
+        self.config.set_auto_scale_policy(policy);
+        self.auto_scale = true;
+        self
+    }
+
+    /// Build the pool, or return the error if the settings make no sense, or the OS refuses to
+    /// spawn any worker thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    ///
+    /// match ThreadPool::builder().size(0).try_build() {
+    ///     Err(BuilderError::ZeroSize) => {}
+    ///     _ => panic!("the pool can't start without any worker"),
+    /// }
+    ///
+    /// let policy = AutoScalePolicy::default();
+    /// match ThreadPool::builder().size(4).worker_bounds(1, 2).auto_scale(policy).try_build() {
+    ///     Err(BuilderError::MaxBelowSize { size: 4, max_workers: 2 }) => {}
+    ///     _ => panic!("the pool can't start beyond its max size"),
+    /// }
+    ///
+    /// // an auto scaled pool can start small, and grow on demand
+    /// let pool = ThreadPool::builder().size(0).auto_scale(policy).try_build().unwrap();
+    /// assert_eq!(pool.get_size(), 1);
+    /// ```
+    pub fn try_build(self) -> Result<ThreadPool, BuilderError> {
+        let size = self.validate()?;
+        let mut pool =
+            ThreadPool::try_new_with_config(size, self.config).map_err(BuilderError::Spawn)?;
+
+        pool.toggle_auto_scale(self.auto_scale);
+        Ok(pool)
+    }
+
+    /// Build the pool like `try_build`, but fall back to the lazily created pool if the OS refuses
+    /// to spawn the worker threads, like `ThreadPool::new_with_config` does. Panics if the settings
+    /// make no sense.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    ///
+    /// static STARTED: AtomicUsize = AtomicUsize::new(0);
+    /// static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut pool = ThreadPool::builder()
+    ///     .size(2)
+    ///     .name("builder")
+    ///     .stack_size(4 * 1024 * 1024)
+    ///     .before_start(|_| { STARTED.fetch_add(1, Ordering::SeqCst); })
+    ///     .after_drop(|_| { DROPPED.fetch_add(1, Ordering::SeqCst); })
+    ///     .build();
+    ///
+    /// assert_eq!(pool.get_size(), 2);
+    /// assert_eq!(STARTED.load(Ordering::SeqCst), 2);
+    ///
+    /// // the workers are named after the pool, and have the stack to spare
+    /// let (tx, rx) = mpsc::channel();
+    /// pool.exec(move || {
+    ///     let buf = [1u8; 2 * 1024 * 1024];
+    ///     let name = thread::current().name().map(String::from);
+    ///     tx.send((name, buf.iter().map(|b| *b as usize).sum::<usize>())).unwrap();
+    /// }, false).unwrap();
+    ///
+    /// let (name, sum) = rx.recv().unwrap();
+    /// assert!(name.unwrap().starts_with("builder-worker-"));
+    /// assert_eq!(sum, 2 * 1024 * 1024);
+    ///
+    /// pool.close();
+    /// assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+    ///
+    /// // keep the only worker busy, then the small queues push back
+    /// let pool = ThreadPool::builder().queue_capacity(1).build();
+    ///
+    /// let (hold_tx, hold_rx) = mpsc::channel::<()>();
+    /// pool.exec(move || { let _ = hold_rx.recv(); }, false).unwrap();
+    /// while pool.stats().active_workers == 0 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// pool.exec(|| {}, true).unwrap();
+    /// pool.exec(|| {}, false).unwrap();
+    /// assert!(matches!(pool.exec(|| {}, false), Err(ExecutionError::QueueFull(_))));
+    ///
+    /// drop(hold_tx);
+    /// ```
+    pub fn build(self) -> ThreadPool {
+        let size = self
+            .validate()
+            .unwrap_or_else(|err| panic!("Unable to build the pool: {}", err));
+
+        let mut pool = ThreadPool::new_with_config(size, self.config);
+        pool.toggle_auto_scale(self.auto_scale);
+        pool
+    }
+
+    /// Build the pool and install it as the shared pool, see `shared_mode::init_with_config`. The
+    /// shared pool will be auto adjusted every `refresh_period`, if it's set, and scale itself under
+    /// pressure if it's set to `auto_scale`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let policy = AutoScalePolicy {
+    ///     scale_down_idle_threshold: Duration::from_millis(0),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// ThreadPool::builder()
+    ///     .size(2)
+    ///     .name("shared")
+    ///     .worker_bounds(2, 3)
+    ///     .queue_capacity(4)
+    ///     .auto_scale(policy)
+    ///     .refresh_period(Duration::from_secs(1))
+    ///     .build_static()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(shared_mode::stats().unwrap().worker_count, 2);
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// shared_mode::run(move || tx.send(()).unwrap()).unwrap();
+    /// rx.recv().unwrap();
+    ///
+    /// // keep the workers busy, then the pool under pressure grows
+    /// let (hold_tx, hold_rx) = crossbeam_channel::unbounded::<()>();
+    /// for busy in 1..=2 {
+    ///     let hold_rx = hold_rx.clone();
+    ///     shared_mode::run(move || { let _ = hold_rx.recv(); }).unwrap();
+    ///     while shared_mode::stats().unwrap().active_workers < busy {
+    ///         thread::yield_now();
+    ///     }
+    /// }
+    ///
+    /// for _ in 0..10 {
+    ///     let _ = shared_mode::run(|| thread::sleep(Duration::from_millis(10)));
+    /// }
+    ///
+    /// assert_eq!(shared_mode::worker_count(), 3);
+    /// drop(hold_tx);
+    ///
+    /// // and the auto adjustment every refresh period shrinks the idle pool back
+    /// let deadline = Instant::now() + Duration::from_secs(10);
+    /// while shared_mode::worker_count() > 2 && Instant::now() < deadline {
+    ///     thread::sleep(Duration::from_millis(20));
+    /// }
+    ///
+    /// assert_eq!(shared_mode::worker_count(), 2);
+    /// shared_mode::close();
+    /// ```
+    pub fn build_static(self) -> io::Result<()> {
+        let size = self.validate()?;
+        single::init_pool(size, self.config, self.auto_scale)
+    }
+
+    /// Check the settings, and return the number of workers the pool shall start with.
+    fn validate(&self) -> Result<usize, BuilderError> {
+        if self.size == 0 && !self.auto_scale {
+            return Err(BuilderError::ZeroSize);
+        }
+
+        // the auto scaled pool starts with the least number of workers it may shrink to
+        let size = self.size.max(self.config.min_workers()).max(1);
+
+        if let Some(max_workers) = self.config.max_workers() {
+            if max_workers < size {
+                return Err(BuilderError::MaxBelowSize { size, max_workers });
+            }
+        }
+
+        Ok(size)
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::config::{Config, ConfigStatus};
+use crate::debug::{is_debug_mode, log, Level};
+use crate::manager::StatusBehaviorDefinitions;
+use crate::metrics::{PoolMetrics, PoolMetricsSnapshot};
+use crate::pool::ExecutionError;
+
+/// The burst pool, which keeps no persistent workers around. Every job submitted will be handed to a
+/// freshly spawned thread, and the thread goes away once the job is done. This is useful for rare,
+/// heavy and independent jobs where pooling adds no value, or where reusing a worker thread could
+/// leak states from one job into the next.
+///
+/// The pool still caps the number of threads running at the same time: once `max_concurrency` jobs
+/// are in flight, a new submission will block the caller until the oldest job is done, or return
+/// with the `ExecutionError::Timeout` error immediately if the pool is in the non-blocking mode.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::BurstPool;
+/// use std::collections::HashSet;
+/// use std::sync::{Arc, Mutex};
+/// use std::thread;
+///
+/// let ids = Arc::new(Mutex::new(Vec::new()));
+/// let mut pool = BurstPool::new(4);
+///
+/// for _ in 0..8 {
+///     let ids = Arc::clone(&ids);
+///     pool.exec(move || {
+///         ids.lock().unwrap().push(thread::current().id());
+///     }).unwrap();
+/// }
+///
+/// // wait for all jobs to finish
+/// pool.close();
+///
+/// // every job has been executed, and each on its own thread
+/// let ids = ids.lock().unwrap();
+/// assert_eq!(ids.len(), 8);
+/// assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 8);
+/// assert_eq!(pool.metrics().since_start_jobs_completed, 8);
+/// ```
+pub struct BurstPool {
+    /// The pool configurations, the pool name, thread stack size, worker behaviors and the blocking
+    /// mode will be honored when spawning the job threads.
+    config: Config,
+
+    /// The maximum number of threads that can be running jobs at the same time.
+    max_concurrency: usize,
+
+    /// The job counters and the job latency histogram, shared with the job threads.
+    metrics: Arc<PoolMetrics>,
+
+    /// The handles to the job threads that have been spawned but not yet joined, in the order of
+    /// their creation.
+    handles: VecDeque<(usize, JoinHandle<()>)>,
+
+    /// The id of the last spawned job thread.
+    last_id: usize,
+
+    /// If the pool is closed and hence rejecting any new jobs.
+    closed: bool,
+}
+
+impl BurstPool {
+    /// Create a `BurstPool` with default configurations, which will run at most `max_concurrency`
+    /// jobs at the same time.
+    pub fn new(max_concurrency: usize) -> BurstPool {
+        Self::new_with_config(max_concurrency, Config::default())
+    }
+
+    /// Create a `BurstPool` with supplied configurations, which will run at most `max_concurrency`
+    /// jobs at the same time.
+    pub fn new_with_config(max_concurrency: usize, config: Config) -> BurstPool {
+        let cap = if max_concurrency == 0 {
+            1
+        } else {
+            max_concurrency
+        };
+
+        BurstPool {
+            config,
+            max_concurrency: cap,
+            metrics: Arc::new(PoolMetrics::new()),
+            handles: VecDeque::with_capacity(cap),
+            last_id: 0,
+            closed: false,
+        }
+    }
+
+    /// Spawn a new thread to run the job. If the pool has already hit the concurrency cap, the
+    /// caller will be blocked until the oldest running job is done, unless the pool is configured
+    /// to be non-blocking, where the `ExecutionError::Timeout` error will be returned instead.
+    ///
+    /// If the OS refuses to spawn the thread, the job is dropped and the cause is returned in the
+    /// `ExecutionError::SpawnFailed` error. The start hooks are run on the job thread, so none of
+    /// the worker behaviors are invoked for the thread that has never started.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::error::Error;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// static HOOKS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// fn hook(_id: usize) {
+    ///     HOOKS.fetch_add(1, Ordering::SeqCst);
+    /// }
+    ///
+    /// // no thread can be given a stack that large
+    /// let mut config = Config::new();
+    /// config.set_worker_stack_size(usize::MAX / 4);
+    /// config.set_before_start(hook);
+    /// config.set_after_drop(hook);
+    ///
+    /// let mut pool = BurstPool::new_with_config(2, config);
+    /// let err = pool.exec(|| {}).unwrap_err();
+    ///
+    /// assert!(matches!(err, ExecutionError::SpawnFailed(_)));
+    /// assert!(err.source().is_some());
+    /// assert_eq!(pool.active_count(), 0);
+    /// assert_eq!(HOOKS.load(Ordering::SeqCst), 0);
+    /// assert_eq!(pool.metrics().since_start_jobs_completed, 0);
+    /// ```
+    pub fn exec<F: FnOnce() + Send + 'static>(&mut self, f: F) -> Result<(), ExecutionError> {
+        if self.closed {
+            return Err(ExecutionError::PoolClosed);
+        }
+
+        // release the threads that have done their jobs
+        self.reap();
+
+        if self.handles.len() >= self.max_concurrency {
+            if self.config.non_blocking() {
+                return Err(ExecutionError::Timeout);
+            }
+
+            // wait for the oldest job to finish to make room for the new one
+            if let Some((id, handle)) = self.handles.pop_front() {
+                self.join(id, handle);
+            }
+        }
+
+        let id = self.last_id + 1;
+        let mut builder = thread::Builder::new();
+
+        if let Some(name) = self.config.worker_name(id) {
+            builder = builder.name(name);
+        }
+
+        if self.config.thread_size() > 0 {
+            builder = builder.stack_size(self.config.thread_size());
+        }
+
+        let behavior = self.config.worker_behavior().clone();
+        let metrics = Arc::clone(&self.metrics);
+
+        let job = move || {
+            behavior.before_start(id);
+            behavior.after_start(id);
+
+            let start = Instant::now();
+            metrics.job_started();
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            metrics.job_finished();
+
+            // the panic is handed on to the join, where the panic hook is invoked
+            match result {
+                Ok(()) => metrics.record(start.elapsed()),
+                Err(err) => panic::resume_unwind(err),
+            }
+        };
+
+        match builder.spawn(job) {
+            Ok(handle) => {
+                self.last_id = id;
+                self.handles.push_back((id, handle));
+
+                Ok(())
+            }
+            Err(err) => {
+                if is_debug_mode() {
+                    log(
+                        Level::Warn,
+                        format_args!("Unable to spawn the job thread {}: {:?}", id, err),
+                    );
+                }
+
+                Err(ExecutionError::SpawnFailed(err))
+            }
+        }
+    }
+
+    /// Check the number of job threads that are spawned but not yet joined.
+    pub fn active_count(&mut self) -> usize {
+        self.reap();
+        self.handles.len()
+    }
+
+    /// Take a snapshot of the job counters and the job latency histogram of the pool, see
+    /// `ThreadPool::metrics` for details.
+    pub fn metrics(&self) -> PoolMetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Check the maximum number of jobs that can be running at the same time.
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    /// Close the pool, reject any new jobs, and block the caller until all running jobs are done.
+    pub fn close(&mut self) {
+        self.closed = true;
+
+        while let Some((id, handle)) = self.handles.pop_front() {
+            self.join(id, handle);
+        }
+    }
+
+    fn reap(&mut self) {
+        let mut pos = 0;
+        while pos < self.handles.len() {
+            if self.handles[pos].1.is_finished() {
+                if let Some((id, handle)) = self.handles.remove(pos) {
+                    self.join(id, handle);
+                }
+            } else {
+                pos += 1;
+            }
+        }
+    }
+
+    fn join(&self, id: usize, handle: JoinHandle<()>) {
+        let behavior = self.config.worker_behavior();
+
+        behavior.before_drop(id);
+
+        if let Err(err) = handle.join() {
+            if is_debug_mode() {
+                log(Level::Warn, format_args!("The job thread {} has panicked", id));
+            }
+
+            behavior.on_panic(id, err.as_ref());
+        }
+
+        behavior.after_drop(id);
+    }
+}
+
+impl Drop for BurstPool {
+    fn drop(&mut self) {
+        if is_debug_mode() {
+            log(
+                Level::Info,
+                format_args!("Shutting down the burst pool, waiting for all jobs to finish."),
+            );
+        }
+
+        self.close();
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
+
+const STATE_PENDING: u8 = 0;
+const STATE_RUNNING: u8 = 1;
+const STATE_FINISHED: u8 = 2;
+const STATE_SKIPPED: u8 = 3;
+
+/// The states shared by the job, its token and its handle.
+struct CancelState {
+    cancelled: AtomicBool,
+    state: AtomicU8,
+}
+
+/// The token passed to a cancellable job, which the job can check every now and then to quit early
+/// once it's cancelled.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<CancelState>,
+}
+
+impl CancellationToken {
+    /// Check if the job has been cancelled through its handle.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// The handle to cancel the job submitted through `ThreadPool::exec_cancellable`, and to check its
+/// outcome.
+#[derive(Clone)]
+pub struct CancelHandle {
+    inner: Arc<CancelState>,
+}
+
+impl CancelHandle {
+    /// Cancel the job: if a worker hasn't started it yet, it will be skipped; otherwise the running
+    /// job can find it out from its token.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Check if the job has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Check if the job is done, i.e. it has been run to the end, has panicked, or has been skipped.
+    pub fn is_finished(&self) -> bool {
+        self.inner.state.load(Ordering::Acquire) >= STATE_FINISHED
+    }
+
+    /// Check if the job has been skipped because it was cancelled before a worker started it, or it
+    /// has been dropped by the pool without running at all.
+    pub fn was_skipped(&self) -> bool {
+        self.inner.state.load(Ordering::Acquire) == STATE_SKIPPED
+    }
+}
+
+/// Mark the job as done when it's dropped: finished if it has been started, or skipped otherwise.
+struct CancelGuard(Arc<CancelState>);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        let state = if self.0.state.load(Ordering::Acquire) == STATE_RUNNING {
+            STATE_FINISHED
+        } else {
+            STATE_SKIPPED
+        };
+
+        self.0.state.store(state, Ordering::Release);
+    }
+}
+
+/// Wrap the job such that it will be skipped if it's cancelled before it's started.
+pub(crate) fn cancellable<F>(f: F) -> (impl FnOnce() + Send + 'static, CancelHandle)
+where
+    F: FnOnce(&CancellationToken) + Send + 'static,
+{
+    let inner = Arc::new(CancelState {
+        cancelled: AtomicBool::new(false),
+        state: AtomicU8::new(STATE_PENDING),
+    });
+
+    let guard = CancelGuard(Arc::clone(&inner));
+    let job = move || {
+        let guard = guard;
+        if guard.0.cancelled.load(Ordering::Acquire) {
+            return;
+        }
+
+        guard.0.state.store(STATE_RUNNING, Ordering::Release);
+        f(&CancellationToken {
+            inner: Arc::clone(&guard.0),
+        });
+    };
+
+    (job, CancelHandle { inner })
+}
@@ -1,35 +1,621 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::io;
+#[cfg(feature = "persist-metrics")]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
-use crate::manager::{StatusBehaviorSetter, StatusBehaviors};
-use crate::model::WorkerUpdate;
+use crate::debug::{is_debug_mode, log, Level};
+use crate::manager::{JobBehaviors, StatusBehaviorSetter, StatusBehaviors, WorkerEvent};
+use crate::model::{
+    hook_status, JobTimeout, RetryFailure, WorkerPanic, WorkerStarved, WorkerUpdate,
+};
+use crate::scaling::{AutoScalePolicy, AutoScaler, ScalingPolicy};
+use crate::state::WorkerStateHooks;
+use crossbeam_channel::Sender;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum TimeoutPolicy {
     DirectRun,
     Drop,
     LossyRetry,
 }
 
+/// The order in which the workers run the queued jobs. The `Fifo` ordering is the default, and
+/// runs the jobs in the order they're queued.
+///
+/// With the `Lifo` ordering, a worker takes a small batch of a job queue at once, and runs the most
+/// recently submitted job of the batch first, which keeps the freshest data in the CPU caches. The
+/// batches are still taken in the order they're queued, but the jobs run out of order, so this is
+/// only meant for the throughput-bound batch processing, where no job is latency sensitive.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum JobOrdering {
+    /// Run the oldest queued job first.
+    #[default]
+    Fifo,
+
+    /// Run the newest queued job first.
+    Lifo,
+}
+
+/// The way the workers split their attention between the priority and the normal job queues. Each
+/// worker either waits longer on the priority queue, waits longer on the normal queue, or floats
+/// between both, and its role is worked out from the strategy and the number of workers, which is
+/// redone whenever the pool is resized. The `pri_cap` is the max number of priority jobs a worker
+/// takes in a row while normal jobs are waiting, such that the normal jobs won't starve.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FetchStrategy {
+    /// The `pri_ratio` share of the workers, rounded down, favor the priority jobs, as many of the
+    /// rest favor the normal jobs, and the others float. This is the default, with a third of the
+    /// workers in each role and a `pri_cap` of 4.
+    Balanced { pri_ratio: f32, pri_cap: u8 },
+
+    /// All workers favor the priority jobs, and only pick up a normal job after `pri_cap` priority
+    /// jobs in a row, or when there's no priority job.
+    PriorityFirst { pri_cap: u8 },
+
+    /// All workers float, and take the priority and the normal jobs in turn while both are waiting.
+    RoundRobin,
+}
+
+impl Default for FetchStrategy {
+    fn default() -> Self {
+        FetchStrategy::Balanced {
+            pri_ratio: 1.0 / 3.0,
+            pri_cap: 4,
+        }
+    }
+}
+
+/// The way the workers take jobs from the pool. The pool starts with the `Deque` backend by default,
+/// and can be switched at runtime with `ThreadPool::switch_backend`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DispatchBackend {
+    /// The workers only take jobs from the shared job queues, one job at a time. This has the least
+    /// overhead and keeps the jobs strictly in the queued order, which suits low job rates.
+    Channel,
+
+    /// The workers also move a share of the backlog to their local work-stealing deques, and idle
+    /// workers steal jobs from their busy siblings, which keeps all workers busy at high job rates.
+    #[default]
+    Deque,
+}
+
+/// The parking profile decides how long an idle worker will keep polling the job queues before
+/// moving on to the next round of checks. Shorter rounds and timeouts mean lower job pickup latency
+/// at the price of higher CPU usage, and longer ones favor CPU-frugal batch workloads.
+///
+/// By default a worker moves on right after its spinning rounds, and only parks on the queue for up
+/// to the `timeout` or the `long_timeout` if they're given through `ParkingProfile::new`.
+///
+/// Once a worker has come back empty handed for `idle_rounds` rounds in a row, it stops polling and
+/// blocks on the job queues until a job arrives, or the `idle_timeout` has passed, such that an idle
+/// pool barely uses any CPU.
+#[derive(Copy, Clone, Debug)]
+pub struct ParkingProfile {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) long_timeout: Option<Duration>,
+    pub(crate) short_rounds: u8,
+    pub(crate) long_rounds: u8,
+    pub(crate) idle_rounds: u32,
+    pub(crate) idle_timeout: Duration,
+}
+
+impl ParkingProfile {
+    /// Create the parking profile. The `short_rounds` and `timeout` are used by workers that could
+    /// skip the current queue for pending jobs in the other queue, and the `long_rounds` and the
+    /// `long_timeout` are used by workers that are designated to wait on the current queue. Once
+    /// done with the rounds, the worker parks on the queue for up to the timeout before moving on.
+    pub fn new(
+        timeout: Duration,
+        long_timeout: Duration,
+        short_rounds: u8,
+        long_rounds: u8,
+    ) -> Self {
+        ParkingProfile {
+            timeout: Some(timeout),
+            long_timeout: Some(long_timeout),
+            short_rounds,
+            long_rounds,
+            ..Default::default()
+        }
+    }
+
+    /// Set how many empty rounds an idle worker shall go through before blocking on the job queues,
+    /// and how long it may block at a time. The worker wakes up as soon as a job arrives, but the
+    /// `timeout` bounds how late it will notice the pool closing or stealable jobs in its siblings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let mut profile = ParkingProfile::default();
+    /// profile.set_idle_park(64, Duration::from_millis(20));
+    ///
+    /// let mut config = Config::default();
+    /// config.set_parking_profile(profile);
+    ///
+    /// let pool = ThreadPool::new_with_config(8, config);
+    ///
+    /// // let the workers settle down, then make sure the idle pool is not spinning
+    /// thread::sleep(Duration::from_millis(100));
+    /// # #[cfg(target_os = "linux")]
+    /// # {
+    /// #     fn cpu_time() -> Duration {
+    /// #         let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    /// #         unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut ts) };
+    /// #         Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    /// #     }
+    /// let before = cpu_time();
+    /// thread::sleep(Duration::from_millis(500));
+    ///
+    /// // 8 spinning workers would burn through several seconds of CPU time
+    /// assert!(cpu_time() - before < Duration::from_millis(100));
+    /// # }
+    ///
+    /// drop(pool);
+    /// ```
+    pub fn set_idle_park(&mut self, rounds: u32, timeout: Duration) -> &mut Self {
+        self.idle_rounds = rounds;
+        self.idle_timeout = timeout;
+        self
+    }
+}
+
+impl Default for ParkingProfile {
+    fn default() -> Self {
+        ParkingProfile {
+            timeout: None,
+            long_timeout: None,
+            short_rounds: 2,
+            long_rounds: 8,
+            idle_rounds: 1024,
+            idle_timeout: Duration::from_millis(10),
+        }
+    }
+}
+
+/// The configurations for the housekeeping threads of the pools, e.g. the auto adjustment thread,
+/// such that they can be named, and kept off the latency sensitive cores.
+///
+/// The CPU affinity and the thread priority (i.e. the `nice` value) are only applied on Linux, and
+/// will be ignored on other platforms.
+#[derive(Clone, Debug, Default)]
+pub struct BgThreadConfig {
+    name: Option<String>,
+    cpu_affinity: Vec<usize>,
+    priority: Option<i32>,
+}
+
+impl BgThreadConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the background threads.
+    pub fn set_name(&mut self, name: String) -> &mut Self {
+        if name.is_empty() {
+            self.name = None;
+        } else {
+            self.name.replace(name);
+        }
+
+        self
+    }
+
+    /// Pin the background threads to the given CPU cores. An empty list will let the OS decide. The
+    /// cores beyond the `CPU_SETSIZE` limit of the platform are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::mpsc;
+    /// use std::time::Duration;
+    ///
+    /// // the out-of-range core is skipped, and the timer thread keeps working
+    /// let mut bg_config = BgThreadConfig::new();
+    /// bg_config.set_cpu_affinity(vec![usize::MAX, 0]);
+    ///
+    /// let mut config = Config::new();
+    /// config.set_background_thread_config(bg_config);
+    ///
+    /// let pool = ThreadPool::new_with_config(1, config);
+    /// let (tx, rx) = mpsc::channel();
+    ///
+    /// pool.exec_after(move || tx.send(()).unwrap(), Duration::from_millis(10)).unwrap();
+    /// assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    /// ```
+    pub fn set_cpu_affinity(&mut self, cores: Vec<usize>) -> &mut Self {
+        self.cpu_affinity = cores;
+        self
+    }
+
+    /// Set the `nice` value of the background threads, where a larger value means a lower priority.
+    pub fn set_priority(&mut self, nice: i32) -> &mut Self {
+        self.priority.replace(nice);
+        self
+    }
+
+    pub fn name(&self) -> Option<&String> {
+        self.name.as_ref()
+    }
+
+    pub fn cpu_affinity(&self) -> &[usize] {
+        &self.cpu_affinity
+    }
+
+    pub fn priority(&self) -> Option<i32> {
+        self.priority
+    }
+
+    /// Spawn a background thread with this config, and the `default_name` will be used if no name
+    /// is given in the config. An error will be returned if the OS refuses to spawn the thread.
+    pub(crate) fn spawn<F>(&self, default_name: &str, f: F) -> io::Result<JoinHandle<()>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let name = self
+            .name
+            .clone()
+            .unwrap_or_else(|| default_name.to_string());
+
+        let config = self.clone();
+
+        thread::Builder::new()
+            .name(name)
+            .spawn(move || {
+                config.apply();
+                f();
+            })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn apply(&self) {
+        unsafe {
+            if !self.cpu_affinity.is_empty() {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                let mut pinned = false;
+
+                // the cores out of the set's range can't be pinned to, skip them
+                for &core in self.cpu_affinity.iter() {
+                    if core < libc::CPU_SETSIZE as usize {
+                        libc::CPU_SET(core, &mut set);
+                        pinned = true;
+                    }
+                }
+
+                if pinned {
+                    pinned =
+                        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+                            == 0;
+                }
+
+                if !pinned && is_debug_mode() {
+                    log(
+                        Level::Warn,
+                        format_args!(
+                            "Unable to pin the background thread to cores: {:?}",
+                            self.cpu_affinity
+                        ),
+                    );
+                }
+            }
+
+            if let Some(nice) = self.priority {
+                let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+                if libc::setpriority(libc::PRIO_PROCESS, tid, nice) != 0 && is_debug_mode() {
+                    log(
+                        Level::Warn,
+                        format_args!("Unable to set the background thread priority to: {}", nice),
+                    );
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn apply(&self) {
+        if (!self.cpu_affinity.is_empty() || self.priority.is_some()) && is_debug_mode() {
+            log(
+                Level::Warn,
+                format_args!(
+                    "The background thread affinity and priority are not supported on this platform"
+                ),
+            );
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Config {
     non_blocking: bool,
+    queue_capacity: Option<usize>,
     pool_name: Option<String>,
+    thread_name_pattern: Option<String>,
+    thread_name_prefix: Option<String>,
+    affinity: Vec<usize>,
+    parking_profile: ParkingProfile,
+    dispatch_backend: DispatchBackend,
+    ordering: JobOrdering,
+    fetch_strategy: FetchStrategy,
+    bg_thread_config: BgThreadConfig,
+    #[cfg(feature = "persist-metrics")]
+    metrics_persistence: Option<(PathBuf, Duration)>,
+    idle_task_threshold: Duration,
+    max_idle: Option<Duration>,
+    privileged_workers: Option<usize>,
+    require_sync_start: bool,
     refresh_period: Option<Duration>,
+    scaling_policy: Option<Arc<dyn ScalingPolicy>>,
+    min_workers: usize,
+    max_workers: Option<usize>,
     worker_behaviors: StatusBehaviors,
+    job_behaviors: JobBehaviors,
+    event_sink: Option<Sender<WorkerEvent>>,
+    worker_state: WorkerStateHooks,
+    retry_failure: Option<RetryFailure>,
+    job_timeout: Option<Duration>,
+    on_job_timeout: Option<JobTimeout>,
     thread_size: usize,
     timeout_policy: TimeoutPolicy,
+    map_chunk_size: Option<usize>,
 }
 
 impl Config {
     pub fn new() -> Self {
         Config {
             non_blocking: false,
+            queue_capacity: None,
             pool_name: None,
+            thread_name_pattern: None,
+            thread_name_prefix: None,
+            affinity: Vec::new(),
+            parking_profile: ParkingProfile::default(),
+            dispatch_backend: DispatchBackend::default(),
+            ordering: JobOrdering::default(),
+            fetch_strategy: FetchStrategy::default(),
+            bg_thread_config: BgThreadConfig::default(),
+            #[cfg(feature = "persist-metrics")]
+            metrics_persistence: None,
+            idle_task_threshold: Duration::from_millis(100),
+            max_idle: None,
+            privileged_workers: None,
+            require_sync_start: false,
             refresh_period: None,
+            scaling_policy: None,
+            min_workers: 0,
+            max_workers: None,
             worker_behaviors: StatusBehaviors::default(),
+            job_behaviors: JobBehaviors::default(),
+            event_sink: None,
+            worker_state: WorkerStateHooks::default(),
+            retry_failure: None,
+            job_timeout: None,
+            on_job_timeout: None,
             thread_size: 0,
             timeout_policy: TimeoutPolicy::Drop,
+            map_chunk_size: None,
+        }
+    }
+}
+
+/// Print the effective configurations, e.g. to log them when the pool fails to start. The hooks and
+/// the policies are only shown as `set` or `none`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::time::Duration;
+///
+/// let mut config = Config::new();
+/// config
+///     .set_pool_name(String::from("io"))
+///     .set_refresh_period(Some(Duration::from_secs(5)));
+/// config.set_after_drop(|_| {});
+///
+/// let printed = format!("{:?}", config);
+/// assert!(printed.contains("pool_name: Some(\"io\")"));
+/// assert!(printed.contains("refresh_period: Some(5s)"));
+/// assert!(printed.contains("before_start: none"));
+/// assert!(printed.contains("after_drop: set"));
+/// ```
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut config = f.debug_struct("Config");
+        config
+            .field("pool_name", &self.pool_name)
+            .field("thread_name_pattern", &self.thread_name_pattern)
+            .field("thread_name_prefix", &self.thread_name_prefix)
+            .field("thread_size", &self.thread_size)
+            .field("non_blocking", &self.non_blocking)
+            .field("queue_capacity", &self.queue_capacity)
+            .field("timeout_policy", &self.timeout_policy)
+            .field("map_chunk_size", &self.map_chunk_size)
+            .field("refresh_period", &self.refresh_period)
+            .field("scaling_policy", &hook_status(&self.scaling_policy))
+            .field("min_workers", &self.min_workers)
+            .field("max_workers", &self.max_workers)
+            .field("max_idle", &self.max_idle)
+            .field("privileged_workers", &self.privileged_workers)
+            .field("affinity", &self.affinity)
+            .field("parking_profile", &self.parking_profile)
+            .field("dispatch_backend", &self.dispatch_backend)
+            .field("ordering", &self.ordering)
+            .field("fetch_strategy", &self.fetch_strategy)
+            .field("bg_thread_config", &self.bg_thread_config)
+            .field("idle_task_threshold", &self.idle_task_threshold)
+            .field("require_sync_start", &self.require_sync_start)
+            .field("job_timeout", &self.job_timeout)
+            .field("on_job_timeout", &hook_status(&self.on_job_timeout))
+            .field("retry_failure", &hook_status(&self.retry_failure))
+            .field("worker_behaviors", &self.worker_behaviors)
+            .field("job_behaviors", &self.job_behaviors)
+            .field("event_sink", &hook_status(&self.event_sink))
+            .field("worker_state", &self.worker_state);
+
+        #[cfg(feature = "persist-metrics")]
+        config.field("metrics_persistence", &self.metrics_persistence);
+
+        config.finish()
+    }
+}
+
+impl Config {
+    /// The thread name of the worker with the given id: per the thread name pattern if it's set, or
+    /// `{prefix}-worker-{id}` if the pool has a thread name prefix or a name. Otherwise the worker
+    /// thread will be unnamed.
+    pub(crate) fn worker_name(&self, id: usize) -> Option<String> {
+        let pool = self.pool_name.as_ref();
+
+        match (self.thread_name_pattern.as_ref(), self.thread_name_prefix.as_ref().or(pool)) {
+            (Some(pattern), _) => Some(
+                pattern
+                    .replace("{pool}", pool.map(String::as_str).unwrap_or(""))
+                    .replace("{id}", &id.to_string()),
+            ),
+            (None, Some(prefix)) => Some(format!("{}-worker-{}", prefix, id)),
+            (None, None) => None,
+        }
+    }
+
+    /// The CPU core to pin the nth spawned worker to, if the affinity is set.
+    pub(crate) fn worker_core(&self, nth: usize) -> Option<usize> {
+        if self.affinity.is_empty() {
+            return None;
+        }
+
+        Some(self.affinity[nth % self.affinity.len()])
+    }
+
+    /// The hooks to create and tear down the state of each worker
+    pub(crate) fn worker_state_hooks(&self) -> &WorkerStateHooks {
+        &self.worker_state
+    }
+
+    /// The callback to take the error of a job that has failed all its attempts in `exec_retry`
+    pub(crate) fn retry_failure(&self) -> Option<&RetryFailure> {
+        self.retry_failure.as_ref()
+    }
+
+    /// The callback to report the workers running a job for longer than the job timeout
+    pub(crate) fn on_job_timeout(&self) -> Option<&JobTimeout> {
+        self.on_job_timeout.as_ref()
+    }
+}
+
+impl Config {
+    /// Set the name of the pool, and pass the config on for more settings, see `set_pool_name`.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.set_pool_name(name.to_string());
+        self
+    }
+
+    /// Set the stack size of the worker threads, see `set_worker_stack_size`.
+    pub fn with_thread_size(mut self, size: usize) -> Self {
+        self.set_worker_stack_size(size);
+        self
+    }
+
+    /// Set the capacity of each job queue, see `set_queue_capacity`.
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.set_queue_capacity(Some(capacity));
+        self
+    }
+
+    /// Set how often the shared pool shall be auto adjusted, see `set_refresh_period`.
+    pub fn with_refresh_period(mut self, period: Duration) -> Self {
+        self.set_refresh_period(Some(period));
+        self
+    }
+
+    /// Set how long the workers can be idle before they quit, see `set_max_idle`.
+    pub fn with_max_idle(mut self, max_idle: Duration) -> Self {
+        self.set_max_idle(Some(max_idle));
+        self
+    }
+
+    /// Set how many workers the pool shall keep even if they're idle, see
+    /// `set_privileged_worker_count`.
+    pub fn with_privileged_worker_count(mut self, count: usize) -> Self {
+        self.set_privileged_worker_count(count);
+        self
+    }
+
+    /// Set the bounds of the pool size on auto adjustments, see `set_min_workers` and
+    /// `set_max_workers`.
+    pub fn with_worker_bounds(mut self, min_workers: usize, max_workers: usize) -> Self {
+        self.set_min_workers(min_workers).set_max_workers(Some(max_workers));
+        self
+    }
+
+    /// Set how long a job can run before it's reported as overdue, see `set_job_timeout`.
+    pub fn with_job_timeout(mut self, timeout: Duration) -> Self {
+        self.set_job_timeout(timeout);
+        self
+    }
+
+    /// Finish the chained settings, or return the error if they contradict each other. The config
+    /// is taken as is by the pools otherwise, where the conflicts are settled quietly, e.g. the
+    /// `min_workers` wins over a smaller `max_workers`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::time::Duration;
+    ///
+    /// let config = Config::new()
+    ///     .with_name("web-pool")
+    ///     .with_refresh_period(Duration::from_secs(30))
+    ///     .with_max_idle(Duration::from_secs(60))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(config.pool_name().map(String::as_str), Some("web-pool"));
+    /// assert_eq!(config.refresh_period(), Some(Duration::from_secs(30)));
+    /// assert_eq!(config.max_idle(), Some(Duration::from_secs(60)));
+    ///
+    /// match Config::new().with_worker_bounds(4, 2).build() {
+    ///     Err(ConfigError::MaxBelowMin { min_workers: 4, max_workers: 2 }) => {}
+    ///     _ => panic!("the pool can't shrink to 4 workers, but grow to 2 at most"),
+    /// }
+    ///
+    /// match Config::new().with_queue_capacity(0).build() {
+    ///     Err(ConfigError::ZeroQueueCapacity) => {}
+    ///     _ => panic!("the queues shall take at least 1 job"),
+    /// }
+    /// ```
+    pub fn build(self) -> Result<Config, ConfigError> {
+        if let Some(max_workers) = self.max_workers {
+            if max_workers < self.min_workers {
+                return Err(ConfigError::MaxBelowMin {
+                    min_workers: self.min_workers,
+                    max_workers,
+                });
+            }
         }
+
+        if self.queue_capacity == Some(0) {
+            return Err(ConfigError::ZeroQueueCapacity);
+        }
+
+        Ok(self)
     }
 }
 
@@ -39,19 +625,108 @@ impl Default for Config {
     }
 }
 
+/// Enumeration of the settings that `Config::build` finds contradicting each other.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The pool is allowed to grow to fewer workers than it shall keep on auto adjustments.
+    MaxBelowMin { min_workers: usize, max_workers: usize },
+
+    /// The job queues can't take any job.
+    ZeroQueueCapacity,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::MaxBelowMin {
+                min_workers,
+                max_workers,
+            } => write!(
+                f,
+                "the pool keeps {} workers but is capped at {} workers",
+                min_workers, max_workers
+            ),
+            ConfigError::ZeroQueueCapacity => f.write_str("the job queues have no capacity"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
 pub trait ConfigStatus {
     fn pool_name(&self) -> Option<&String>;
+    fn thread_name_pattern(&self) -> Option<&String>;
+    fn thread_name_prefix(&self) -> Option<&String>;
+    fn affinity(&self) -> &[usize];
+    fn parking_profile(&self) -> ParkingProfile;
+    fn dispatch_backend(&self) -> DispatchBackend;
+    fn ordering(&self) -> JobOrdering;
+    fn fetch_strategy(&self) -> FetchStrategy;
+    fn background_thread_config(&self) -> &BgThreadConfig;
+    #[cfg(feature = "persist-metrics")]
+    fn metrics_persistence(&self) -> Option<&(PathBuf, Duration)>;
+    fn idle_task_threshold(&self) -> Duration;
+    fn max_idle(&self) -> Option<Duration>;
+    fn privileged_worker_count(&self) -> Option<usize>;
+    fn job_timeout(&self) -> Option<Duration>;
+    fn require_sync_start(&self) -> bool;
     fn refresh_period(&self) -> Option<Duration>;
+    fn scaling_policy(&self) -> Option<&Arc<dyn ScalingPolicy>>;
+    fn min_workers(&self) -> usize;
+    fn max_workers(&self) -> Option<usize>;
     fn worker_behavior(&self) -> &StatusBehaviors;
+    fn job_behavior(&self) -> &JobBehaviors;
+    fn event_sink(&self) -> Option<&Sender<WorkerEvent>>;
     fn non_blocking(&self) -> bool;
+    fn queue_capacity(&self) -> Option<usize>;
     fn thread_size(&self) -> usize;
     fn timeout_policy(&self) -> TimeoutPolicy;
+    fn map_chunk_size(&self) -> Option<usize>;
     fn set_pool_name(&mut self, name: String) -> &mut Self;
+    fn set_thread_name_pattern(&mut self, pattern: &str) -> &mut Self;
+    fn set_thread_name_prefix(&mut self, prefix: &str) -> &mut Self;
+    fn set_affinity(&mut self, cores: Vec<usize>) -> &mut Self;
+    fn set_parking_profile(&mut self, profile: ParkingProfile) -> &mut Self;
+    fn set_dispatch_backend(&mut self, backend: DispatchBackend) -> &mut Self;
+    fn set_ordering(&mut self, ordering: JobOrdering) -> &mut Self;
+    fn set_fetch_strategy(&mut self, strategy: FetchStrategy) -> &mut Self;
+    fn set_background_thread_config(&mut self, config: BgThreadConfig) -> &mut Self;
+    #[cfg(feature = "persist-metrics")]
+    fn set_metrics_persistence(&mut self, path: PathBuf, flush_interval: Duration) -> &mut Self;
+    fn set_idle_task_threshold(&mut self, threshold: Duration) -> &mut Self;
+    fn set_max_idle(&mut self, max_idle: Option<Duration>) -> &mut Self;
+    fn set_privileged_worker_count(&mut self, count: usize) -> &mut Self;
+    fn set_job_timeout(&mut self, timeout: Duration) -> &mut Self;
+    fn set_on_job_timeout<F>(&mut self, on_timeout: F) -> &mut Self
+    where
+        F: Fn(usize, Duration) + Send + Sync + 'static;
+    fn set_require_sync_start(&mut self, sync_start: bool) -> &mut Self;
     fn set_refresh_period(&mut self, period: Option<Duration>) -> &mut Self;
+    fn set_scaling_policy(&mut self, policy: Arc<dyn ScalingPolicy>) -> &mut Self;
+    fn set_auto_scale_policy(&mut self, policy: AutoScalePolicy) -> &mut Self;
+    fn set_min_workers(&mut self, min_workers: usize) -> &mut Self;
+    fn set_max_workers(&mut self, max_workers: Option<usize>) -> &mut Self;
     fn set_worker_behavior(&mut self, behavior: StatusBehaviors) -> &mut Self;
+    fn set_job_behavior(&mut self, behavior: JobBehaviors) -> &mut Self;
+    fn set_event_sink(&mut self, sink: Sender<WorkerEvent>) -> &mut Self;
+    fn set_worker_init<T, F>(&mut self, init: F) -> &mut Self
+    where
+        T: Send + 'static,
+        F: Fn(usize) -> T + Send + Sync + 'static;
+    fn set_worker_teardown<T, F>(&mut self, teardown: F) -> &mut Self
+    where
+        T: 'static,
+        F: Fn(usize, T) + Send + Sync + 'static;
+    fn set_retry_failure<E, F>(&mut self, on_failure: F) -> &mut Self
+    where
+        E: Send + 'static,
+        F: Fn(E) + Send + Sync + 'static;
     fn set_none_blocking(&mut self, non_blocking: bool) -> &mut Self;
+    fn set_queue_capacity(&mut self, capacity: Option<usize>) -> &mut Self;
     fn set_thread_size(&mut self, size: usize) -> &mut Self;
+    fn set_worker_stack_size(&mut self, size: usize) -> &mut Self;
     fn set_timeout_policy(&mut self, policy: TimeoutPolicy) -> &mut Self;
+    fn set_map_chunk_size(&mut self, size: Option<usize>) -> &mut Self;
 }
 
 impl ConfigStatus for Config {
@@ -60,21 +735,122 @@ impl ConfigStatus for Config {
         self.pool_name.as_ref()
     }
 
+    /// Check the thread name pattern of the workers, if it's set
+    fn thread_name_pattern(&self) -> Option<&String> {
+        self.thread_name_pattern.as_ref()
+    }
+
+    /// Check the thread name prefix of the workers, if it's set
+    fn thread_name_prefix(&self) -> Option<&String> {
+        self.thread_name_prefix.as_ref()
+    }
+
+    /// Check the CPU cores the workers are pinned to, if any
+    fn affinity(&self) -> &[usize] {
+        &self.affinity
+    }
+
+    /// Check the parking profile of the idle workers
+    fn parking_profile(&self) -> ParkingProfile {
+        self.parking_profile
+    }
+
+    /// Check the backend the pool starts with
+    fn dispatch_backend(&self) -> DispatchBackend {
+        self.dispatch_backend
+    }
+
+    /// Check the order in which the workers run the queued jobs
+    fn ordering(&self) -> JobOrdering {
+        self.ordering
+    }
+
+    /// Check how the workers split their attention between the priority and the normal jobs
+    fn fetch_strategy(&self) -> FetchStrategy {
+        self.fetch_strategy
+    }
+
+    /// Check the configurations of the housekeeping threads
+    fn background_thread_config(&self) -> &BgThreadConfig {
+        &self.bg_thread_config
+    }
+
+    /// Check where and how often the pool metrics shall be persisted
+    #[cfg(feature = "persist-metrics")]
+    fn metrics_persistence(&self) -> Option<&(PathBuf, Duration)> {
+        self.metrics_persistence.as_ref()
+    }
+
+    /// Check how long a worker shall be idle before it can run the pool's idle task
+    fn idle_task_threshold(&self) -> Duration {
+        self.idle_task_threshold
+    }
+
+    /// Check how long an extended worker can be idle before it quits, if it's set
+    fn max_idle(&self) -> Option<Duration> {
+        self.max_idle
+    }
+
+    /// Check how many workers the pool shall keep even if they're idle, if it's set
+    fn privileged_worker_count(&self) -> Option<usize> {
+        self.privileged_workers
+    }
+
+    /// Check how long a job can run before it's reported as overdue, if it's set
+    fn job_timeout(&self) -> Option<Duration> {
+        self.job_timeout
+    }
+
+    /// Check if the pool shall wait for all workers to start before taking jobs
+    fn require_sync_start(&self) -> bool {
+        self.require_sync_start
+    }
+
     /// Check the auto balancing period for the `index_mode`
     fn refresh_period(&self) -> Option<Duration> {
         self.refresh_period
     }
 
+    /// Check the custom policy to decide the pool size on auto adjustments, if it's set
+    fn scaling_policy(&self) -> Option<&Arc<dyn ScalingPolicy>> {
+        self.scaling_policy.as_ref()
+    }
+
+    /// Check the least number of workers the pool shall keep on auto adjustments
+    fn min_workers(&self) -> usize {
+        self.min_workers
+    }
+
+    /// Check the most number of workers the pool can grow to on auto adjustments, if it's set
+    fn max_workers(&self) -> Option<usize> {
+        self.max_workers
+    }
+
     /// Obtain a copy of the status behavior object
     fn worker_behavior(&self) -> &StatusBehaviors {
         &self.worker_behaviors
     }
 
+    /// Obtain the hooks to trace the jobs
+    fn job_behavior(&self) -> &JobBehaviors {
+        &self.job_behaviors
+    }
+
+    /// Obtain the channel the workers report their lifecycle events to
+    fn event_sink(&self) -> Option<&Sender<WorkerEvent>> {
+        self.event_sink.as_ref()
+    }
+
     /// Check if the config has turned on the `non_blocking` mode
     fn non_blocking(&self) -> bool {
         self.non_blocking
     }
 
+    /// Check the bounded capacity of the job queues, if it's set
+    fn queue_capacity(&self) -> Option<usize> {
+        self.queue_capacity
+    }
+
     /// Check the desired stack size for each thread in the pool
     fn thread_size(&self) -> usize {
         self.thread_size
@@ -85,6 +861,11 @@ impl ConfigStatus for Config {
         self.timeout_policy
     }
 
+    /// Check how many items a `map` job takes at a time, if it's set
+    fn map_chunk_size(&self) -> Option<usize> {
+        self.map_chunk_size
+    }
+
     fn set_pool_name(&mut self, name: String) -> &mut Self {
         if name.is_empty() {
             self.pool_name = None;
@@ -95,16 +876,746 @@ impl ConfigStatus for Config {
         self
     }
 
+    /// Set the name pattern of the worker threads, where `{pool}` will be replaced by the pool name
+    /// and `{id}` by the worker id, e.g. `"{pool}-worker-{id}"`. Without a pattern, the workers will be
+    /// named after the thread name prefix instead. Set an empty pattern to remove it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::collections::HashSet;
+    /// use std::sync::{mpsc, Arc, Barrier};
+    /// use std::thread;
+    ///
+    /// // collect the thread names of all workers, each job holds on to its worker until all
+    /// // workers have taken one
+    /// fn worker_names(pool: &ThreadPool) -> HashSet<String> {
+    ///     let size = pool.get_size();
+    ///     let barrier = Arc::new(Barrier::new(size));
+    ///     let (tx, rx) = mpsc::channel();
+    ///
+    ///     for _ in 0..size {
+    ///         let (barrier, tx) = (Arc::clone(&barrier), tx.clone());
+    ///         pool.exec(move || {
+    ///             tx.send(thread::current().name().unwrap().to_owned()).unwrap();
+    ///             barrier.wait();
+    ///         }, false).unwrap();
+    ///     }
+    ///
+    ///     rx.iter().take(size).collect()
+    /// }
+    ///
+    /// fn expected_names(pool: &ThreadPool) -> HashSet<String> {
+    ///     let mut names = HashSet::new();
+    ///     let mut id = pool.get_first_worker_id();
+    ///
+    ///     while let Some(curr) = id {
+    ///         names.insert(format!("io-worker-{}", curr));
+    ///         id = pool.get_next_worker_id(curr);
+    ///     }
+    ///
+    ///     names
+    /// }
+    ///
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_pool_name(String::from("io"))
+    ///     .set_thread_name_pattern("{pool}-worker-{id}");
+    ///
+    /// let mut pool = ThreadPool::new_with_config(2, config);
+    /// assert_eq!(worker_names(&pool), expected_names(&pool));
+    ///
+    /// // the workers added later follow the same pattern with their own ids
+    /// pool.resize(4);
+    /// assert_eq!(pool.get_size(), 4);
+    /// assert_eq!(worker_names(&pool), expected_names(&pool));
+    /// ```
+    fn set_thread_name_pattern(&mut self, pattern: &str) -> &mut Self {
+        if pattern.is_empty() {
+            self.thread_name_pattern = None;
+        } else {
+            self.thread_name_pattern.replace(pattern.to_owned());
+        }
+
+        self
+    }
+
+    /// Set the prefix of the worker thread names, such that the workers will be named like
+    /// `{prefix}-worker-{id}`. Without a prefix, the pool name will be used as the prefix, and the
+    /// workers of an unnamed pool will be unnamed. Set an empty prefix to remove it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::thread;
+    ///
+    /// let name_of = |config: Config| {
+    ///     let pool = ThreadPool::new_with_config(1, config);
+    ///     let id = pool.get_first_worker_id().unwrap();
+    ///     let name = pool.sync_block(|| thread::current().name().map(String::from)).unwrap();
+    ///
+    ///     (id, name)
+    /// };
+    ///
+    /// let mut config = Config::new();
+    /// config.set_pool_name(String::from("mypool"));
+    ///
+    /// let (id, name) = name_of(config.clone());
+    /// assert_eq!(name, Some(format!("mypool-worker-{}", id)));
+    ///
+    /// config.set_thread_name_prefix("db");
+    ///
+    /// let (id, name) = name_of(config);
+    /// assert_eq!(name, Some(format!("db-worker-{}", id)));
+    /// ```
+    fn set_thread_name_prefix(&mut self, prefix: &str) -> &mut Self {
+        if prefix.is_empty() {
+            self.thread_name_prefix = None;
+        } else {
+            self.thread_name_prefix.replace(prefix.to_owned());
+        }
+
+        self
+    }
+
+    /// Pin the workers to the given CPU cores, such that the nth spawned worker will be pinned to
+    /// `cores[n % cores.len()]`. This only takes effect with the `cpu-affinity` feature on Linux
+    /// and macOS, and is a no-op otherwise. On macOS the threads can't be pinned, so the workers of
+    /// the same core are only hinted to share the L2 cache. Set an empty list to let the OS
+    /// schedule the workers freely.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::mpsc;
+    ///
+    /// let mut config = Config::new();
+    /// config.set_affinity(vec![0]);
+    ///
+    /// let pool = ThreadPool::new_with_config(2, config);
+    /// let (tx, rx) = mpsc::channel::<Option<i32>>();
+    ///
+    /// for _ in 0..8 {
+    ///     let tx = tx.clone();
+    ///     pool.exec(move || {
+    ///         #[cfg(all(feature = "cpu-affinity", target_os = "linux"))]
+    ///         tx.send(Some(unsafe { libc::sched_getcpu() })).unwrap();
+    ///
+    ///         #[cfg(not(all(feature = "cpu-affinity", target_os = "linux")))]
+    ///         tx.send(None).unwrap();
+    ///     }, false).unwrap();
+    /// }
+    ///
+    /// // all jobs shall land on core 0, if the workers can be pinned on this platform
+    /// for cpu in rx.iter().take(8) {
+    ///     assert!(cpu.map_or(true, |cpu| cpu == 0));
+    /// }
+    /// ```
+    fn set_affinity(&mut self, cores: Vec<usize>) -> &mut Self {
+        self.affinity = cores;
+        self
+    }
+
+    /// Set how long idle workers shall poll the job queues before moving on. Latency sensitive pools
+    /// may use very short rounds, while CPU-frugal batch pools may prefer longer ones.
+    fn set_parking_profile(&mut self, profile: ParkingProfile) -> &mut Self {
+        self.parking_profile = profile;
+        self
+    }
+
+    /// Set the backend the pool starts with, which can be switched later at runtime.
+    fn set_dispatch_backend(&mut self, backend: DispatchBackend) -> &mut Self {
+        self.dispatch_backend = backend;
+        self
+    }
+
+    /// Set the order in which the workers run the queued jobs, see `JobOrdering` for the caveats.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::{mpsc, Arc, Mutex};
+    ///
+    /// let mut config = Config::new();
+    /// config.set_ordering(JobOrdering::Lifo);
+    ///
+    /// let pool = ThreadPool::new_with_config(1, config);
+    /// let done = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// // keep the only worker busy while the jobs are queued
+    /// let (hold_tx, hold_rx) = mpsc::channel::<()>();
+    /// let (started_tx, started_rx) = mpsc::channel();
+    /// pool.exec(move || {
+    ///     started_tx.send(()).unwrap();
+    ///     let _ = hold_rx.recv();
+    /// }, false).unwrap();
+    /// started_rx.recv().unwrap();
+    ///
+    /// for i in 1..=6 {
+    ///     let done = Arc::clone(&done);
+    ///     pool.exec(move || done.lock().unwrap().push(i), false).unwrap();
+    /// }
+    ///
+    /// // the jobs are taken in the batches of 3, and the newest job of each batch runs first
+    /// drop(hold_tx);
+    /// assert!(pool.wait_idle_timeout(std::time::Duration::from_secs(5)));
+    /// assert_eq!(*done.lock().unwrap(), vec![3, 2, 1, 6, 5, 4]);
+    /// ```
+    fn set_ordering(&mut self, ordering: JobOrdering) -> &mut Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// Set how the workers split their attention between the priority and the normal job queues,
+    /// see `FetchStrategy` for the options. A zero `pri_cap` is taken as 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::{mpsc, Arc, Mutex};
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let mut config = Config::new();
+    /// config.set_fetch_strategy(FetchStrategy::PriorityFirst { pri_cap: 4 });
+    ///
+    /// // the priority jobs go ahead of the normal ones queued before them on a single worker
+    /// let pool = ThreadPool::new_with_config(1, config.clone());
+    /// let done = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// let (hold_tx, hold_rx) = mpsc::channel::<()>();
+    /// pool.exec_with_priority(move || { let _ = hold_rx.recv(); }, Priority::Normal).unwrap();
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while pool.stats().active_workers == 0 && Instant::now() < deadline {
+    ///     thread::yield_now();
+    /// }
+    /// assert_eq!(pool.stats().active_workers, 1);
+    ///
+    /// for (name, priority) in vec![
+    ///     ("normal-1", Priority::Normal),
+    ///     ("normal-2", Priority::Normal),
+    ///     ("high-1", Priority::High),
+    ///     ("high-2", Priority::High),
+    /// ] {
+    ///     let done = Arc::clone(&done);
+    ///     pool.exec_with_priority(move || done.lock().unwrap().push(name), priority).unwrap();
+    /// }
+    ///
+    /// drop(hold_tx);
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while done.lock().unwrap().len() < 4 && Instant::now() < deadline {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// assert_eq!(*done.lock().unwrap(), vec!["high-1", "high-2", "normal-1", "normal-2"]);
+    ///
+    /// // and the normal jobs still get their turn under a constant stream of priority jobs
+    /// let pool = Arc::new(ThreadPool::new_with_config(2, config));
+    /// let normal_done = Arc::new(AtomicBool::new(false));
+    /// let flood = {
+    ///     let (pool, normal_done) = (Arc::clone(&pool), Arc::clone(&normal_done));
+    ///     thread::spawn(move || {
+    ///         // bounded, such that a starved normal job fails the assert below instead of hanging
+    ///         let deadline = Instant::now() + Duration::from_secs(10);
+    ///         while !normal_done.load(Ordering::SeqCst) && Instant::now() < deadline {
+    ///             pool.exec_with_priority(|| thread::yield_now(), Priority::High).unwrap();
+    ///         }
+    ///     })
+    /// };
+    ///
+    /// let flag = Arc::clone(&normal_done);
+    /// pool.exec_with_priority(move || flag.store(true, Ordering::SeqCst), Priority::Normal)
+    ///     .unwrap();
+    ///
+    /// flood.join().unwrap();
+    /// assert!(normal_done.load(Ordering::SeqCst));
+    /// ```
+    fn set_fetch_strategy(&mut self, strategy: FetchStrategy) -> &mut Self {
+        self.fetch_strategy = match strategy {
+            FetchStrategy::Balanced { pri_ratio, pri_cap } => FetchStrategy::Balanced {
+                pri_ratio,
+                pri_cap: pri_cap.max(1),
+            },
+            FetchStrategy::PriorityFirst { pri_cap } => FetchStrategy::PriorityFirst {
+                pri_cap: pri_cap.max(1),
+            },
+            FetchStrategy::RoundRobin => FetchStrategy::RoundRobin,
+        };
+        self
+    }
+
+    /// Set the name, CPU affinity and priority of the housekeeping threads, e.g. the auto adjustment
+    /// thread, such that they can be kept off the hot cores.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::fs;
+    /// use std::time::Duration;
+    ///
+    /// let mut bg_config = BgThreadConfig::new();
+    /// bg_config.set_name(String::from("bg-adjust")).set_cpu_affinity(vec![0]);
+    ///
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_refresh_period(Some(Duration::from_secs(10)))
+    ///     .set_background_thread_config(bg_config);
+    ///
+    /// shared_mode::init_with_config(2, config).expect("Unable to spawn the pool workers");
+    ///
+    /// if cfg!(target_os = "linux") {
+    ///     // give the auto adjustment thread a moment to apply the config
+    ///     std::thread::sleep(Duration::from_millis(50));
+    ///
+    ///     let status = fs::read_dir("/proc/self/task")
+    ///         .unwrap()
+    ///         .map(|task| task.unwrap().path())
+    ///         .find(|path| {
+    ///             fs::read_to_string(path.join("comm")).unwrap().trim() == "bg-adjust"
+    ///         })
+    ///         .map(|path| fs::read_to_string(path.join("status")).unwrap())
+    ///         .expect("the auto adjustment thread shall be named per config");
+    ///
+    ///     assert!(status
+    ///         .lines()
+    ///         .any(|line| line.starts_with("Cpus_allowed_list:") && line.ends_with("\t0")));
+    /// }
+    ///
+    /// shared_mode::close();
+    /// ```
+    fn set_background_thread_config(&mut self, config: BgThreadConfig) -> &mut Self {
+        self.bg_thread_config = config;
+        self
+    }
+
+    /// Persist the pool metrics to the file at `path` every `flush_interval`, and when the pool is
+    /// closed. A new pool created with the same path will load and carry on the lifetime totals from
+    /// the file, while the since-start counters will begin from zero. Corrupt or version-mismatched
+    /// files will be ignored with a warning.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::fs;
+    /// use std::time::Duration;
+    ///
+    /// let path = std::env::temp_dir().join(format!("pool-metrics-{}", std::process::id()));
+    /// let mut config = Config::new();
+    /// config.set_metrics_persistence(path.clone(), Duration::from_secs(60));
+    ///
+    /// for run in 1..=2 {
+    ///     let mut pool = ThreadPool::new_with_config(2, config.clone());
+    ///     for _ in 0..10 {
+    ///         pool.sync_block(|| {}).unwrap();
+    ///     }
+    ///
+    ///     // the final totals will be written on close
+    ///     pool.close();
+    ///
+    ///     let metrics = pool.metrics();
+    ///     assert_eq!(metrics.since_start_jobs_completed, 10);
+    ///     assert_eq!(metrics.lifetime_jobs_completed, 10 * run);
+    /// }
+    ///
+    /// // a corrupt file will be ignored, and the pool starts from scratch
+    /// fs::write(&path, b"garbage").unwrap();
+    /// let pool = ThreadPool::new_with_config(2, config);
+    /// assert_eq!(pool.metrics().lifetime_jobs_completed, 0);
+    ///
+    /// drop(pool);
+    /// fs::remove_file(&path).unwrap();
+    /// ```
+    ///
+    /// The file is replaced as a whole, so a reader never sees a torn file, even when the periodic
+    /// flushes race the final flush on close:
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::fs;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// // magic, version, payload length, payload, and the FNV-1a checksum of the payload, all in
+    /// // little endian; return the lifetime job count at the head of the payload
+    /// fn parse(buf: &[u8]) -> u64 {
+    ///     let u64_at = |at: usize| {
+    ///         let mut bytes = [0u8; 8];
+    ///         bytes.copy_from_slice(&buf[at..at + 8]);
+    ///         u64::from_le_bytes(bytes)
+    ///     };
+    ///
+    ///     assert_eq!(&buf[..8], &[b'T', b'P', b'M', b'S', 1, 0, 0, 0]);
+    ///     let len = u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]) as usize;
+    ///     assert_eq!(buf.len(), 12 + len + 8);
+    ///
+    ///     let checksum = buf[12..12 + len].iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+    ///         (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    ///     });
+    ///
+    ///     assert_eq!(u64_at(12 + len), checksum);
+    ///     u64_at(12)
+    /// }
+    ///
+    /// let path = std::env::temp_dir().join(format!("pool-metrics-race-{}", std::process::id()));
+    /// let mut config = Config::new();
+    /// config.set_metrics_persistence(path.clone(), Duration::from_millis(1));
+    ///
+    /// for run in 1..=20u64 {
+    ///     let mut pool = ThreadPool::new_with_config(2, config.clone());
+    ///     let done = Arc::new(AtomicBool::new(false));
+    ///
+    ///     // keep reading the file while the pool flushes and closes
+    ///     let reader = {
+    ///         let (path, done) = (path.clone(), Arc::clone(&done));
+    ///         thread::spawn(move || {
+    ///             while !done.load(Ordering::Acquire) {
+    ///                 if let Ok(buf) = fs::read(&path) {
+    ///                     assert!(parse(&buf) <= 5 * run);
+    ///                 }
+    ///             }
+    ///         })
+    ///     };
+    ///
+    ///     for _ in 0..5 {
+    ///         pool.sync_block(|| {}).unwrap();
+    ///     }
+    ///
+    ///     pool.close();
+    ///     done.store(true, Ordering::Release);
+    ///     reader.join().unwrap();
+    ///
+    ///     // the final flush on close always lands last
+    ///     assert_eq!(parse(&fs::read(&path).unwrap()), 5 * run);
+    /// }
+    ///
+    /// fs::remove_file(&path).unwrap();
+    /// ```
+    #[cfg(feature = "persist-metrics")]
+    fn set_metrics_persistence(&mut self, path: PathBuf, flush_interval: Duration) -> &mut Self {
+        self.metrics_persistence = Some((path, flush_interval));
+        self
+    }
+
+    /// Set how long a worker shall be idle before it can donate its cycles to the pool's idle task.
+    fn set_idle_task_threshold(&mut self, threshold: Duration) -> &mut Self {
+        self.idle_task_threshold = threshold;
+        self
+    }
+
+    /// Set how long the workers added by the auto scaling can be idle before they quit, the workers
+    /// the pool is created with will stay regardless, unless `set_privileged_worker_count` says
+    /// otherwise. Set `None`
+    /// to use the default idle timeout.
+    fn set_max_idle(&mut self, max_idle: Option<Duration>) -> &mut Self {
+        self.max_idle = max_idle;
+        self
+    }
+
+    /// Set how many of the workers shall be privileged, i.e. the first `count` workers the pool is
+    /// created or resized with, which stay regardless. The other workers will quit once they've been
+    /// idle for longer than the `max_idle`, like the auto scaled workers. The pool then brings back
+    /// one worker per new job, until it's back to the size it's created with. All the workers are
+    /// privileged by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_privileged_worker_count(1)
+    ///     .set_max_idle(Some(Duration::from_millis(200)));
+    ///
+    /// let mut pool = ThreadPool::new_with_config(4, config);
+    /// let privileged = pool.get_first_worker_id();
+    /// assert_eq!(pool.worker_count(), 4);
+    ///
+    /// // the idle workers purge themselves, but the privileged one stays
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while pool.worker_count() > 1 && Instant::now() < deadline {
+    ///     thread::sleep(Duration::from_millis(10));
+    /// }
+    ///
+    /// thread::sleep(Duration::from_millis(300));
+    /// assert_eq!(pool.worker_count(), 1);
+    /// assert_eq!(pool.reap(), 3);
+    /// assert_eq!(pool.get_first_worker_id(), privileged);
+    ///
+    /// // the new jobs grow the pool back to the size it's created with
+    /// let (tx, rx) = mpsc::channel();
+    /// for i in 0..8 {
+    ///     let tx = tx.clone();
+    ///     pool.exec(move || tx.send(i).unwrap(), false).unwrap();
+    /// }
+    ///
+    /// assert_eq!(rx.iter().take(8).sum::<i32>(), 28);
+    /// assert_eq!(pool.worker_count(), 4);
+    ///
+    /// // so do the workers added on resize, which leave the privileged one alone as well
+    /// pool.resize(6);
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while pool.worker_count() > 1 && Instant::now() < deadline {
+    ///     thread::sleep(Duration::from_millis(10));
+    /// }
+    ///
+    /// assert_eq!(pool.worker_count(), 1);
+    /// pool.reap();
+    /// assert_eq!(pool.worker_ids(), vec![privileged.unwrap()]);
+    /// ```
+    fn set_privileged_worker_count(&mut self, count: usize) -> &mut Self {
+        self.privileged_workers = Some(count);
+        self
+    }
+
+    /// Set how long a job can run before it's reported as overdue. The running jobs can't be stopped
+    /// halfway, so the overdue jobs are only reported: once per job to the `on_job_timeout` callback,
+    /// and counted in the `since_start_jobs_timed_out` of the pool metrics. If the pool is allowed to
+    /// auto scale, it will also add a worker to make up for each stuck one when taking new jobs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// static REPORTS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_job_timeout(Duration::from_millis(20))
+    ///     .set_on_job_timeout(|_id, elapsed| {
+    ///         assert!(elapsed >= Duration::from_millis(20));
+    ///         REPORTS.fetch_add(1, Ordering::SeqCst);
+    ///     });
+    ///
+    /// let pool = ThreadPool::new_with_config(2, config);
+    ///
+    /// // the slow job is only reported once, however long it runs over the timeout
+    /// pool.exec(|| thread::sleep(Duration::from_millis(200)), false).unwrap();
+    /// pool.exec(|| {}, false).unwrap();
+    /// pool.drain();
+    ///
+    /// assert_eq!(REPORTS.load(Ordering::SeqCst), 1);
+    /// assert_eq!(pool.metrics().since_start_jobs_timed_out, 1);
+    /// ```
+    fn set_job_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.job_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the callback to report the worker that has been running a job for longer than the job
+    /// timeout, with the worker id and how long the job has been running. The callback is run on the
+    /// timer thread of the pool, so a slow callback also holds up the delayed jobs.
+    fn set_on_job_timeout<F>(&mut self, on_timeout: F) -> &mut Self
+    where
+        F: Fn(usize, Duration) + Send + Sync + 'static,
+    {
+        self.on_job_timeout = Some(Arc::new(on_timeout));
+        self
+    }
+
+    /// Require the pool to block the caller when adding new workers, e.g. in `ThreadPool::new` or
+    /// when activating a lazy created pool, until every new worker thread has been initialized and
+    /// is ready to take jobs. This guarantees a fully initialized pool before any job is run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// static STARTED: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut config = Config::new();
+    /// config.set_require_sync_start(true);
+    /// config.set_worker_init(|id| {
+    ///     // runs on the worker thread, and some workers take longer to initialize than others
+    ///     thread::sleep(Duration::from_millis(20 * (id % 4) as u64));
+    ///     STARTED.fetch_add(1, Ordering::SeqCst);
+    /// });
+    ///
+    /// let pool = ThreadPool::new_with_config(4, config);
+    /// assert_eq!(pool.get_size(), 4);
+    ///
+    /// // whichever worker gets a job first, all workers have been initialized by then
+    /// let (tx, rx) = mpsc::channel();
+    /// for _ in 0..4 {
+    ///     let tx = tx.clone();
+    ///     pool.exec(move || {
+    ///         assert_eq!(STARTED.load(Ordering::SeqCst), 4);
+    ///         tx.send(()).unwrap();
+    ///     }, false).unwrap();
+    /// }
+    ///
+    /// drop(tx);
+    /// assert_eq!(rx.iter().count(), 4);
+    /// ```
+    fn set_require_sync_start(&mut self, sync_start: bool) -> &mut Self {
+        self.require_sync_start = sync_start;
+        self
+    }
+
     fn set_refresh_period(&mut self, period: Option<Duration>) -> &mut Self {
         self.refresh_period = period;
         self
     }
 
+    /// Override the built-in heuristic that decides the pool size when the pool is auto adjusted.
+    fn set_scaling_policy(&mut self, policy: Arc<dyn ScalingPolicy>) -> &mut Self {
+        self.scaling_policy = Some(policy);
+        self
+    }
+
+    /// Auto adjust the pool per the given tunables, see `AutoScalePolicy` for details. This replaces
+    /// the scaling policy set earlier, if any.
+    fn set_auto_scale_policy(&mut self, policy: AutoScalePolicy) -> &mut Self {
+        self.scaling_policy = Some(Arc::new(AutoScaler::new(policy)));
+        self
+    }
+
+    /// Set the least number of workers the pool shall keep when it's auto adjusted, whatever size the
+    /// scaling policy asks for.
+    fn set_min_workers(&mut self, min_workers: usize) -> &mut Self {
+        self.min_workers = min_workers;
+        self
+    }
+
+    /// Set the most number of workers the pool can grow to when it's auto adjusted, or `None` to
+    /// leave it to the scaling policy. If it's less than the `min_workers`, the `min_workers` wins.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::Arc;
+    ///
+    /// // a policy that wants a pool of the fixed size
+    /// struct Fixed(usize);
+    ///
+    /// impl ScalingPolicy for Fixed {
+    ///     fn desired_size(&self, _stats: &PoolStats, _current: usize) -> usize {
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_scaling_policy(Arc::new(Fixed(64)))
+    ///     .set_min_workers(2)
+    ///     .set_max_workers(Some(6));
+    ///
+    /// let mut pool = ThreadPool::new_with_config(4, config.clone());
+    /// pool.auto_adjust();
+    /// assert_eq!(pool.get_size(), 6);
+    ///
+    /// config.set_scaling_policy(Arc::new(Fixed(1)));
+    /// let mut pool = ThreadPool::new_with_config(4, config);
+    /// pool.auto_adjust();
+    /// assert_eq!(pool.get_size(), 2);
+    /// ```
+    fn set_max_workers(&mut self, max_workers: Option<usize>) -> &mut Self {
+        self.max_workers = max_workers;
+        self
+    }
+
     fn set_worker_behavior(&mut self, behavior: StatusBehaviors) -> &mut Self {
         self.worker_behaviors = behavior;
         self
     }
 
+    /// Set the hooks to trace each job through the pool, see `JobBehaviors` for examples.
+    fn set_job_behavior(&mut self, behavior: JobBehaviors) -> &mut Self {
+        self.job_behaviors = behavior;
+        self
+    }
+
+    /// Set the channel for the workers to report their lifecycle events to, such that a monitoring
+    /// task can receive the events of all workers in one place. The workers never block on the
+    /// sink: the events are dropped if a bounded channel is full, or if the receiver is gone.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate crossbeam_channel;
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let (tx, rx) = crossbeam_channel::unbounded();
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_event_sink(tx)
+    ///     .set_privileged_worker_count(1)
+    ///     .set_max_idle(Some(Duration::from_millis(50)));
+    ///
+    /// let mut pool = ThreadPool::new_with_config(2, config);
+    /// pool.exec(|| panic!("oops"), false).unwrap();
+    ///
+    /// // the unprivileged worker purges itself once it's been idle for long enough
+    /// while pool.worker_count() > 1 {
+    ///     thread::sleep(Duration::from_millis(10));
+    /// }
+    ///
+    /// pool.close();
+    ///
+    /// let events: Vec<WorkerEvent> = rx.try_iter().collect();
+    /// let count = |f: fn(&WorkerEvent) -> bool| events.iter().filter(|e| f(e)).count();
+    ///
+    /// assert_eq!(count(|e| matches!(e, WorkerEvent::Started(_))), 2);
+    /// assert_eq!(count(|e| matches!(e, WorkerEvent::PanicCaught(_))), 1);
+    /// assert_eq!(count(|e| matches!(e, WorkerEvent::Purged(_))), 1);
+    /// assert_eq!(count(|e| matches!(e, WorkerEvent::Stopped(_))), 1);
+    /// ```
+    fn set_event_sink(&mut self, sink: Sender<WorkerEvent>) -> &mut Self {
+        self.event_sink = Some(sink);
+        self
+    }
+
     /// Toggle on/off of the pool's non-blocking mode. If the pool is in the non-blocking mode, the
     /// `ThreadPool` will take the job submission and move on immediately, regardless of if the job
     /// submission is successful or not.
@@ -117,15 +1628,191 @@ impl ConfigStatus for Config {
         self
     }
 
+    /// Bound each job queue of the pool to hold at most `capacity` jobs, and apply the backpressure
+    /// to the job producers: once the queue is full, `exec` will return `ExecutionError::QueueFull`
+    /// instead of blocking the caller, while `exec_blocking` will wait for the space. Set it to `None`
+    /// to use the default queue size and dispatch behaviors. A pool allowed to auto scale will add
+    /// workers when the queue fills up, just like it does with the unbounded queues.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::{mpsc, Arc, Mutex};
+    /// use std::thread;
+    ///
+    /// let mut config = Config::new();
+    /// config.set_queue_capacity(Some(4));
+    ///
+    /// let mut pool = ThreadPool::new_with_config(2, config);
+    /// pool.toggle_auto_scale(true);
+    ///
+    /// // keep both workers busy
+    /// let (tx, rx) = mpsc::channel::<()>();
+    /// let rx = Arc::new(Mutex::new(rx));
+    /// for _ in 0..2 {
+    ///     let rx = Arc::clone(&rx);
+    ///     pool.exec(move || { let _ = rx.lock().unwrap().recv(); }, false).unwrap();
+    /// }
+    ///
+    /// while pool.stats().active_workers < 2 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// // filling up the bounded queues brings in more workers to take the jobs
+    /// for _ in 0..16 {
+    ///     let _ = pool.exec(|| {}, false);
+    /// }
+    ///
+    /// assert!(pool.get_size() > 2);
+    /// drop(tx);
+    /// ```
+    fn set_queue_capacity(&mut self, capacity: Option<usize>) -> &mut Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// Create a state of the type `T` for each worker when it starts, which the jobs can access with
+    /// `with_worker_state` while running on that worker. This is useful for the per-thread resources
+    /// that are expensive to create, e.g. a database connection or a scratch buffer.
+    fn set_worker_init<T, F>(&mut self, init: F) -> &mut Self
+    where
+        T: Send + 'static,
+        F: Fn(usize) -> T + Send + Sync + 'static,
+    {
+        self.worker_state.set_init(init);
+        self
+    }
+
+    /// Hand over the worker state to the teardown hook right before the worker quits, whether the
+    /// worker is retired by a resize, purged after idling, or the pool is closing.
+    fn set_worker_teardown<T, F>(&mut self, teardown: F) -> &mut Self
+    where
+        T: 'static,
+        F: Fn(usize, T) + Send + Sync + 'static,
+    {
+        self.worker_state.set_teardown(teardown);
+        self
+    }
+
+    /// Set the callback to take the last error of a job submitted through `ThreadPool::exec_retry`,
+    /// once the job has failed all its attempts. The callback will be run by the worker, see
+    /// `ThreadPool::exec_retry` for examples.
+    ///
+    /// The pool keeps one callback for all jobs, so only the errors of the type `E` are passed to
+    /// it. The errors of any other type are dropped, and only reported in the debug mode; the jobs
+    /// failing with different error types shall map their errors into `E`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::time::Duration;
+    ///
+    /// static GAVE_UP: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut config = Config::new();
+    /// config.set_retry_failure(|_err: String| {
+    ///     GAVE_UP.fetch_add(1, Ordering::SeqCst);
+    /// });
+    ///
+    /// let pool = ThreadPool::new_with_config(1, config);
+    /// let backoff = Duration::from_millis(1);
+    ///
+    /// // the error of another type can't be passed to the callback
+    /// pool.exec_retry(|| Err(42), 2, backoff).unwrap();
+    ///
+    /// // unless it's mapped into the type the callback takes
+    /// pool.exec_retry(|| Err(42).map_err(|code: i32| code.to_string()), 2, backoff).unwrap();
+    ///
+    /// pool.drain();
+    /// assert_eq!(GAVE_UP.load(Ordering::SeqCst), 1);
+    /// ```
+    fn set_retry_failure<E, F>(&mut self, on_failure: F) -> &mut Self
+    where
+        E: Send + 'static,
+        F: Fn(E) + Send + Sync + 'static,
+    {
+        self.retry_failure = Some(Arc::new(move |err: Box<dyn Any + Send>| {
+            match err.downcast::<E>() {
+                Ok(err) => on_failure(*err),
+                Err(_) if is_debug_mode() => log(
+                    Level::Warn,
+                    format_args!(
+                        "The retry failure callback takes the errors of {}, dropping the error",
+                        std::any::type_name::<E>()
+                    ),
+                ),
+                Err(_) => {}
+            }
+        }));
+
+        self
+    }
+
+    /// Set the stack size of the worker threads in bytes, the same as `set_worker_stack_size`.
     fn set_thread_size(&mut self, size: usize) -> &mut Self {
+        self.set_worker_stack_size(size)
+    }
+
+    /// Set the stack size of the worker threads in bytes, or 0 to use the platform default. All the
+    /// workers of the pool are spawned with it, including the ones added on resize or auto scaling.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::hint::black_box;
+    /// use std::sync::mpsc;
+    ///
+    /// // each call takes 64KB of the stack, so 48 calls take 3MB, more than the default 2MB
+    /// fn dig(depth: usize) -> usize {
+    ///     let frame = black_box([depth as u8; 64 * 1024]);
+    ///     if depth == 0 {
+    ///         return frame[0] as usize;
+    ///     }
+    ///
+    ///     dig(depth - 1) + frame[frame.len() - 1] as usize
+    /// }
+    ///
+    /// let mut config = Config::new();
+    /// config.set_worker_stack_size(16 * 1024 * 1024);
+    ///
+    /// let mut pool = ThreadPool::new_with_config(1, config);
+    /// pool.resize(4);
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// for _ in 0..4 {
+    ///     let tx = tx.clone();
+    ///     pool.exec(move || tx.send(dig(48)).unwrap(), false).unwrap();
+    /// }
+    ///
+    /// assert!(rx.iter().take(4).all(|sum| sum == (0..=48).sum()));
+    /// ```
+    fn set_worker_stack_size(&mut self, size: usize) -> &mut Self {
         self.thread_size = size;
         self
     }
-
     fn set_timeout_policy(&mut self, policy: TimeoutPolicy) -> &mut Self {
         self.timeout_policy = policy;
         self
     }
+
+    /// Set how many items each job of `ThreadPool::map` takes at a time. Larger chunks cut down
+    /// the overhead of queueing one job per item when the items vastly outnumber the workers, but
+    /// a slow chunk may hold up the whole call. Set `None` to split the items into a few chunks
+    /// per worker, which is the default. A zero chunk size is taken as 1.
+    fn set_map_chunk_size(&mut self, size: Option<usize>) -> &mut Self {
+        self.map_chunk_size = size.map(|size| size.max(1));
+        self
+    }
 }
 
 impl StatusBehaviorSetter for Config {
@@ -144,4 +1831,12 @@ impl StatusBehaviorSetter for Config {
     fn set_after_drop(&mut self, behavior: WorkerUpdate) {
         self.worker_behaviors.set_after_drop(behavior);
     }
+
+    fn set_on_panic(&mut self, behavior: WorkerPanic) {
+        self.worker_behaviors.set_on_panic(behavior);
+    }
+
+    fn set_on_starved_worker(&mut self, behavior: WorkerStarved) {
+        self.worker_behaviors.set_on_starved_worker(behavior);
+    }
 }
@@ -1,19 +1,128 @@
 use std::env;
-use std::sync::Once;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
 
-static ONCE: Once = Once::new();
-static mut DEBUG: bool = false;
+use crate::worker::current_worker_context;
+
+const DEBUG_UNSET: u8 = 0;
+const DEBUG_OFF: u8 = 1;
+const DEBUG_ON: u8 = 2;
+
+/// The debug mode, which is read from the `DEBUG_POOL` env var on the first check, unless it's set
+/// by `set_debug` before that.
+static DEBUG: AtomicU8 = AtomicU8::new(DEBUG_UNSET);
+
+type Logger = Arc<dyn Fn(Level, &str) + Send + Sync>;
+
+static LOGGER: RwLock<Option<Logger>> = RwLock::new(None);
+
+/// The severity of a message from the pools.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
 
 #[inline(always)]
 pub(crate) fn is_debug_mode() -> bool {
-    unsafe {
-        ONCE.call_once(|| {
-            DEBUG = match env::var("DEBUG_POOL") {
-                Ok(val) => (&val == "1"),
+    match DEBUG.load(Ordering::Acquire) {
+        DEBUG_ON => true,
+        DEBUG_OFF => false,
+        _ => {
+            let from_env = match env::var("DEBUG_POOL") {
+                Ok(val) => &val == "1",
                 Err(_) => false,
             };
-        });
 
-        DEBUG
+            // a racing `set_debug` call wins over the env var
+            let mode = if from_env { DEBUG_ON } else { DEBUG_OFF };
+            let _ = DEBUG.compare_exchange(DEBUG_UNSET, mode, Ordering::AcqRel, Ordering::Acquire);
+
+            DEBUG.load(Ordering::Acquire) == DEBUG_ON
+        }
+    }
+}
+
+/// Turn the debug mode on or off at runtime, which overrides the `DEBUG_POOL` env var. In the
+/// debug mode, the pools report what they're doing and what has gone wrong to the logger set by
+/// `set_logger`, or to stdout and stderr if there's none.
+pub fn set_debug(enabled: bool) {
+    DEBUG.store(if enabled { DEBUG_ON } else { DEBUG_OFF }, Ordering::Release);
+}
+
+/// Route the messages from the pools to the `logger` instead of stdout and stderr, e.g. to hand
+/// them to a structured logger. The messages logged on a worker thread are prefixed with the pool
+/// name, if it's set, and the worker id, like `[io/worker-16]`. The logger may be called from any
+/// thread, including the workers, and shall not block for long.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::{Arc, Mutex};
+///
+/// let logs = Arc::new(Mutex::new(Vec::new()));
+/// let sink = Arc::clone(&logs);
+/// set_logger(move |level, msg| sink.lock().unwrap().push((level, msg.to_string())));
+/// set_debug(true);
+///
+/// let mut config = Config::new();
+/// config.set_pool_name(String::from("logged"));
+///
+/// let mut pool = ThreadPool::new_with_config(1, config);
+/// pool.exec(|| panic!("boom"), false).unwrap();
+///
+/// // the only worker is done with the bad job once it gets to the next one
+/// pool.sync_block(|| {}).unwrap();
+///
+/// assert!(logs.lock().unwrap().iter().any(|(level, msg)| {
+///     *level == Level::Warn
+///         && msg.starts_with("[logged/worker-")
+///         && msg.contains("has caught a panic from the job")
+/// }));
+///
+/// // nothing is logged once the debug mode is off
+/// let count = logs.lock().unwrap().len();
+/// set_debug(false);
+/// drop(pool);
+///
+/// assert_eq!(logs.lock().unwrap().len(), count);
+/// clear_logger();
+/// ```
+pub fn set_logger<F>(logger: F)
+where
+    F: Fn(Level, &str) + Send + Sync + 'static,
+{
+    *LOGGER.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(logger));
+}
+
+/// Remove the logger set by `set_logger`, and print the messages to stdout and stderr again.
+pub fn clear_logger() {
+    *LOGGER.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Hand the message to the logger, or print it if there's no logger: the `Debug` and `Info`
+/// messages go to stdout, and the rest to stderr.
+pub(crate) fn log(level: Level, args: fmt::Arguments) {
+    let msg = match current_worker_context() {
+        Some(context) => format!("[{}] {}", context, args),
+        None => args.to_string(),
+    };
+
+    // don't hold the lock while the logger is running
+    let logger = LOGGER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+
+    match (logger, level) {
+        (Some(logger), _) => logger(level, &msg),
+        (None, Level::Debug) | (None, Level::Info) => println!("{}", msg),
+        (None, _) => eprintln!("{}", msg),
     }
 }
@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::manager::JobBehaviors;
+use crate::metrics::PoolMetrics;
+use crate::model::Message;
+use crate::pool::ExecutionError;
+use crossbeam_channel::{SendTimeoutError, Sender};
+
+/// How long the dispatcher waits for the space in a full job queue before checking if the pool has
+/// been closed in the meantime.
+const SEND_RETRY: Duration = Duration::from_millis(10);
+
+/// A lightweight handle to submit jobs to a `ThreadPool` from anywhere, created by
+/// `ThreadPool::dispatcher`. It can be cloned and shared across threads freely, and keeps working
+/// for as long as the pool is open. Once the pool is closed, the jobs are rejected with
+/// `ExecutionError::Disconnected`.
+///
+/// The dispatcher hands the jobs straight to the job queues, and waits for the space if a queue is
+/// full, until the pool is closed. Unlike `ThreadPool::exec`, it neither activates a lazily built or hibernating pool, nor
+/// scales the pool under pressure.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::num::NonZeroUsize;
+/// use std::sync::Arc;
+/// use std::sync::mpsc;
+/// use std::thread;
+///
+/// let pool = Arc::new(ThreadPool::new(NonZeroUsize::new(4).unwrap()));
+/// let dispatcher = pool.dispatcher();
+/// let (tx, rx) = mpsc::channel();
+///
+/// // 16 producers share the pool without a mutex: half through the pool itself, half through the
+/// // dispatcher
+/// let producers: Vec<_> = (0..16)
+///     .map(|i| {
+///         let (pool, dispatcher) = (Arc::clone(&pool), dispatcher.clone());
+///         let tx = tx.clone();
+///
+///         thread::spawn(move || {
+///             for _ in 0..625 {
+///                 let tx = tx.clone();
+///                 let job = move || tx.send(()).unwrap();
+///
+///                 if i % 2 == 0 {
+///                     pool.exec(job, false).unwrap();
+///                 } else {
+///                     dispatcher.exec(job).unwrap();
+///                 }
+///             }
+///         })
+///     })
+///     .collect();
+///
+/// producers.into_iter().for_each(|p| p.join().unwrap());
+/// assert_eq!(rx.iter().take(10_000).count(), 10_000);
+///
+/// // the dispatcher outlives the pool, but the jobs are rejected from then on
+/// let mut pool = Arc::try_unwrap(pool).ok().unwrap();
+/// pool.close();
+///
+/// let err = dispatcher.exec_priority(|| {}).unwrap_err();
+/// assert!(matches!(err, ExecutionError::Disconnected));
+/// ```
+///
+/// A caller waiting on a full queue is let go once the pool is closed:
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::mpsc;
+/// use std::thread;
+/// use std::time::{Duration, Instant};
+///
+/// let mut config = Config::new();
+/// config.set_queue_capacity(Some(1));
+///
+/// let mut pool = ThreadPool::new_with_config(1, config);
+/// let dispatcher = pool.dispatcher();
+///
+/// // hold the only worker, and fill up the queue behind it
+/// let (hold_tx, hold_rx) = mpsc::channel::<()>();
+/// dispatcher.exec(move || { let _ = hold_rx.recv(); }).unwrap();
+///
+/// let deadline = Instant::now() + Duration::from_secs(5);
+/// while pool.stats().active_workers == 0 && Instant::now() < deadline {
+///     thread::yield_now();
+/// }
+/// dispatcher.exec(|| {}).unwrap();
+///
+/// let (tx, rx) = mpsc::channel();
+/// let blocked = dispatcher.clone();
+/// thread::spawn(move || tx.send(blocked.exec(|| {})).unwrap());
+///
+/// // the job can't be queued while the worker is held
+/// assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+///
+/// // let the worker go once the pool is closing, the queued job is dropped with the pool
+/// thread::spawn(move || {
+///     thread::sleep(Duration::from_millis(50));
+///     drop(hold_tx);
+/// });
+/// pool.force_close();
+///
+/// let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+/// assert!(matches!(res, Err(ExecutionError::Disconnected)));
+/// ```
+#[derive(Clone)]
+pub struct Dispatcher {
+    chan: (Sender<Message>, Sender<Message>, Sender<Message>),
+    behaviors: Option<JobBehaviors>,
+    metrics: Arc<PoolMetrics>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(
+        chan: (Sender<Message>, Sender<Message>, Sender<Message>),
+        behaviors: Option<JobBehaviors>,
+        metrics: Arc<PoolMetrics>,
+        closed: Arc<AtomicBool>,
+    ) -> Self {
+        Dispatcher {
+            chan,
+            behaviors,
+            metrics,
+            closed,
+        }
+    }
+
+    /// Submit the job to the normal job queue of the pool.
+    pub fn exec<F: FnOnce() + Send + 'static>(&self, f: F) -> Result<(), ExecutionError> {
+        self.send(&self.chan.1, Message::SingleJob(Box::new(f)))
+    }
+
+    /// Submit the job to the priority job queue of the pool.
+    pub fn exec_priority<F: FnOnce() + Send + 'static>(&self, f: F) -> Result<(), ExecutionError> {
+        self.send(&self.chan.0, Message::SingleJob(Box::new(f)))
+    }
+
+    fn send(&self, chan: &Sender<Message>, message: Message) -> Result<(), ExecutionError> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(ExecutionError::Disconnected);
+        }
+
+        let (message, count) = match self.behaviors.as_ref() {
+            Some(behaviors) => behaviors.instrument(message),
+            None => (message, 1),
+        };
+
+        // wait for the space in slices, such that a close while we're waiting turns the job away
+        // instead of leaving the caller blocked on a queue that no worker takes from anymore
+        let mut message = message;
+        loop {
+            match chan.send_timeout(message, SEND_RETRY) {
+                Ok(()) => break,
+                Err(SendTimeoutError::Timeout(m)) => {
+                    if self.closed.load(Ordering::Acquire) {
+                        return Err(ExecutionError::Disconnected);
+                    }
+
+                    message = m;
+                }
+                Err(SendTimeoutError::Disconnected(_)) => {
+                    return Err(ExecutionError::Disconnected);
+                }
+            }
+        }
+
+        // same as the pool's `pending_jobs`, the depth of all job queues
+        let pending = self.chan.0.len() + self.chan.1.len() + self.chan.2.len();
+        self.metrics.record_queue_depth(pending);
+        if let Some(behaviors) = self.behaviors.as_ref() {
+            behaviors.enqueued(count, pending);
+        }
+
+        Ok(())
+    }
+}
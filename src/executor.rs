@@ -2,132 +2,424 @@
 
 use std::cell::RefCell;
 use std::future::Future;
-use std::pin::Pin;
-use std::sync::{Arc, mpsc::RecvTimeoutError};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock, mpsc::RecvTimeoutError};
 use std::task::{Context, Poll, Waker};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::thread::{self, Thread, JoinHandle};
 
 use crate::ExecutionError;
 use async_task::{Task};
-use crossbeam_deque::Worker;
+use crossbeam_deque::{Injector, Steal, Worker};
 use crossbeam_channel as channel;
 use crossbeam_utils::sync::Parker;
 use crossbeam_channel::{Sender, Receiver};
 
-#[macro_export]
-macro_rules! pin_mut {
-    ( $( $x:ident ), *) => {
-        $(
-            // Move the value to ensure that it is owned
-            let mut $x = $x;
-
-            // Shadow the original binding so that it can't be directly accessed
-            // ever again.
-            #[allow(unused_mut)]
-            let mut $x = unsafe {
-                $crate::core_export::pin::Pin::new_unchecked(&mut $x)
-            };
-        ) *
-    }
+/// Block the current thread until the future is completed, and return its output. The future can
+/// call `block_on` again, e.g. a pool job that is itself run by `block_on` can wait for another
+/// future, in which case the nested call will park the thread on its own.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll};
+///
+/// // a future that's pending once before it's ready
+/// struct YieldOnce(bool);
+///
+/// impl Future for YieldOnce {
+///     type Output = ();
+///
+///     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+///         if self.0 {
+///             return Poll::Ready(());
+///         }
+///
+///         self.0 = true;
+///         cx.waker().wake_by_ref();
+///         Poll::Pending
+///     }
+/// }
+///
+/// // the async block holds a reference to its own local across the await point, which makes it
+/// // a self-referential state machine that must stay pinned while being polled
+/// let sum = block_on(async {
+///     let values = vec![1, 2, 3];
+///     let first = &values[0];
+///
+///     YieldOnce(false).await;
+///     first + values.iter().sum::<i32>()
+/// });
+///
+/// assert_eq!(sum.unwrap(), 7);
+///
+/// // wait for the future spawned on the other pool, from within the future we're blocked on
+/// let fut_pool = FutPool::new(1);
+/// let nested = block_on(async {
+///     let rx = fut_pool.spawn(async { YieldOnce(false).await; 2 });
+///     block_on(async move { rx.recv().unwrap() }).unwrap() * 3
+/// });
+///
+/// assert_eq!(nested.unwrap(), 6);
+/// ```
+pub fn block_on<T>(fut: impl Future<Output=T>) -> Result<T, ExecutionError> {
+    park_on(fut, None)
 }
 
-pub fn block_on<T>(mut fut: impl Future<Output=T>) -> Result<T, ExecutionError> {
-    thread_local! {
-        static CACHE: RefCell<(Parker, Waker)> = {
-            let parker = Parker::new();
-            let unparker = parker.unparker().clone();
-            let waker = async_task::waker_fn(move || unparker.unpark());
+/// Block the current thread until the future is completed like `block_on`, or return the
+/// `ExecutionError::Timeout` error if it's not completed within the `timeout`. The future will not
+/// be polled again once it has timed out, and is dropped as is: whatever it has done by then, e.g.
+/// the data it has written or the jobs it has spawned, is not undone.
+///
+/// The future may still wake the thread after the timeout, e.g. through a waker it has handed out.
+/// Such late wake ups are ignored, and won't cut short the next `block_on` on the thread.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::{Arc, Mutex};
+/// use std::task::{Context, Poll, Waker};
+/// use std::time::Duration;
+///
+/// // a future that's never woken up
+/// struct Stuck;
+///
+/// impl Future for Stuck {
+///     type Output = ();
+///
+///     fn poll(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<()> {
+///         Poll::Pending
+///     }
+/// }
+///
+/// match block_on_timeout(Stuck, Duration::from_millis(20)) {
+///     Err(ExecutionError::Timeout) => {}
+///     _ => panic!("the future shall time out"),
+/// }
+///
+/// assert_eq!(block_on_timeout(async { 42 }, Duration::from_millis(20)).unwrap(), 42);
+///
+/// // a future that hands out its waker, and then counts how many times it's polled
+/// struct Counted(Arc<Mutex<Option<Waker>>>, Arc<AtomicUsize>);
+///
+/// impl Future for Counted {
+///     type Output = ();
+///
+///     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+///         self.0.lock().unwrap().replace(cx.waker().clone());
+///         self.1.fetch_add(1, Ordering::SeqCst);
+///         Poll::Pending
+///     }
+/// }
+///
+/// let (waker, polls) = (Arc::new(Mutex::new(None)), Arc::new(AtomicUsize::new(0)));
+/// let late = Counted(Arc::clone(&waker), Arc::new(AtomicUsize::new(0)));
+/// assert!(block_on_timeout(late, Duration::from_millis(20)).is_err());
+///
+/// // wake up the timed out future, which shall not wake up the next one
+/// waker.lock().unwrap().take().unwrap().wake();
+///
+/// let next = Counted(Arc::new(Mutex::new(None)), Arc::clone(&polls));
+/// assert!(block_on_timeout(next, Duration::from_millis(20)).is_err());
+/// assert_eq!(polls.load(Ordering::SeqCst), 1);
+/// ```
+pub fn block_on_timeout<T>(
+    fut: impl Future<Output=T>,
+    timeout: Duration,
+) -> Result<T, ExecutionError> {
+    park_on(fut, Some(Instant::now() + timeout))
+}
 
-            RefCell::new((parker, waker))
-        };
+fn park_on<T>(fut: impl Future<Output=T>, deadline: Option<Instant>) -> Result<T, ExecutionError> {
+    thread_local! {
+        static CACHE: RefCell<(Parker, Waker)> = RefCell::new(new_parker());
     }
 
-    CACHE.with(|cache| {
-        let (parker, waker) =
-            &mut *cache.try_borrow_mut().expect("recursive entry forbidden ... ");
-
-        pin_mut!(fut);
+    CACHE.with(|cache| match cache.try_borrow_mut() {
+        Ok(mut cached) => {
+            let (parker, waker) = &*cached;
+            let res = poll_until(fut, parker, waker, deadline);
 
-        loop {
-            match fut.as_mut().poll(&mut Context::from_waker(&waker)) {
-                Poll::Ready(val) => return Ok(val),
-                Poll::Pending => parker.park(),
+            // the timed out future may still hold the waker, don't let it unpark the next one
+            if let Err(ExecutionError::Timeout) = res {
+                *cached = new_parker();
             }
+
+            res
+        }
+        Err(_) => {
+            // nested in the future polled by the outer `block_on`, which is holding the cached
+            // parker, so park on a fresh one instead
+            let (parker, waker) = new_parker();
+            poll_until(fut, &parker, &waker, deadline)
         }
     })
 }
 
+/// Create the parker to block the thread on, and the waker to unpark it.
+fn new_parker() -> (Parker, Waker) {
+    let parker = Parker::new();
+    let unparker = parker.unparker().clone();
+    let waker = async_task::waker_fn(move || unparker.unpark());
+
+    (parker, waker)
+}
+
+/// Poll the future until it's completed, and park the thread in between the polls.
+fn poll_until<T>(
+    fut: impl Future<Output=T>,
+    parker: &Parker,
+    waker: &Waker,
+    deadline: Option<Instant>,
+) -> Result<T, ExecutionError> {
+    let mut fut = pin!(fut);
+
+    loop {
+        if let Poll::Ready(val) = fut.as_mut().poll(&mut Context::from_waker(waker)) {
+            return Ok(val);
+        }
+
+        match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(ExecutionError::Timeout);
+                }
+
+                parker.park_timeout(deadline - now);
+
+                if Instant::now() >= deadline {
+                    return Err(ExecutionError::Timeout);
+                }
+            }
+            None => parker.park(),
+        }
+    }
+}
+
+/// How long an idle future worker parks before checking the queue again, in case a wake up has
+/// gone to a busy sibling.
+const FUT_IDLE_PERIOD: Duration = Duration::from_millis(16);
+
+/// A pool of threads to run the futures on. The woken futures are scheduled into a shared queue,
+/// from which the idle workers steal and poll them.
 pub struct FutPool {
-    workers: Vec<Thread>,
+    queue: Arc<Injector<Task<()>>>,
+    workers: Arc<Vec<Thread>>,
+    handles: Vec<JoinHandle<()>>,
+    next_wake: Arc<AtomicUsize>,
+    closing: Arc<AtomicBool>,
 }
 
 impl FutPool {
-    pub fn spawn<F, R>(fut: F) -> Receiver<R>
+    /// Create the pool with `size` worker threads, at least 1 worker will be spawned. The workers
+    /// will finish all scheduled futures before the pool is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    ///
+    /// let pool = FutPool::new(2);
+    ///
+    /// let results: Vec<_> = (0..8).map(|i| pool.spawn(async move { i * 2 })).collect();
+    /// let sum: usize = results.into_iter().map(|rx| rx.recv().unwrap()).sum();
+    ///
+    /// assert_eq!(sum, 56);
+    /// ```
+    pub fn new(size: usize) -> Self {
+        let queue = Arc::new(Injector::new());
+        let closing = Arc::new(AtomicBool::new(false));
+        let mut handles = Vec::with_capacity(size.max(1));
+
+        for id in 0..size.max(1) {
+            let queue = Arc::clone(&queue);
+            let closing = Arc::clone(&closing);
+
+            let handle = thread::Builder::new()
+                .name(format!("fut-worker-{}", id))
+                .spawn(move || FutPool::run_worker(&queue, &closing))
+                .expect("Unable to spawn the future workers");
+
+            handles.push(handle);
+        }
+
+        let workers = handles.iter().map(|h| h.thread().clone()).collect();
+
+        FutPool {
+            queue,
+            workers: Arc::new(workers),
+            handles,
+            next_wake: Arc::new(AtomicUsize::new(0)),
+            closing,
+        }
+    }
+
+    /// Run the future on the pool, and the returned receiver will get the output of the future once
+    /// it's completed.
+    pub fn spawn<F, R>(&self, fut: F) -> Receiver<R>
     where
         F: Future<Output = R> + Send + 'static,
         R: Send + 'static,
     {
         let (tx, rx): (Sender<R>, Receiver<R>) = channel::bounded(1);
 
-        let f = Box::pin(async move {
-            tx.send(fut.await).expect("failed to send the result back ... ")
-        });
+        let f = async move {
+            // the caller may have dropped the receiver, which is fine
+            tx.send(fut.await).unwrap_or_default();
+        };
+
+        let queue = Arc::clone(&self.queue);
+        let workers = Arc::clone(&self.workers);
+        let next_wake = Arc::clone(&self.next_wake);
+
+        // whenever the task is woken up, put it back to the queue and wake up a worker for it
+        let schedule = move |task| {
+            queue.push(task);
 
-        //TODO: send the task to the pool of workers
+            let id = next_wake.fetch_add(1, Ordering::Relaxed) % workers.len();
+            workers[id].unpark();
+        };
+
+        let (task, _handle) = async_task::spawn(f, schedule, ());
+        task.schedule();
 
         rx
     }
+
+    fn run_worker(queue: &Injector<Task<()>>, closing: &AtomicBool) {
+        loop {
+            match queue.steal() {
+                Steal::Success(task) => {
+                    task.run();
+                }
+                Steal::Retry => continue,
+                Steal::Empty => {
+                    if closing.load(Ordering::Acquire) {
+                        return;
+                    }
+
+                    thread::park_timeout(FUT_IDLE_PERIOD);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for FutPool {
+    fn drop(&mut self) {
+        self.closing.store(true, Ordering::Release);
+
+        for worker in self.workers.iter() {
+            worker.unpark();
+        }
+
+        for handle in self.handles.drain(..) {
+            handle.join().unwrap_or_default();
+        }
+    }
 }
 
+/// Run the future on the global future pool, which is created with a worker per CPU core on the
+/// first use, and the returned receiver will get the output of the future once it's completed. The
+/// future makes progress on the pool threads, whichever thread it's submitted from or woken up by.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use std::sync::{Arc, Mutex};
+/// use std::task::{Context, Poll, Waker};
+/// use std::thread;
+///
+/// // the value handed over by another thread
+/// #[derive(Default)]
+/// struct Slot {
+///     value: Option<usize>,
+///     waker: Option<Waker>,
+/// }
+///
+/// struct Handover(Arc<Mutex<Slot>>);
+///
+/// impl Future for Handover {
+///     type Output = usize;
+///
+///     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<usize> {
+///         let mut slot = self.0.lock().unwrap();
+///         match slot.value.take() {
+///             Some(value) => Poll::Ready(value),
+///             None => {
+///                 slot.waker = Some(cx.waker().clone());
+///                 Poll::Pending
+///             }
+///         }
+///     }
+/// }
+///
+/// // the futures are submitted from a few threads, and woken up from a few others
+/// let submitters: Vec<_> = (0..4)
+///     .map(|i| {
+///         thread::spawn(move || {
+///             let slot = Arc::new(Mutex::new(Slot::default()));
+///             let handover = Handover(Arc::clone(&slot));
+///             let rx = spawn(async move { handover.await * 2 });
+///
+///             thread::spawn(move || {
+///                 let mut slot = slot.lock().unwrap();
+///                 slot.value = Some(i);
+///                 if let Some(waker) = slot.waker.take() {
+///                     waker.wake();
+///                 }
+///             });
+///
+///             rx
+///         })
+///     })
+///     .collect();
+///
+/// let sum: usize = submitters.into_iter().map(|s| s.join().unwrap().recv().unwrap()).sum();
+/// assert_eq!(sum, 12);
+/// ```
 pub fn spawn<F, R>(fut: F) -> Receiver<R>
 where
     F: Future<Output = R> + Send + 'static,
     R: Send + 'static,
 {
-    let (tx, rx): (Sender<R>, Receiver<R>) = channel::bounded(1);
-
-    let f = Box::pin(async move {
-        tx.send(fut.await).expect("failed to send the result back ... ")
-    });
-
-    rx
+    enqueue(fut)
 }
 
-thread_local! {
-    static QUEUE: Arc<Worker<Task<()>>> = Arc::new(Worker::new_fifo());
-}
+/// The future pool shared by the whole process, which is created on the first use.
+static GLOBAL_POOL: OnceLock<FutPool> = OnceLock::new();
 
+/// Run the future on the global future pool, such that the task will make progress on the pool
+/// threads no matter which thread it's submitted from or woken up by. See `spawn` for examples.
 pub(crate) fn enqueue<F, R>(future: F) -> channel::Receiver<R>
 where
-    F: Future<Output = R> + 'static,
+    F: Future<Output = R> + Send + 'static,
     R: Send + 'static,
 {
-    // If the task gets woken up, it will be sent into this channel.
-    let (s, r) = channel::bounded(1);
-    let fut = async move {
-        s.send(future.await);
-    };
-
-    let schedule = move |task| {
-        QUEUE.with(|q| {
-            q.push(task);
-        });
-    };
-
-    // Create a task with the future and the schedule function.
-    let (task, handle) =
-        async_task::spawn_local(fut, schedule, ());
-
-    task.run();
-
-    r
-}
-
-fn poll() {
-    QUEUE.with(|q| {
-        if let Some(mut task) = q.pop() {
-            task.run();
-        }
-    });
+    GLOBAL_POOL
+        .get_or_init(|| {
+            let size = thread::available_parallelism().map_or(1, |size| size.get());
+            FutPool::new(size)
+        })
+        .spawn(future)
 }
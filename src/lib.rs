@@ -1,40 +1,79 @@
+mod affinity;
+mod builder;
+mod burst;
+mod cancel;
 mod config;
 mod debug;
+mod dispatcher;
 mod executor;
 mod manager;
+mod metrics;
 mod model;
 mod multi;
 mod pool;
+mod scaling;
+mod scope;
 mod single;
+#[cfg(feature = "stack-capture")]
+mod stack;
+mod state;
+mod timer;
+mod watchdog;
 mod worker;
 
-#[doc(hidden)]
-pub mod core_export {
-    pub use core::*;
-}
-
 pub use crate::{
-    config::{Config, ConfigStatus, TimeoutPolicy},
-    manager::{StatusBehaviorSetter, StatusBehaviors},
+    builder::{BuilderError, PoolBuilder},
+    burst::BurstPool,
+    cancel::{CancelHandle, CancellationToken},
+    config::{
+        BgThreadConfig, Config, ConfigError, ConfigStatus, DispatchBackend, FetchStrategy,
+        JobOrdering, ParkingProfile, TimeoutPolicy,
+    },
+    debug::{clear_logger, set_debug, set_logger, Level},
+    dispatcher::Dispatcher,
+    manager::{IdleTask, JobBehaviors, StatusBehaviorSetter, StatusBehaviors, WorkerEvent},
+    metrics::{PoolMetricsSnapshot, PoolStats, LATENCY_BUCKETS},
+    scaling::{AutoScalePolicy, DefaultScalingPolicy, ScalingPolicy},
+    scope::Scope,
     pool::{
-        ExecutionError, Hibernation, PoolManager, PoolState, ThreadPool, ThreadPoolStates,
+        AlreadyInitialized, BatchError, ExecutionError, Hibernation, PoolManager, PoolState,
+        Priority, RejectedJob, ThreadPool, ThreadPoolStates,
     },
+    state::with_worker_state,
+    timer::TaskId,
     executor::{
-        block_on, FutPool,
+        block_on, block_on_timeout, spawn, FutPool,
     },
 };
 
+#[cfg(feature = "stack-capture")]
+pub use crate::stack::CaptureError;
+
 pub mod shared_mode {
-    pub use crate::single::{close, init_with_config, initialize, resize, run};
+    pub use crate::single::{
+        close, close_timeout, drain, init_with_config, initialize, initialize_with_auto_adjustment,
+        is_initialized, is_paused, pause, pending_jobs, resize, resume, run, run_batch,
+        set_spawn_detached, spawn, stats, try_run, wait_for_idle, worker_count,
+    };
 }
 
 pub mod index_mode {
-    pub use crate::multi::{close, initialize, resize_pool, run_with};
+    pub use crate::multi::{
+        add_pool, clear_routes, close, close_ordered, init_with_config, initialize,
+        initialize_with_auto_adjustment, initialize_with_stealing, is_auto_adjustment_running,
+        is_initialized, is_pool_in_auto_mode, list_pools, pool_exists, pool_pending_jobs,
+        pool_worker_count, remove_pool, resize_pool, run_batch_with, run_tagged, run_with,
+        run_with_or_spawn, run_with_priority, set_default_pool, set_fallback_pool, set_route,
+        set_run_policy, set_shutdown_order, stats, toggle_pool_auto_mode, toggle_pool_stealing,
+        try_run_with, RemovedPool, RunError, RunPolicy,
+    };
 }
 
 pub mod prelude {
+    #[allow(ambiguous_glob_reexports)]
     pub use crate::index_mode::*;
     pub use crate::executor::block_on;
+    #[allow(ambiguous_glob_reexports)]
     pub use crate::shared_mode::*;
     pub use crate::*;
 }
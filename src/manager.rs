@@ -1,14 +1,26 @@
 #![allow(dead_code)]
-use std::sync::atomic::{AtomicI8, AtomicU64, Ordering};
-
-use crate::config::{Config, ConfigStatus};
-use crate::debug::is_debug_mode;
+use std::io;
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicI8, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, ConfigStatus, DispatchBackend, FetchStrategy};
+use crate::debug::{is_debug_mode, log, Level};
 use crate::model::{
-    concede_update, reset_lock, spin_update, Backoff, Message, WorkerUpdate, EXPIRE_PERIOD,
+    concede_update, hook_status, reset_lock, spin_update, Backoff, FnBox, Job, JobEnqueued,
+    JobProgress, JobQueues, Message, StartLatch, WorkerPanic, WorkerStarved, WorkerUpdate, EXPIRE_PERIOD,
 };
+use crate::metrics::PoolMetrics;
 use crate::pool::PoolStatus;
-use crate::worker::Worker;
+use crate::watchdog::JobClocks;
+#[cfg(feature = "stack-capture")]
+use crate::stack::{self, StackSlot};
+use crate::worker::{current_worker_id, Worker, WorkerConfig, WorkerShared};
 use crossbeam_channel::Receiver;
+use crossbeam_deque::{Steal, Stealer, Worker as LocalQueue};
+use parking_lot::RwLock;
 use std::sync::Arc;
 
 /// The first id that can be taken by workers. All previous ones are reserved for future use in the
@@ -16,6 +28,10 @@ use std::sync::Arc;
 /// `last_worker_id` shall be greater than this id number.
 const INIT_ID: usize = 15;
 
+/// The time budget handed to the idle task on each run, such that the worker can get back to the
+/// job queues in a timely manner even if the idle task doesn't check the stop signal often.
+const IDLE_TASK_BUDGET: Duration = Duration::from_millis(1);
+
 #[doc(hidden)]
 pub(crate) struct Manager {
     config: Config,
@@ -23,7 +39,13 @@ pub(crate) struct Manager {
     mutating: AtomicI8,
     last_worker_id: usize,
     idle_threshold: IdleThreshold,
-    chan: (Receiver<Message>, Receiver<Message>),
+    idle_task: IdleTaskSlot,
+    roles: FetchRoles,
+    stealers: StealRegistry,
+    siblings: SiblingQueues,
+    clocks: JobClocks,
+    metrics: Arc<PoolMetrics>,
+    chan: JobQueues,
 }
 
 impl Manager {
@@ -31,35 +53,54 @@ impl Manager {
         config: Config,
         range: usize,
         status: PoolStatus,
-        pri_rx: Receiver<Message>,
-        rx: Receiver<Message>,
+        chan: JobQueues,
+        metrics: Arc<PoolMetrics>,
         lazy_built: bool,
-    ) -> Manager {
+    ) -> io::Result<Manager> {
+        let max_idle = config
+            .max_idle()
+            .map_or(4 * EXPIRE_PERIOD, |max_idle| max_idle.as_millis() as u64);
+
         let idle_threshold = IdleThreshold {
-            inner: Arc::new((
-                AtomicU64::new(EXPIRE_PERIOD),
-                AtomicU64::new(4 * EXPIRE_PERIOD),
-            )),
+            inner: Arc::new((AtomicU64::new(EXPIRE_PERIOD), AtomicU64::new(max_idle))),
         };
 
+        let idle_task = IdleTaskSlot::new(config.idle_task_threshold());
+        let roles = FetchRoles::new(config.fetch_strategy());
+        let backend = config.dispatch_backend();
+
         let mut m = Manager {
             config,
             workers: Vec::new(),
             mutating: AtomicI8::new(0),
             last_worker_id: INIT_ID,
             idle_threshold,
-            chan: (pri_rx, rx),
+            idle_task,
+            roles,
+            stealers: StealRegistry::new(backend == DispatchBackend::Deque),
+            siblings: SiblingQueues::default(),
+            clocks: JobClocks::new(),
+            metrics,
+            chan,
         };
 
         if !lazy_built {
-            m.add_workers(range, true, status);
+            if let Err(err) = m.fill_workers(range, status) {
+                // we can live with a smaller pool, but not with an empty one.
+                if m.workers.is_empty() {
+                    return Err(err);
+                }
+            }
 
             if is_debug_mode() {
-                println!("Pool has been initialized with {} pools", m.workers.len());
+                log(
+                    Level::Info,
+                    format_args!("Pool has been initialized with {} pools", m.workers.len()),
+                );
             }
         }
 
-        m
+        Ok(m)
     }
 
     pub(crate) fn remove_all(&mut self, sync_remove: bool) {
@@ -71,15 +112,16 @@ impl Manager {
         // wait for the in-progress process to finish
         self.spin_update(-1);
 
-        // the behaviors
-        let behavior = self.config.worker_behavior();
+        // broadcast the quit signal to all workers before joining any of them, such that the parked
+        // workers wind down together and the wait is bounded by the slowest worker alone
+        self.workers.iter().for_each(Worker::signal_quit);
 
-        // the remove signal should have been sent by now, or this will forever block
+        // the drop behaviors are invoked by the worker itself when it's dropped
         for mut worker in self.workers.drain(..) {
             let id = worker.get_id();
 
             if is_debug_mode() {
-                println!("Sync retiring worker {}", id);
+                log(Level::Info, format_args!("Sync retiring worker {}", id));
             }
 
             // call retire so we will block until all workers have been awakened again, meaning
@@ -87,22 +129,80 @@ impl Manager {
             // is delivered such that workers may be able to quit the infinite-loop and join the
             // thread later.
             if sync_remove {
-                behavior.before_drop(id);
                 worker.retire();
-                behavior.after_drop(id);
             }
         }
 
         // release worker lock
         self.reset_lock();
 
-        // also clear the graveyard
+        // all workers are gone, so start over with the worker ids
         self.last_worker_id = INIT_ID;
     }
 
-    pub(crate) fn add_workers(&mut self, count: usize, privileged: bool, status: PoolStatus) {
+    /// Remove all workers like `remove_all` in sync mode, but give up waiting on the workers that are
+    /// still running after the `deadline`. The stuck workers will be detached, and their ids will be
+    /// returned to the caller.
+    pub(crate) fn remove_all_timeout(&mut self, deadline: Instant) -> Vec<usize> {
+        let mut stuck = Vec::new();
+
+        // nothing to remove now
+        if self.workers.is_empty() {
+            return stuck;
+        }
+
+        // wait for the in-progress process to finish
+        self.spin_update(-1);
+
+        // same as `remove_all`, signal everyone first so the deadline applies to all at once
+        self.workers.iter().for_each(Worker::signal_quit);
+
+        // the drop behaviors are invoked by the worker itself when it's dropped
+        for mut worker in self.workers.drain(..) {
+            let id = worker.get_id();
+
+            if is_debug_mode() {
+                log(Level::Info, format_args!("Retiring worker {} before the deadline", id));
+            }
+
+            if !worker.retire_timeout(deadline) {
+                // report where the worker is stuck at before letting it go
+                #[cfg(feature = "stack-capture")]
+                if is_debug_mode() {
+                    if let Some(frames) = stack::report(worker.stack_slot()) {
+                        log(
+                            Level::Warn,
+                            format_args!(
+                                "Worker {} still running after the close timeout at:\n{}",
+                                id, frames
+                            ),
+                        );
+                    }
+                }
+
+                stuck.push(id);
+            }
+        }
+
+        // release worker lock
+        self.reset_lock();
+
+        // all workers are gone, so start over with the worker ids
+        self.last_worker_id = INIT_ID;
+
+        stuck
+    }
+
+    /// Add `count` new workers to the pool. If the OS refuses to spawn more threads, we will keep the
+    /// workers that have been created so far, and return the spawn error to the caller.
+    pub(crate) fn add_workers(
+        &mut self,
+        count: usize,
+        privileged: bool,
+        status: PoolStatus,
+    ) -> io::Result<()> {
         if count == 0 {
-            return;
+            return Ok(());
         }
 
         if self.last_worker_id > INIT_ID {
@@ -112,40 +212,164 @@ impl Manager {
 
         // if someone is actively adding workers, we skip this op
         if !self.concede_update(1) {
-            return;
+            return Ok(());
         }
 
         // reserve rooms for workers
         self.workers.reserve(count);
 
         // the start id is the next integer from the last worker's id
-        let base_name = self.config.pool_name().cloned();
         let stack_size = self.config.thread_size();
+        let parking = self.config.parking_profile();
+        let start_latch = if self.config.require_sync_start() {
+            Some(StartLatch::new())
+        } else {
+            None
+        };
 
-        (1..=count).for_each(|offset| {
+        let mut added = 0;
+        let mut result = Ok(());
+
+        for offset in 1..=count {
             // Worker is created to subscribe, but would register self later when pulled from the
             // workers queue
             let id = self.last_worker_id + offset;
-            let (rx, pri_rx) = (self.chan.0.clone(), self.chan.1.clone());
 
-            let worker_name = match base_name.as_ref() {
-                Some(name) => Some(format!("{}-{}", name, id)),
-                None => None,
-            };
+            let worker_name = self.config.worker_name(id);
 
-            self.workers.push(Worker::new(
-                worker_name,
-                id,
+            let config = WorkerConfig {
+                name: worker_name,
+                pool_name: self.config.pool_name().cloned(),
                 stack_size,
                 privileged,
-                (pri_rx, rx),
-                (status.clone(), self.idle_threshold.clone()),
+                parking,
+                ordering: self.config.ordering(),
+                on_panic: self.config.worker_behavior().on_panic_clone(),
+                events: self.config.event_sink().cloned(),
+                on_starved: self.config.worker_behavior().on_starved_worker_clone(),
+                start_latch: start_latch.clone(),
+                state_hooks: self.config.worker_state_hooks().clone(),
+                core: self.config.worker_core(id - INIT_ID - 1),
+                #[cfg(feature = "stack-capture")]
+                stack_slot: Arc::new(StackSlot::new()),
+            };
+
+            let worker = Worker::new(
+                id,
+                config,
+                self.chan.clone(),
+                WorkerShared {
+                    status: status.clone(),
+                    idle_threshold: self.idle_threshold.clone(),
+                    idle_task: self.idle_task.clone(),
+                    roles: self.roles.clone(),
+                    stealers: self.stealers.clone(),
+                    siblings: self.siblings.clone(),
+                    clocks: self.clocks.clone(),
+                    metrics: Arc::clone(&self.metrics),
+                },
                 self.config.worker_behavior(),
-            ));
-        });
+            );
+
+            match worker {
+                Ok(w) => {
+                    self.workers.push(w);
+                    added += 1;
+                }
+                Err(err) => {
+                    if is_debug_mode() {
+                        log(
+                            Level::Warn,
+                            format_args!(
+                                "Unable to spawn worker {}, the pool will run with {} workers: {}",
+                                id,
+                                self.workers.len(),
+                                err
+                            ),
+                        );
+                    }
+
+                    // the OS won't give us more threads, stop trying
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        // wait for all spawned workers to start before handing the pool out
+        if let Some(latch) = start_latch {
+            latch.wait(added);
+        }
 
         self.reset_lock();
-        self.last_worker_id += count;
+        self.last_worker_id += added;
+        self.roles.set_workers(&self.workers);
+
+        result
+    }
+
+    /// Add workers until the pool is back to the `target` size. Only the workers up to the
+    /// privileged worker count of the config stay for good, the others will quit once they've been idle for too
+    /// long, like the auto scaled workers.
+    pub(crate) fn fill_workers(&mut self, target: usize, status: PoolStatus) -> io::Result<()> {
+        if self.last_worker_id > INIT_ID {
+            self.worker_cleanup();
+        }
+
+        let kept = self
+            .config
+            .privileged_worker_count()
+            .map_or(target, |count| count.min(target));
+        self.add_workers(kept.saturating_sub(self.workers.len()), true, status.clone())?;
+        self.add_workers(target.saturating_sub(self.workers.len()), false, status)
+    }
+
+    pub(crate) fn set_idle_task(&self, task: Option<Arc<dyn IdleTask>>) {
+        *self.idle_task.inner.task.write() = task;
+    }
+
+    #[cfg(feature = "stack-capture")]
+    pub(crate) fn stack_slot(&self, id: usize) -> Option<Arc<StackSlot>> {
+        self.workers
+            .iter()
+            .find(|worker| worker.get_id() == id)
+            .map(|worker| Arc::clone(worker.stack_slot()))
+    }
+
+    /// Switch the way the workers take jobs. The jobs already in the local deques stay there, and
+    /// will still be run by their owners on the `Channel` backend.
+    pub(crate) fn set_dispatch_backend(&self, backend: DispatchBackend) {
+        self.stealers.set_enabled(backend == DispatchBackend::Deque);
+    }
+
+    pub(crate) fn dispatch_backend(&self) -> DispatchBackend {
+        if self.stealers.is_enabled() {
+            DispatchBackend::Deque
+        } else {
+            DispatchBackend::Channel
+        }
+    }
+
+    /// The receiving ends of the pool's job queues, which the sibling pools can steal jobs from.
+    pub(crate) fn job_queues(&self) -> JobQueues {
+        self.chan.clone()
+    }
+
+    /// Set the job queues of the sibling pools that the idle workers can steal jobs from; an empty
+    /// list turns off the stealing.
+    pub(crate) fn set_sibling_queues(&self, queues: Vec<JobQueues>) {
+        self.siblings.set(queues);
+    }
+
+    /// The clocks of the running jobs, which the watchdog can check for the overdue jobs.
+    pub(crate) fn job_clocks(&self) -> JobClocks {
+        self.clocks.clone()
+    }
+
+    /// Check if the workers have no job waiting in their local queues.
+    /// The local queues of the workers, such that the pool can check on them without the lock.
+    pub(crate) fn local_queues(&self) -> StealRegistry {
+        self.stealers.clone()
     }
 
     pub(crate) fn wake_up(&self) {
@@ -153,8 +377,10 @@ impl Manager {
         self.workers.iter().for_each(|worker| worker.wake_up());
     }
 
-    pub(crate) fn worker_cleanup(&mut self) {
-        let (mut pos, mut end) = (0usize, self.workers.len());
+    /// Join and remove the workers that have quit, and return how many of them are removed.
+    pub(crate) fn worker_cleanup(&mut self) -> usize {
+        let count = self.workers.len();
+        let (mut pos, mut end) = (0usize, count);
         while pos < end {
             let worker: &mut Worker = &mut self.workers[pos];
 
@@ -166,6 +392,39 @@ impl Manager {
                 pos += 1;
             }
         }
+
+        self.roles.set_workers(&self.workers);
+        count - end
+    }
+
+    /// Hand the job to the worker with the given id alone. The job is returned if there's no such
+    /// worker, or the worker has quit or is quitting.
+    pub(crate) fn pin_job(&self, id: usize, job: Job) -> Result<(), Job> {
+        match self.workers.iter().find(|worker| worker.get_id() == id) {
+            Some(worker) => worker.pin_job(job),
+            None => Err(job),
+        }
+    }
+
+    /// Count the workers that are still running, leaving out the ones that have quit or retired
+    /// themselves but not yet been cleaned up.
+    pub(crate) fn live_workers_count(&self) -> usize {
+        self.workers.iter().filter(|worker| worker.is_alive()).count()
+    }
+
+    /// Check if any worker is still running, which is cheaper than counting all of them.
+    pub(crate) fn has_live_workers(&self) -> bool {
+        self.workers.iter().any(Worker::is_alive)
+    }
+
+    /// Get the job queue by its id: 0 for the priority queue, 1 for the normal queue, and 2 for the
+    /// low priority queue.
+    fn queue(&self, id: u8) -> &Receiver<Message> {
+        match id {
+            0 => &self.chan.0,
+            1 => &self.chan.1,
+            _ => &self.chan.2,
+        }
     }
 }
 
@@ -174,12 +433,13 @@ pub(crate) trait WorkerManagement {
     fn workers_count(&self) -> usize;
     fn worker_auto_sleep(&mut self, life_in_ms: usize);
     fn worker_auto_expire(&mut self, life_in_ms: usize);
-    fn extend_by(&mut self, more: usize, status: PoolStatus);
+    fn extend_by(&mut self, more: usize, status: PoolStatus) -> io::Result<()>;
     fn shrink_by(&mut self, less: usize) -> Vec<usize>;
     fn dismiss_worker(&mut self, id: usize) -> Option<usize>;
     fn first_worker_id(&self) -> usize;
     fn last_worker_id(&self) -> usize;
     fn next_worker_id(&self, curr_id: usize) -> usize;
+    fn worker_ids(&self) -> Vec<usize>;
 }
 
 impl WorkerManagement for Manager {
@@ -201,8 +461,9 @@ impl WorkerManagement for Manager {
             .store(life_in_ms as u64, Ordering::SeqCst);
     }
 
-    fn extend_by(&mut self, more: usize, status: PoolStatus) {
-        self.add_workers(more, true, status);
+    fn extend_by(&mut self, more: usize, status: PoolStatus) -> io::Result<()> {
+        let target = self.live_workers_count() + more;
+        self.fill_workers(target, status)
     }
 
     fn shrink_by(&mut self, less: usize) -> Vec<usize> {
@@ -229,6 +490,7 @@ impl WorkerManagement for Manager {
             .collect();
 
         self.reset_lock();
+        self.roles.set_workers(&self.workers);
         workers
     }
 
@@ -245,6 +507,7 @@ impl WorkerManagement for Manager {
                 self.spin_update(-1);
                 let mut retired = self.workers.swap_remove(idx);
                 self.reset_lock();
+                self.roles.set_workers(&self.workers);
 
                 // now update the return value and notify worker to dismiss
                 res.replace(retired.get_id());
@@ -258,33 +521,28 @@ impl WorkerManagement for Manager {
     }
 
     fn first_worker_id(&self) -> usize {
-        match self.workers.first() {
-            Some(worker) => worker.get_id(),
-            None => 0,
-        }
+        self.workers.iter().map(Worker::get_id).min().unwrap_or(0)
     }
 
     fn last_worker_id(&self) -> usize {
-        match self.workers.last() {
-            Some(worker) => worker.get_id(),
-            None => 0,
-        }
+        self.workers.iter().map(Worker::get_id).max().unwrap_or(0)
     }
 
     fn next_worker_id(&self, curr_id: usize) -> usize {
-        let mut found = false;
-        for worker in self.workers.iter() {
-            let id = worker.get_id();
-            if found {
-                return id;
-            }
-
-            if id == curr_id {
-                found = true;
-            }
-        }
+        // workers are swap-removed on dismissal, so search by id instead of the position; the
+        // current worker may be gone already, then continue with the next larger id.
+        self.workers
+            .iter()
+            .map(Worker::get_id)
+            .filter(|&id| id > curr_id)
+            .min()
+            .unwrap_or(0)
+    }
 
-        0
+    fn worker_ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.workers.iter().map(Worker::get_id).collect();
+        ids.sort_unstable();
+        ids
     }
 }
 
@@ -296,13 +554,7 @@ pub(crate) trait JobManagement {
 
 impl JobManagement for Manager {
     fn drop_one(&self, from: u8) -> usize {
-        let chan = if from == 0 {
-            // pop one task from the priority queue
-            &self.chan.0
-        } else {
-            // pop one task from the normal queue
-            &self.chan.1
-        };
+        let chan = self.queue(from);
 
         if chan.is_empty() {
             return 0;
@@ -316,13 +568,7 @@ impl JobManagement for Manager {
     }
 
     fn drop_many(&self, from: u8, target: usize) -> usize {
-        let chan = if from == 0 {
-            // pop one task from the priority queue
-            &self.chan.0
-        } else {
-            // pop one task from the normal queue
-            &self.chan.1
-        };
+        let chan = self.queue(from);
 
         if chan.is_empty() {
             return 0;
@@ -358,12 +604,45 @@ impl Backoff for Manager {
     }
 }
 
+/// The lifecycle events of the pool workers, which are pushed to the event sink set by
+/// `ConfigStatus::set_event_sink` as they happen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkerEvent {
+    /// The worker with the id is up and running, and ready to take jobs.
+    Started(usize),
+
+    /// The worker has quit, because it's been told to, or the pool is closing.
+    Stopped(usize),
+
+    /// The worker has been idle for longer than the `max_idle` period, and has retired itself.
+    Purged(usize),
+
+    /// A job or the idle task has panicked on the worker, which survives the panic and takes new
+    /// jobs.
+    PanicCaught(usize),
+}
+
 #[derive(Clone)]
 pub struct StatusBehaviors {
     before_start: Option<WorkerUpdate>,
     after_start: Option<WorkerUpdate>,
     before_drop: Option<WorkerUpdate>,
     after_drop: Option<WorkerUpdate>,
+    on_panic: Option<WorkerPanic>,
+    on_starved_worker: Option<WorkerStarved>,
+}
+
+impl fmt::Debug for StatusBehaviors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StatusBehaviors")
+            .field("before_start", &hook_status(&self.before_start))
+            .field("after_start", &hook_status(&self.after_start))
+            .field("before_drop", &hook_status(&self.before_drop))
+            .field("after_drop", &hook_status(&self.after_drop))
+            .field("on_panic", &hook_status(&self.on_panic))
+            .field("on_starved_worker", &hook_status(&self.on_starved_worker))
+            .finish()
+    }
 }
 
 impl StatusBehaviors {
@@ -373,6 +652,8 @@ impl StatusBehaviors {
             after_start: None,
             before_drop: None,
             after_drop: None,
+            on_panic: None,
+            on_starved_worker: None,
         }
     }
 }
@@ -382,6 +663,8 @@ pub trait StatusBehaviorSetter {
     fn set_after_start(&mut self, behavior: WorkerUpdate);
     fn set_before_drop(&mut self, behavior: WorkerUpdate);
     fn set_after_drop(&mut self, behavior: WorkerUpdate);
+    fn set_on_panic(&mut self, behavior: WorkerPanic);
+    fn set_on_starved_worker(&mut self, behavior: WorkerStarved);
 }
 
 impl StatusBehaviorSetter for StatusBehaviors {
@@ -400,6 +683,89 @@ impl StatusBehaviorSetter for StatusBehaviors {
     fn set_after_drop(&mut self, behavior: WorkerUpdate) {
         self.after_drop.replace(behavior);
     }
+
+    /// Set the behavior to be invoked when a job panics in the worker. The worker will survive the
+    /// panic and continue to take new jobs.
+    fn set_on_panic(&mut self, behavior: WorkerPanic) {
+        self.on_panic.replace(behavior);
+    }
+
+    /// Set the behavior to be invoked when a worker has come back empty handed from the job queues
+    /// for a few rounds in a row, while the pool still has jobs waiting in the queues. Such a worker
+    /// will then check all queues regardless of its preferences.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// static STARVED_ID: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut behaviors = StatusBehaviors::default();
+    /// behaviors.set_on_starved_worker(|id, _rounds| {
+    ///     STARVED_ID.store(id, Ordering::SeqCst);
+    /// });
+    ///
+    /// let mut config = Config::new();
+    /// config.set_worker_behavior(behaviors);
+    ///
+    /// let mut pool = ThreadPool::new_with_config(16, config);
+    /// let ids = pool.worker_ids();
+    /// let (started_tx, started_rx) = mpsc::channel();
+    ///
+    /// // hold every worker on its own gate
+    /// let gates: Vec<_> = ids
+    ///     .iter()
+    ///     .map(|&id| {
+    ///         let (gate_tx, gate_rx) = mpsc::channel::<()>();
+    ///         let started_tx = started_tx.clone();
+    ///
+    ///         pool.exec_on(id, move || {
+    ///             started_tx.send(()).unwrap();
+    ///             gate_rx.recv().unwrap();
+    ///         })
+    ///         .unwrap();
+    ///
+    ///         gate_tx
+    ///     })
+    ///     .collect();
+    ///
+    /// started_rx.iter().take(ids.len()).for_each(drop);
+    ///
+    /// // dismiss all workers but the first one, each leaves a termination notice in the priority
+    /// // queue once it's done with its job
+    /// let mut gates = ids.iter().zip(gates);
+    /// let (&survivor, survivor_gate) = gates.next().unwrap();
+    ///
+    /// for (&id, gate) in gates {
+    ///     let release = thread::spawn(move || {
+    ///         thread::sleep(Duration::from_millis(10));
+    ///         gate.send(()).unwrap();
+    ///     });
+    ///
+    ///     pool.kill_worker(id);
+    ///     release.join().unwrap();
+    /// }
+    ///
+    /// let (done_tx, done_rx) = mpsc::channel();
+    /// pool.exec_with_priority(move || done_tx.send(()).unwrap(), Priority::Low).unwrap();
+    ///
+    /// // the survivor keeps coming back with the notices only, while the job waits behind them,
+    /// // until it's told to check all queues
+    /// survivor_gate.send(()).unwrap();
+    ///
+    /// assert!(done_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    /// assert_eq!(STARVED_ID.load(Ordering::SeqCst), survivor);
+    /// ```
+    fn set_on_starved_worker(&mut self, behavior: WorkerStarved) {
+        self.on_starved_worker.replace(behavior);
+    }
 }
 
 pub(crate) trait StatusBehaviorDefinitions {
@@ -407,8 +773,11 @@ pub(crate) trait StatusBehaviorDefinitions {
     fn after_start(&self, id: usize);
     fn before_drop(&self, id: usize);
     fn after_drop(&self, id: usize);
+    fn on_panic(&self, worker_id: usize, err: &(dyn Any + Send));
     fn before_drop_clone(&self) -> Option<WorkerUpdate>;
     fn after_drop_clone(&self) -> Option<WorkerUpdate>;
+    fn on_panic_clone(&self) -> Option<WorkerPanic>;
+    fn on_starved_worker_clone(&self) -> Option<WorkerStarved>;
 }
 
 impl StatusBehaviorDefinitions for StatusBehaviors {
@@ -436,6 +805,12 @@ impl StatusBehaviorDefinitions for StatusBehaviors {
         }
     }
 
+    fn on_panic(&self, worker_id: usize, err: &(dyn Any + Send)) {
+        if let Some(behavior) = self.on_panic {
+            behavior(worker_id, err);
+        }
+    }
+
     fn before_drop_clone(&self) -> Option<WorkerUpdate> {
         self.before_drop
     }
@@ -443,6 +818,14 @@ impl StatusBehaviorDefinitions for StatusBehaviors {
     fn after_drop_clone(&self) -> Option<WorkerUpdate> {
         self.after_drop
     }
+
+    fn on_panic_clone(&self) -> Option<WorkerPanic> {
+        self.on_panic
+    }
+
+    fn on_starved_worker_clone(&self) -> Option<WorkerStarved> {
+        self.on_starved_worker
+    }
 }
 
 impl Default for StatusBehaviors {
@@ -451,6 +834,223 @@ impl Default for StatusBehaviors {
     }
 }
 
+/// The hooks to trace each job through the pool, e.g. to feed the job counts and latencies to a
+/// metrics system. The hooks are run by the submitter and the workers without any extra locking,
+/// so they shall be cheap and must not block.
+///
+/// The jobs are only stamped for the hooks if any hook is set. The internal jobs of the pool, e.g.
+/// the ones sent by `drain`, are not reported.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+/// use std::sync::{mpsc, Arc, Mutex};
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// #[derive(Default)]
+/// struct Counters {
+///     enqueued: AtomicUsize,
+///     started: AtomicUsize,
+///     completed: AtomicUsize,
+///     max_wait: AtomicU64,
+/// }
+///
+/// let counters = Arc::new(Counters::default());
+/// let mut behaviors = JobBehaviors::new();
+///
+/// let c = Arc::clone(&counters);
+/// behaviors.set_on_job_enqueued(move |_queue_len| {
+///     c.enqueued.fetch_add(1, Ordering::SeqCst);
+/// });
+///
+/// let c = Arc::clone(&counters);
+/// behaviors.set_on_job_started(move |_worker_id, wait| {
+///     c.started.fetch_add(1, Ordering::SeqCst);
+///     c.max_wait.fetch_max(wait.as_nanos() as u64, Ordering::SeqCst);
+/// });
+///
+/// let c = Arc::clone(&counters);
+/// behaviors.set_on_job_completed(move |_worker_id, _exec| {
+///     c.completed.fetch_add(1, Ordering::SeqCst);
+/// });
+///
+/// let mut config = Config::new();
+/// config.set_job_behavior(behaviors);
+///
+/// let pool = ThreadPool::new_with_config(2, config);
+///
+/// // hold both workers, such that the queued jobs have to wait
+/// let (tx, rx) = mpsc::channel::<()>();
+/// let rx = Arc::new(Mutex::new(rx));
+/// for _ in 0..2 {
+///     let rx = Arc::clone(&rx);
+///     pool.exec(move || { let _ = rx.lock().unwrap().recv(); }, false).unwrap();
+/// }
+///
+/// for _ in 0..8 {
+///     pool.exec(|| {}, false).unwrap();
+/// }
+///
+/// pool.exec_batch((0..10).map(|_| || {})).unwrap();
+/// thread::sleep(Duration::from_millis(10));
+///
+/// drop(tx);
+/// pool.wait_idle();
+///
+/// assert_eq!(counters.enqueued.load(Ordering::SeqCst), 20);
+/// assert_eq!(counters.started.load(Ordering::SeqCst), 20);
+/// assert_eq!(counters.completed.load(Ordering::SeqCst), 20);
+///
+/// let max_wait = Duration::from_nanos(counters.max_wait.load(Ordering::SeqCst));
+/// assert!(max_wait >= Duration::from_millis(10) && max_wait < Duration::from_secs(1));
+/// ```
+#[derive(Clone, Default)]
+pub struct JobBehaviors {
+    on_job_enqueued: Option<JobEnqueued>,
+    on_job_started: Option<JobProgress>,
+    on_job_completed: Option<JobProgress>,
+}
+
+impl fmt::Debug for JobBehaviors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JobBehaviors")
+            .field("on_job_enqueued", &hook_status(&self.on_job_enqueued))
+            .field("on_job_started", &hook_status(&self.on_job_started))
+            .field("on_job_completed", &hook_status(&self.on_job_completed))
+            .finish()
+    }
+}
+
+impl JobBehaviors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the hook to be invoked once a job is queued, with the number of the jobs waiting in the
+    /// queues by then. A batch of jobs is reported once per job.
+    pub fn set_on_job_enqueued<F>(&mut self, behavior: F) -> &mut Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_job_enqueued = Some(Arc::new(behavior));
+        self
+    }
+
+    /// Set the hook to be invoked by the worker right before running a job, with the worker id and
+    /// how long the job has been waiting in the queue.
+    pub fn set_on_job_started<F>(&mut self, behavior: F) -> &mut Self
+    where
+        F: Fn(usize, Duration) + Send + Sync + 'static,
+    {
+        self.on_job_started = Some(Arc::new(behavior));
+        self
+    }
+
+    /// Set the hook to be invoked by the worker right after a job has returned, with the worker id
+    /// and how long the job has run for. The jobs that panic are not reported.
+    pub fn set_on_job_completed<F>(&mut self, behavior: F) -> &mut Self
+    where
+        F: Fn(usize, Duration) + Send + Sync + 'static,
+    {
+        self.on_job_completed = Some(Arc::new(behavior));
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.on_job_enqueued.is_none()
+            && self.on_job_started.is_none()
+            && self.on_job_completed.is_none()
+    }
+
+    /// Stamp the jobs in the message with the time they're queued, such that the workers can report
+    /// them to the hooks. Return the stamped message and the number of the jobs in it.
+    pub(crate) fn instrument(&self, message: Message) -> (Message, usize) {
+        if self.on_job_started.is_none() && self.on_job_completed.is_none() {
+            let count = match &message {
+                Message::SingleJob(_) => 1,
+                Message::ChainedJobs(jobs) => jobs.len(),
+                Message::Terminate(_) => 0,
+            };
+
+            return (message, count);
+        }
+
+        match message {
+            Message::SingleJob(job) => (Message::SingleJob(self.stamp(job)), 1),
+            Message::ChainedJobs(jobs) => {
+                let count = jobs.len();
+                let jobs = jobs.into_iter().map(|job| self.stamp(job)).collect();
+                (Message::ChainedJobs(jobs), count)
+            }
+            message => (message, 0),
+        }
+    }
+
+    /// Report the jobs that have just been queued.
+    pub(crate) fn enqueued(&self, count: usize, queue_len: usize) {
+        if let Some(behavior) = self.on_job_enqueued.as_ref() {
+            for _ in 0..count {
+                behavior(queue_len);
+            }
+        }
+    }
+
+    fn stamp(&self, job: Job) -> Job {
+        Box::new(StampedJob {
+            job: Some(job),
+            queued: Instant::now(),
+            on_started: self.on_job_started.clone(),
+            on_completed: self.on_job_completed.clone(),
+        })
+    }
+}
+
+/// The job stamped with the time it's queued, which reports to the job hooks when it's run.
+struct StampedJob {
+    job: Option<Job>,
+    queued: Instant,
+    on_started: Option<JobProgress>,
+    on_completed: Option<JobProgress>,
+}
+
+impl FnBox for StampedJob {
+    fn call_box(self: Box<Self>) {
+        let StampedJob {
+            job,
+            queued,
+            on_started,
+            on_completed,
+        } = *self;
+
+        let job = match job {
+            Some(job) => job,
+            None => return,
+        };
+
+        let id = current_worker_id();
+        let start = Instant::now();
+
+        if let Some(behavior) = on_started {
+            behavior(id, start - queued);
+        }
+
+        job.call_box();
+
+        if let Some(behavior) = on_completed {
+            behavior(id, start.elapsed());
+        }
+    }
+
+    fn take_unstamped(&mut self) -> Option<Job> {
+        self.job.take()
+    }
+}
+
 // Wrapper
 pub(crate) struct IdleThreshold {
     inner: Arc<(AtomicU64, AtomicU64)>,
@@ -461,34 +1061,15 @@ impl IdleThreshold {
         let hibernate: u64 = self.inner.0.load(Ordering::Acquire);
         let retire: u64 = self.inner.1.load(Ordering::Acquire);
 
-        match (hibernate, retire) {
-            (h, r) if h > 0 && r > 0 => {
-                if period < h {
-                    // ok
-                    0
-                } else if period < r {
-                    // hibernate
-                    1
-                } else {
-                    // retire
-                    2
-                }
-            }
-            (h, r) if h > 0 && r == 0 => {
-                if period < h {
-                    0
-                } else {
-                    1
-                }
-            }
-            (h, r) if h == 0 && r > 0 => {
-                if period < r {
-                    0
-                } else {
-                    2
-                }
-            }
-            _ => 0,
+        if retire > 0 && period >= retire {
+            // retire, even if the worker shall have hibernated by now
+            2
+        } else if hibernate > 0 && period >= hibernate {
+            // hibernate
+            1
+        } else {
+            // ok
+            0
         }
     }
 }
@@ -503,3 +1084,278 @@ impl Clone for IdleThreshold {
 
 unsafe impl Send for IdleThreshold {}
 unsafe impl Sync for IdleThreshold {}
+
+/// The queue preferences of the workers, worked out from the fetch strategy and the slots of the
+/// workers, which are kept up to date by the manager.
+#[derive(Clone)]
+pub(crate) struct FetchRoles {
+    strategy: FetchStrategy,
+    /// The ids of the workers in order, where the index of an id is the slot of that worker. The
+    /// ids have gaps once the workers are killed or retired, so they can't be the slots themselves.
+    slots: Arc<RwLock<Vec<usize>>>,
+}
+
+impl FetchRoles {
+    pub(crate) fn new(strategy: FetchStrategy) -> Self {
+        FetchRoles {
+            strategy,
+            slots: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    pub(crate) fn set_workers(&self, workers: &[Worker]) {
+        // only the balanced strategy tells the workers apart
+        if let FetchStrategy::Balanced { .. } = self.strategy {
+            let mut ids: Vec<usize> = workers.iter().map(Worker::get_id).collect();
+            ids.sort_unstable();
+            *self.slots.write() = ids;
+        }
+    }
+
+    /// Tell if the worker shall wait longer on the priority queue, or on the normal queue. The
+    /// worker floats between both queues if neither is set.
+    pub(crate) fn role(&self, id: usize) -> (bool, bool) {
+        match self.strategy {
+            FetchStrategy::Balanced { pri_ratio, .. } => {
+                let slots = self.slots.read();
+                let favored = (slots.len() as f32 * pri_ratio.clamp(0.0, 1.0)) as usize;
+
+                // the worker that's not on the books yet floats between both queues for now
+                let slot = match slots.binary_search(&id) {
+                    Ok(slot) => slot,
+                    Err(_) => return (false, false),
+                };
+
+                (slot < favored, slot >= favored && slot < 2 * favored)
+            }
+            FetchStrategy::PriorityFirst { .. } => (true, false),
+            FetchStrategy::RoundRobin => (false, false),
+        }
+    }
+
+    /// The max number of priority jobs to take in a row while the normal jobs are waiting.
+    pub(crate) fn pri_cap(&self) -> u8 {
+        match self.strategy {
+            FetchStrategy::Balanced { pri_cap, .. } | FetchStrategy::PriorityFirst { pri_cap } => {
+                pri_cap.clamp(1, 254)
+            }
+            FetchStrategy::RoundRobin => 1,
+        }
+    }
+}
+
+/// The background task that an idle pool can donate its cycles to, e.g. compaction or cache warming.
+/// The task will be invoked from an idle worker thread, and at most one worker will be running the
+/// task at any given time.
+pub trait IdleTask: Send + Sync {
+    /// Run some of the background work within the `budget`. The implementation shall check the
+    /// `should_stop` closure often, and return as soon as it yields `true`, which means new jobs have
+    /// arrived, or the pool is shutting down.
+    fn run_some(&self, budget: Duration, should_stop: &dyn Fn() -> bool);
+}
+
+// Wrapper
+pub(crate) struct IdleTaskSlot {
+    inner: Arc<IdleTaskInner>,
+}
+
+struct IdleTaskInner {
+    task: RwLock<Option<Arc<dyn IdleTask>>>,
+    running: AtomicBool,
+    threshold: Duration,
+}
+
+impl IdleTaskSlot {
+    fn new(threshold: Duration) -> Self {
+        IdleTaskSlot {
+            inner: Arc::new(IdleTaskInner {
+                task: RwLock::new(None),
+                running: AtomicBool::new(false),
+                threshold,
+            }),
+        }
+    }
+
+    /// Run the idle task if the worker has been idle longer than the threshold and no one else is
+    /// running the task. Return `Ok(true)` if the task has been invoked, or the panic payload if the
+    /// task has panicked, which is caught such that the worker can get back to the job queues.
+    pub(crate) fn donate(
+        &self,
+        idle: Duration,
+        should_stop: &dyn Fn() -> bool,
+    ) -> Result<bool, Box<dyn Any + Send>> {
+        if idle < self.inner.threshold {
+            return Ok(false);
+        }
+
+        let task = match self.inner.task.read().as_ref() {
+            Some(task) => Arc::clone(task),
+            None => return Ok(false),
+        };
+
+        // someone else is doing the work
+        if self
+            .inner
+            .running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return Ok(false);
+        }
+
+        // hand the slot back even if the task panics, or no worker would ever run it again
+        let _guard = DonationGuard(&self.inner.running);
+
+        if !should_stop() {
+            panic::catch_unwind(AssertUnwindSafe(|| task.run_some(IDLE_TASK_BUDGET, should_stop)))?;
+        }
+
+        Ok(true)
+    }
+}
+
+/// Release the idle task slot when it's dropped, whether the task has returned or panicked.
+struct DonationGuard<'a>(&'a AtomicBool);
+
+impl<'a> Drop for DonationGuard<'a> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl Clone for IdleTaskSlot {
+    fn clone(&self) -> Self {
+        IdleTaskSlot {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+/// The stealers of the workers' local job queues, such that idle workers can take jobs from their
+/// busy siblings before going to park.
+pub(crate) struct StealRegistry {
+    inner: Arc<RwLock<Vec<IdStealer>>>,
+    enabled: Arc<AtomicBool>,
+}
+
+/// The worker id and the stealer of its local queue.
+type IdStealer = (usize, Stealer<Job>);
+
+/// The worker's stealer stays in the registry until this registration is dropped.
+pub(crate) struct StealRegistration {
+    registry: StealRegistry,
+    id: usize,
+}
+
+impl StealRegistry {
+    fn new(enabled: bool) -> Self {
+        StealRegistry {
+            inner: Arc::new(RwLock::new(Vec::new())),
+            enabled: Arc::new(AtomicBool::new(enabled)),
+        }
+    }
+
+    /// Check if the workers shall use their local deques, i.e. on the `Deque` backend.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Release);
+    }
+
+    pub(crate) fn register(&self, id: usize, stealer: Stealer<Job>) -> StealRegistration {
+        self.inner.write().push((id, stealer));
+
+        StealRegistration {
+            registry: self.clone(),
+            id,
+        }
+    }
+
+    /// Steal a batch of jobs from a sibling into the `local` queue, starting from a random sibling
+    /// decided by the `seed`, and return one of the stolen jobs.
+    pub(crate) fn steal_into(&self, my_id: usize, seed: usize, local: &LocalQueue<Job>) -> Option<Job> {
+        let stealers = self.inner.read();
+        let count = stealers.len();
+
+        if count < 2 {
+            return None;
+        }
+
+        let start = seed % count;
+        for offset in 0..count {
+            let (id, stealer) = &stealers[(start + offset) % count];
+            if *id == my_id {
+                continue;
+            }
+
+            loop {
+                match stealer.steal_batch_and_pop(local) {
+                    Steal::Success(job) => return Some(job),
+                    Steal::Empty => break,
+                    Steal::Retry => {}
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Check if all local queues are empty, i.e. no job taken from the shared queues is waiting.
+    pub(crate) fn all_empty(&self) -> bool {
+        self.inner.read().iter().all(|(_, stealer)| stealer.is_empty())
+    }
+}
+
+impl Clone for StealRegistry {
+    fn clone(&self) -> Self {
+        StealRegistry {
+            inner: Arc::clone(&self.inner),
+            enabled: Arc::clone(&self.enabled),
+        }
+    }
+}
+
+impl Drop for StealRegistration {
+    fn drop(&mut self) {
+        self.registry.inner.write().retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// The job queues of the sibling pools in the `index_mode`, such that the idle workers can take
+/// jobs from the swamped siblings.
+#[derive(Clone, Default)]
+pub(crate) struct SiblingQueues {
+    inner: Arc<RwLock<Vec<JobQueues>>>,
+}
+
+impl SiblingQueues {
+    fn set(&self, queues: Vec<JobQueues>) {
+        *self.inner.write() = queues;
+    }
+
+    /// Take a job from the sibling pools, starting from a random sibling decided by the `seed`. The
+    /// priority jobs are taken first, and the low priority jobs are left to their own pool.
+    pub(crate) fn steal(&self, seed: usize) -> Option<Job> {
+        let siblings = self.inner.read();
+        let count = siblings.len();
+
+        if count == 0 {
+            return None;
+        }
+
+        let start = seed % count;
+        for offset in 0..count {
+            let (pri_chan, norm_chan, _) = &siblings[(start + offset) % count];
+
+            // a termination message carries no job, and the sibling's workers would drop it too
+            let message = pri_chan.try_recv().or_else(|_| norm_chan.try_recv());
+            if let Some(job) = message.ok().and_then(Message::into_job) {
+                return Some(job);
+            }
+        }
+
+        None
+    }
+}
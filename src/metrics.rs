@@ -0,0 +1,505 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "persist-metrics")]
+pub(crate) use self::persist::MetricsFlusher;
+
+/// The number of buckets in the job latency histogram. The bucket `i` counts the jobs that took less
+/// than `2^i` microseconds to run (and no less than `2^(i-1)` microseconds), except for the last
+/// bucket, which counts all jobs slower than that.
+pub const LATENCY_BUCKETS: usize = 24;
+
+/// The live statistics of the pool, i.e. how busy the pool is at the moment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The number of workers in the pool.
+    pub worker_count: usize,
+
+    /// The number of workers that are running a job.
+    pub active_workers: usize,
+
+    /// The number of jobs waiting in the normal queue.
+    pub queued_normal: usize,
+
+    /// The number of jobs waiting in the priority queue.
+    pub queued_priority: usize,
+
+    /// The number of jobs waiting in the low priority queue.
+    pub queued_low: usize,
+
+    /// The number of workers that are still running, i.e. the `worker_count` less the ones that
+    /// have quit after being idle for too long.
+    pub workers_alive: usize,
+
+    /// The number of jobs waiting in all job queues.
+    pub jobs_pending: usize,
+
+    /// The number of jobs that this pool has run to completion.
+    pub jobs_completed: u64,
+
+    /// How long the workers have been idle for on average, in milliseconds, where the workers
+    /// running a job count as not idle.
+    pub avg_idle_ms: u64,
+}
+
+impl PoolStats {
+    /// The share of the workers that are not running a job, from 0.0 for a fully busy pool to 1.0
+    /// for an idle one. A pool without workers counts as idle.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    ///
+    /// let stats = PoolStats { worker_count: 4, active_workers: 1, ..PoolStats::default() };
+    /// assert_eq!(stats.idle_ratio(), 0.75);
+    /// assert_eq!(PoolStats::default().idle_ratio(), 1.0);
+    /// ```
+    pub fn idle_ratio(&self) -> f64 {
+        if self.worker_count == 0 {
+            return 1.0;
+        }
+
+        let idle = self.worker_count.saturating_sub(self.active_workers);
+        idle as f64 / self.worker_count as f64
+    }
+}
+
+/// A point-in-time copy of the pool metrics. The `since_start_*` counters only cover the jobs run by
+/// this pool instance, while the `lifetime_*` ones also include the totals persisted by the previous
+/// runs, if the metrics persistence is enabled. Otherwise the two sets of counters are the same.
+#[derive(Clone, Debug, Default)]
+pub struct PoolMetricsSnapshot {
+    pub since_start_jobs_completed: u64,
+    pub since_start_jobs_timed_out: u64,
+    pub since_start_latency_histogram: [u64; LATENCY_BUCKETS],
+    /// The time the workers have spent waiting between the jobs, summed over all workers.
+    pub since_start_idle_time: Duration,
+    /// The most jobs that have been waiting in the job queues at once.
+    pub since_start_peak_queue_depth: usize,
+    pub lifetime_jobs_completed: u64,
+    pub lifetime_latency_histogram: [u64; LATENCY_BUCKETS],
+}
+
+impl PoolMetricsSnapshot {
+    /// The upper bound of the job latency in the histogram bucket, or `None` for the last bucket,
+    /// which has no upper bound.
+    pub fn bucket_upper_bound(index: usize) -> Option<Duration> {
+        if index + 1 >= LATENCY_BUCKETS {
+            return None;
+        }
+
+        Some(Duration::from_micros(1 << index))
+    }
+}
+
+/// The counters shared by all workers of the pool, updated with relaxed atomics to keep the job
+/// execution path cheap.
+#[doc(hidden)]
+#[derive(Default)]
+pub(crate) struct PoolMetrics {
+    active_workers: AtomicUsize,
+    taking_workers: AtomicUsize,
+    retired_workers: AtomicUsize,
+    jobs_completed: AtomicU64,
+    jobs_timed_out: AtomicU64,
+    idle_micros: AtomicU64,
+    peak_queue_depth: AtomicUsize,
+    latency_histogram: [AtomicU64; LATENCY_BUCKETS],
+    prior_jobs_completed: u64,
+    prior_latency_histogram: [u64; LATENCY_BUCKETS],
+    /// Signaled whenever the pool may have become idle.
+    idle_signal: (Mutex<()>, Condvar),
+    /// The number of callers waiting on the idle signal.
+    idle_waiters: AtomicUsize,
+}
+
+impl PoolMetrics {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the metrics on top of the totals from the previous runs.
+    #[cfg(feature = "persist-metrics")]
+    pub(crate) fn with_prior(jobs_completed: u64, latency_histogram: [u64; LATENCY_BUCKETS]) -> Self {
+        PoolMetrics {
+            prior_jobs_completed: jobs_completed,
+            prior_latency_histogram: latency_histogram,
+            ..Self::default()
+        }
+    }
+
+    /// Mark a worker as busy running a job.
+    pub(crate) fn job_started(&self) {
+        self.active_workers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Mark a worker as done with its job, regardless of the job outcome.
+    pub(crate) fn job_finished(&self) {
+        if self.active_workers.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.notify_idle();
+        }
+    }
+
+    /// Mark a worker as taking a job off the queues. The worker counts as busy from right before
+    /// the dequeue until the job is started, such that the pool is never seen idle while the job
+    /// is in the worker's hands.
+    pub(crate) fn take_started(&self) {
+        self.taking_workers.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Mark a worker as done taking a job, either it has started the job or found none.
+    pub(crate) fn take_finished(&self) {
+        self.taking_workers.fetch_sub(1, Ordering::SeqCst);
+
+        // the waiters may be checking on the local queues too, which only shrink on the takes
+        if self.idle_waiters.load(Ordering::SeqCst) > 0 {
+            self.notify_idle();
+        }
+    }
+
+    /// Wake up the callers waiting for the pool to be idle to check again, e.g. when the last busy
+    /// worker is done, or when a worker has run out of work after taking the control messages off
+    /// the job queues.
+    pub(crate) fn notify_idle(&self) {
+        // take the lock such that the waiter can't miss the signal between its check and wait
+        let (lock, cvar) = &self.idle_signal;
+        drop(lock.lock().unwrap_or_else(|err| err.into_inner()));
+        cvar.notify_all();
+    }
+
+    /// Block until `is_idle` holds, which is checked again each time no worker is busy any more, or
+    /// until the `deadline` has passed, if any. Return the last result of `is_idle`.
+    pub(crate) fn wait_idle_until<F>(&self, deadline: Option<Instant>, is_idle: F) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        // announce the waiter before the first check, such that the takes done after the check
+        // will signal us
+        self.idle_waiters.fetch_add(1, Ordering::SeqCst);
+
+        let (lock, cvar) = &self.idle_signal;
+        let mut guard = lock.lock().unwrap_or_else(|err| err.into_inner());
+
+        let idle = loop {
+            if is_idle() {
+                break true;
+            }
+
+            let deadline = match deadline {
+                Some(deadline) => deadline,
+                None => {
+                    guard = cvar.wait(guard).unwrap_or_else(|err| err.into_inner());
+                    continue;
+                }
+            };
+
+            let now = Instant::now();
+            if now >= deadline {
+                break false;
+            }
+
+            guard = cvar
+                .wait_timeout(guard, deadline - now)
+                .map(|(guard, _)| guard)
+                .unwrap_or_else(|err| err.into_inner().0);
+        };
+
+        self.idle_waiters.fetch_sub(1, Ordering::SeqCst);
+        idle
+    }
+
+    pub(crate) fn jobs_completed(&self) -> u64 {
+        self.jobs_completed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn active_workers(&self) -> usize {
+        self.active_workers.load(Ordering::Relaxed)
+    }
+
+    /// The number of workers holding a job taken off the queues that's not yet started.
+    pub(crate) fn taking_workers(&self) -> usize {
+        self.taking_workers.load(Ordering::SeqCst)
+    }
+
+    /// Mark a worker as quit after being idle for too long, such that the pool can bring one back.
+    pub(crate) fn worker_retired(&self) {
+        self.retired_workers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Take one of the retired workers to be replaced, return `false` if there's none.
+    pub(crate) fn take_retired(&self) -> bool {
+        self.retired_workers.load(Ordering::Relaxed) > 0
+            && self
+                .retired_workers
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok()
+    }
+
+    /// Record a completed job and how long it took to run.
+    pub(crate) fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros().min(u128::from(u64::MAX)) as u64;
+        let bucket = ((64 - micros.leading_zeros()) as usize).min(LATENCY_BUCKETS - 1);
+
+        self.jobs_completed.fetch_add(1, Ordering::Relaxed);
+        self.latency_histogram[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record how long a worker has waited before taking its next job.
+    pub(crate) fn record_idle(&self, idle: Duration) {
+        let micros = idle.as_micros().min(u128::from(u64::MAX)) as u64;
+        self.idle_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Record the depth of the job queues after a submission, keeping the peak.
+    pub(crate) fn record_queue_depth(&self, depth: usize) {
+        if depth > self.peak_queue_depth.load(Ordering::Relaxed) {
+            self.peak_queue_depth.fetch_max(depth, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a job that has been running for longer than the job timeout.
+    pub(crate) fn job_timed_out(&self) {
+        self.jobs_timed_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> PoolMetricsSnapshot {
+        let mut snapshot = PoolMetricsSnapshot {
+            since_start_jobs_completed: self.jobs_completed.load(Ordering::Relaxed),
+            since_start_jobs_timed_out: self.jobs_timed_out.load(Ordering::Relaxed),
+            since_start_idle_time: Duration::from_micros(self.idle_micros.load(Ordering::Relaxed)),
+            since_start_peak_queue_depth: self.peak_queue_depth.load(Ordering::Relaxed),
+            lifetime_jobs_completed: self.prior_jobs_completed,
+            lifetime_latency_histogram: self.prior_latency_histogram,
+            ..PoolMetricsSnapshot::default()
+        };
+
+        snapshot.lifetime_jobs_completed += snapshot.since_start_jobs_completed;
+
+        for (i, count) in self.latency_histogram.iter().enumerate() {
+            let count = count.load(Ordering::Relaxed);
+            snapshot.since_start_latency_histogram[i] = count;
+            snapshot.lifetime_latency_histogram[i] += count;
+        }
+
+        snapshot
+    }
+}
+
+#[cfg(feature = "persist-metrics")]
+mod persist {
+    use std::fs;
+    use std::io::{self, ErrorKind};
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    use super::{PoolMetrics, LATENCY_BUCKETS};
+    use crate::config::BgThreadConfig;
+    use crate::debug::{log, Level};
+
+    /// The file layout: magic, version, payload length, payload, and the checksum of the payload.
+    /// All numbers are in little endian.
+    const MAGIC: &[u8; 4] = b"TPMS";
+    const VERSION: u32 = 1;
+    const PAYLOAD_LEN: usize = 8 + 4 + 8 * LATENCY_BUCKETS;
+    const FILE_LEN: usize = 4 + 4 + 4 + PAYLOAD_LEN + 8;
+
+    /// Make sure concurrent flushes in the same process won't share the temp file.
+    static TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+
+    /// The maintenance thread that periodically writes the lifetime totals of the pool metrics to
+    /// the disk, and one last time when the pool is closed.
+    pub(crate) struct MetricsFlusher {
+        path: PathBuf,
+        metrics: Arc<PoolMetrics>,
+        stop: Arc<(Mutex<bool>, Condvar)>,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl MetricsFlusher {
+        /// Load the totals persisted by the previous runs. Corrupt or version-mismatched files are
+        /// ignored with a warning, and the metrics will start from scratch.
+        pub(crate) fn load(path: &Path) -> PoolMetrics {
+            match read(path) {
+                Ok((jobs, histogram)) => PoolMetrics::with_prior(jobs, histogram),
+                Err(ref err) if err.kind() == ErrorKind::NotFound => PoolMetrics::new(),
+                Err(err) => {
+                    log(
+                        Level::Error,
+                        format_args!(
+                            "Ignoring the persisted pool metrics at {}: {}",
+                            path.display(),
+                            err
+                        ),
+                    );
+
+                    PoolMetrics::new()
+                }
+            }
+        }
+
+        pub(crate) fn start(
+            path: PathBuf,
+            flush_interval: Duration,
+            metrics: Arc<PoolMetrics>,
+            bg_config: &BgThreadConfig,
+        ) -> io::Result<MetricsFlusher> {
+            let stop = Arc::new((Mutex::new(false), Condvar::new()));
+            let (thread_path, thread_metrics, thread_stop) =
+                (path.clone(), Arc::clone(&metrics), Arc::clone(&stop));
+
+            let handle = bg_config.spawn("pool-metrics", move || {
+                let (lock, cvar) = &*thread_stop;
+                let mut stopped = lock.lock().unwrap_or_else(|err| err.into_inner());
+
+                loop {
+                    stopped = cvar
+                        .wait_timeout(stopped, flush_interval)
+                        .map(|(guard, _)| guard)
+                        .unwrap_or_else(|err| err.into_inner().0);
+
+                    if *stopped {
+                        return;
+                    }
+
+                    flush(&thread_path, &thread_metrics);
+                }
+            })?;
+
+            Ok(MetricsFlusher {
+                path,
+                metrics,
+                stop,
+                handle: Some(handle),
+            })
+        }
+
+        /// Stop the maintenance thread and write the final totals.
+        pub(crate) fn stop(&mut self) {
+            if let Some(handle) = self.handle.take() {
+                let (lock, cvar) = &*self.stop;
+                *lock.lock().unwrap_or_else(|err| err.into_inner()) = true;
+                cvar.notify_all();
+
+                handle.join().unwrap_or_else(|err| {
+                    log(Level::Error, format_args!("Unable to join the metrics thread: {:?}", err));
+                });
+
+                flush(&self.path, &self.metrics);
+            }
+        }
+    }
+
+    impl Drop for MetricsFlusher {
+        fn drop(&mut self) {
+            self.stop();
+        }
+    }
+
+    fn flush(path: &Path, metrics: &PoolMetrics) {
+        let snapshot = metrics.snapshot();
+
+        if let Err(err) = write(
+            path,
+            snapshot.lifetime_jobs_completed,
+            &snapshot.lifetime_latency_histogram,
+        ) {
+            log(
+                Level::Error,
+                format_args!("Unable to persist the pool metrics to {}: {}", path.display(), err),
+            );
+        }
+    }
+
+    /// Write to a temp file first and then rename it over the target, such that readers will never
+    /// see a torn file.
+    fn write(path: &Path, jobs: u64, histogram: &[u64; LATENCY_BUCKETS]) -> io::Result<()> {
+        let mut payload = Vec::with_capacity(PAYLOAD_LEN);
+        payload.extend_from_slice(&jobs.to_le_bytes());
+        payload.extend_from_slice(&(LATENCY_BUCKETS as u32).to_le_bytes());
+        for count in histogram.iter() {
+            payload.extend_from_slice(&count.to_le_bytes());
+        }
+
+        let mut buf = Vec::with_capacity(FILE_LEN);
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&payload);
+        buf.extend_from_slice(&checksum(&payload).to_le_bytes());
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(format!(
+            ".{}.{}.tmp",
+            process::id(),
+            TEMP_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let temp = PathBuf::from(temp);
+        if let Err(err) = fs::write(&temp, &buf).and_then(|_| fs::rename(&temp, path)) {
+            let _ = fs::remove_file(&temp);
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    fn read(path: &Path) -> io::Result<(u64, [u64; LATENCY_BUCKETS])> {
+        let buf = fs::read(path)?;
+        let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_string());
+
+        if buf.len() != FILE_LEN || &buf[0..4] != MAGIC {
+            return Err(invalid("not a pool metrics file"));
+        }
+
+        if read_u32(&buf[4..8]) != VERSION {
+            return Err(invalid("unsupported pool metrics version"));
+        }
+
+        if read_u32(&buf[8..12]) as usize != PAYLOAD_LEN {
+            return Err(invalid("unexpected payload length"));
+        }
+
+        let payload = &buf[12..12 + PAYLOAD_LEN];
+        if read_u64(&buf[12 + PAYLOAD_LEN..]) != checksum(payload) {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        if read_u32(&payload[8..12]) as usize != LATENCY_BUCKETS {
+            return Err(invalid("unexpected histogram size"));
+        }
+
+        let mut histogram = [0u64; LATENCY_BUCKETS];
+        for (i, count) in histogram.iter_mut().enumerate() {
+            *count = read_u64(&payload[12 + 8 * i..]);
+        }
+
+        Ok((read_u64(&payload[0..8]), histogram))
+    }
+
+    fn read_u32(buf: &[u8]) -> u32 {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&buf[..4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn read_u64(buf: &[u8]) -> u64 {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&buf[..8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    /// The 64-bit FNV-1a hash of the payload.
+    fn checksum(payload: &[u8]) -> u64 {
+        payload.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
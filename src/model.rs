@@ -1,16 +1,23 @@
 #![allow(unused)]
 
-use crossbeam_channel::Sender;
+use crossbeam_channel::{Receiver, Sender};
+use parking_lot::{Condvar, Mutex};
+use std::any::Any;
+use std::fmt;
+use std::io;
 use std::future::Future;
-use std::io::ErrorKind;
-use std::ops::{Deref, DerefMut};
+use std::hint;
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
 use std::sync::{
-    atomic::{self, AtomicI8, Ordering},
+    atomic::{AtomicBool, AtomicI8, Ordering},
     Arc,
 };
-use std::thread::{self, Thread};
-use std::time::Duration;
+use std::thread::{self, JoinHandle, Thread};
+use std::time::{Duration, Instant};
+
+use crate::config::BgThreadConfig;
+use crate::debug::{log, Level};
 
 // Constant flags
 pub(crate) const FLAG_NORMAL: u8 = 0;
@@ -20,10 +27,10 @@ pub(crate) const FLAG_HIBERNATING: u8 = 1 << 2;
 pub(crate) const FLAG_LAZY_INIT: u8 = 1 << 3;
 pub(crate) const FLAG_REST: u8 = 1 << 4;
 pub(crate) const FLAG_SLEEP_WORKERS: u8 = 1 << 5;
-pub(crate) const EXPIRE_PERIOD: u64 = 128;
+/// How long an extended worker can be idle before it hibernates, in milliseconds.
+pub(crate) const EXPIRE_PERIOD: u64 = 128_000;
 
 const BACKOFF_RETRY_LIMIT: usize = 16;
-const ERR_MSG: &str = "Undefined behavior: the pool has been invoked without being initialized ...";
 
 // Enum ...
 pub(crate) enum Message {
@@ -32,10 +39,67 @@ pub(crate) enum Message {
     Terminate(Vec<usize>),
 }
 
+impl Message {
+    /// Take the job out of the message, a batch of jobs will be run one after another as one job.
+    /// A panic in the batch won't stop the rest of the batch, and the first panic will be resumed
+    /// once the batch is done, such that the worker can report it.
+    pub(crate) fn into_job(self) -> Option<Job> {
+        match self {
+            Message::SingleJob(job) => Some(job),
+            Message::ChainedJobs(jobs) => Some(Box::new(move || {
+                let mut first_panic = None;
+
+                for job in jobs {
+                    if let Err(err) = panic::catch_unwind(AssertUnwindSafe(|| job.call_box())) {
+                        first_panic.get_or_insert(err);
+                    }
+                }
+
+                if let Some(err) = first_panic {
+                    panic::resume_unwind(err);
+                }
+            })),
+            Message::Terminate(_) => None,
+        }
+    }
+
+    /// Strip the jobs in the message of the time stamps they're given when queued, such that they
+    /// can be handed back to the caller as they were submitted.
+    pub(crate) fn unstamped(self) -> Message {
+        let unstamp = |mut job: Job| job.take_unstamped().unwrap_or(job);
+
+        match self {
+            Message::SingleJob(job) => Message::SingleJob(unstamp(job)),
+            Message::ChainedJobs(jobs) => {
+                Message::ChainedJobs(jobs.into_iter().map(unstamp).collect())
+            }
+            message => message,
+        }
+    }
+}
+
 // Base types
 pub(crate) type Job = Box<dyn FnBox + Send + 'static>;
 pub(crate) type WorkerUpdate = fn(id: usize);
 
+/// The receiving ends of the job queues, ordered by priority: high, normal and low.
+pub(crate) type JobQueues = (Receiver<Message>, Receiver<Message>, Receiver<Message>);
+pub(crate) type WorkerPanic = fn(id: usize, err: &(dyn Any + Send));
+pub(crate) type WorkerStarved = fn(id: usize, empty_rounds: usize);
+
+/// The callback to take the last error of a retried job that has run out of attempts.
+pub(crate) type RetryFailure = Arc<dyn Fn(Box<dyn Any + Send>) + Send + Sync>;
+
+/// The callback to report the worker that has been running a job for longer than the job timeout.
+pub(crate) type JobTimeout = Arc<dyn Fn(usize, Duration) + Send + Sync>;
+
+/// The callback to report a job being queued, with the number of jobs waiting in the queues.
+pub(crate) type JobEnqueued = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// The callback to report a job being started or completed by the worker, with how long the job
+/// has been waiting or running for.
+pub(crate) type JobProgress = Arc<dyn Fn(usize, Duration) + Send + Sync>;
+
 // Traits
 pub(crate) trait Backoff {
     fn spin_update(&self, new: i8);
@@ -45,6 +109,11 @@ pub(crate) trait Backoff {
 
 pub(crate) trait FnBox {
     fn call_box(self: Box<Self>);
+
+    /// Take the job out of the wrapper that reports it to the job hooks, if it's such a wrapper.
+    fn take_unstamped(&mut self) -> Option<Job> {
+        None
+    }
 }
 
 pub(crate) trait FnResBox<R> {
@@ -64,53 +133,90 @@ impl<R: Send, F: FnOnce() -> R> FnResBox<R> for F {
     }
 }
 
-/// The inner storage wrapper struct
-pub(crate) struct StaticStore<T>(Option<T>);
-
-/// The struct that will hold the actual pool. The implementation is sound because all usage is internal
-/// and we're guaranteed that before each call, the real values are actually set ahead.
-impl<T> StaticStore<T> {
-    pub(crate) const fn init() -> Self {
-        StaticStore(None)
+/// Describe the optional hook in the `Debug` output, since the functions and closures can't be
+/// printed in any useful way.
+pub(crate) fn hook_status<T>(hook: &Option<T>) -> fmt::Arguments<'static> {
+    if hook.is_some() {
+        format_args!("set")
+    } else {
+        format_args!("none")
     }
+}
 
-    pub(crate) fn as_mut(&mut self) -> Result<&mut T, ErrorKind> {
-        self.0.as_mut().ok_or(ErrorKind::NotFound)
-    }
+/// The latch for the newly spawned workers to report they're up and running, such that the spawner
+/// can wait for all of them before handing out the pool.
+#[derive(Clone)]
+pub(crate) struct StartLatch(Arc<(Mutex<usize>, Condvar)>);
 
-    pub(crate) fn as_ref(&self) -> Result<&T, ErrorKind> {
-        self.0.as_ref().ok_or(ErrorKind::NotFound)
+impl StartLatch {
+    pub(crate) fn new() -> Self {
+        StartLatch(Arc::new((Mutex::new(0), Condvar::new())))
     }
 
-    pub(crate) fn set(&mut self, val: T) {
-        self.0.replace(val);
+    /// Report that the worker has been initialized.
+    pub(crate) fn arrive(&self) {
+        let (lock, cvar) = &*self.0;
+        *lock.lock() += 1;
+        cvar.notify_all();
     }
 
-    pub(crate) fn take(&mut self) -> Option<T> {
-        self.0.take()
+    /// Block the caller until `count` workers have arrived.
+    pub(crate) fn wait(&self, count: usize) {
+        let (lock, cvar) = &*self.0;
+        let mut arrived = lock.lock();
+
+        while *arrived < count {
+            cvar.wait(&mut arrived);
+        }
     }
 }
 
-impl<T> Deref for StaticStore<T> {
-    type Target = T;
-
-    fn deref(&self) -> &Self::Target {
-        self.0.as_ref().expect(ERR_MSG)
-    }
+/// The thread to auto adjust the pools every period, which quits once it's told to stop.
+pub(crate) struct AutoAdjustHandler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
 }
 
-impl<T> DerefMut for StaticStore<T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.0.as_mut().expect(ERR_MSG)
+impl AutoAdjustHandler {
+    pub(crate) fn start(
+        period: Duration,
+        bg_config: &BgThreadConfig,
+        trigger: fn(),
+    ) -> io::Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+
+        let handle = bg_config.spawn("pool-auto-adjust", move || {
+            let mut due = Instant::now() + period;
+
+            loop {
+                // check the flag before each sleep, and wake up early when told to stop
+                if thread_stop.load(Ordering::Acquire) {
+                    return;
+                }
+
+                let now = Instant::now();
+                if now < due {
+                    thread::park_timeout(due - now);
+                    continue;
+                }
+
+                trigger();
+                due = Instant::now() + period;
+            }
+        })?;
+
+        Ok(AutoAdjustHandler { stop, handle })
     }
-}
 
-impl<T> Drop for StaticStore<T> {
-    fn drop(&mut self) {
-        if let Some(inner) = self.0.take() {
-            // manually call the drop before we leave
-            drop(inner);
-        }
+    /// Tell the thread to stop, and wait for it to quit.
+    pub(crate) fn stop(self) {
+        self.stop.store(true, Ordering::Release);
+        self.handle.thread().unpark();
+
+        self.handle.join().unwrap_or_else(|e| {
+            log(Level::Error, format_args!("Unable to join the thread: {:?}", e));
+        });
     }
 }
 
@@ -154,7 +260,7 @@ pub(crate) fn reset_lock(state: &AtomicI8) {
 
 pub(crate) fn cpu_relax(count: usize) {
     for _ in 0..(1 << count) {
-        atomic::spin_loop_hint()
+        hint::spin_loop()
     }
 }
 
@@ -1,111 +1,457 @@
 #![allow(dead_code)]
 
-use std::io::ErrorKind;
-use std::sync::atomic::{AtomicBool, AtomicI8, Ordering};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
 
-use crate::config::{Config, ConfigStatus};
-use crate::debug::is_debug_mode;
-use crate::model::{concede_update, reset_lock, spin_update, Backoff, StaticStore};
-use crate::pool::{PoolManager, PoolState, ThreadPool};
+use crate::config::{BgThreadConfig, Config, ConfigStatus};
+use crate::debug::{is_debug_mode, log, Level};
+use crate::metrics::{PoolMetricsSnapshot, PoolStats};
+use crate::model::AutoAdjustHandler;
+use crate::pool::{
+    AlreadyInitialized, BatchError, ExecutionError, PoolManager, PoolState, ThreadPool,
+};
 use hashbrown::{HashMap, HashSet};
-use parking_lot::{Once, OnceState, ONCE_INIT};
+use parking_lot::{Mutex, Once, OnceState, RwLock, ONCE_INIT};
 
 /// Atomic constants
 static ONCE: Once = ONCE_INIT;
 static CLOSING: AtomicBool = AtomicBool::new(false);
 
 /// The actual store
-static mut MULTI_POOL: StaticStore<PoolStore> = StaticStore::init(); //Option<PoolStore> = None;
+static MULTI_POOL: OnceLock<PoolStore> = OnceLock::new();
 
+/// Enumeration of the reasons a job can't be run by the pools in the `index_mode`.
+#[derive(Debug)]
+pub enum RunError {
+    /// The pools are shutting down, or have been closed.
+    PoolClosing,
+
+    /// No pool has been created with the given key, and there's no default pool to fall back to.
+    PoolNotFound,
+
+    /// The pool has been found, but it has rejected the job.
+    ExecFailed(ExecutionError),
+
+    /// The pool has been found, but it has rejected some jobs of the batch.
+    BatchFailed(BatchError),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::PoolClosing => f.write_str("the pools are closing"),
+            RunError::PoolNotFound => f.write_str("no pool is found to run the job"),
+            RunError::ExecFailed(err) => write!(f, "the pool has rejected the job: {}", err),
+            RunError::BatchFailed(err) => write!(f, "the pool has rejected the batch: {}", err),
+        }
+    }
+}
+
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RunError::ExecFailed(err) => Some(err),
+            RunError::BatchFailed(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ExecutionError> for RunError {
+    fn from(err: ExecutionError) -> Self {
+        RunError::ExecFailed(err)
+    }
+}
+
+/// What to do with the jobs submitted with keys that no pool has been created with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RunPolicy {
+    /// Reject the job with `RunError::PoolNotFound`, even if a fallback pool is set.
+    Reject,
+
+    /// Run the job in the fallback pool, or reject it if there's no fallback pool. This is the
+    /// default policy.
+    #[default]
+    Fallback,
+
+    /// Run the job in the fallback pool, or on a dedicated thread if there's no fallback pool. Mind
+    /// that the number of such threads is unbounded.
+    SpawnDetached,
+}
+
+/// The final state of a pool removed through `remove_pool`.
+#[derive(Clone, Debug)]
+pub struct RemovedPool {
+    /// The number of workers the pool had when it was removed.
+    pub worker_count: usize,
+
+    /// The job counters of the pool, taken after it's closed.
+    pub metrics: PoolMetricsSnapshot,
+}
+
+/// The pools registry. Job submissions only need to take the read lock of the `store`, such that
+/// they can be made from many threads at the same time; adding, removing or resizing pools will
+/// take the write lock.
 struct PoolStore {
-    store: HashMap<String, ThreadPool>,
-    mutating: AtomicI8,
-    auto_adjust_period: Option<Duration>,
-    auto_adjust_handler: Option<JoinHandle<()>>,
-    auto_adjust_register: HashSet<String>,
+    store: RwLock<HashMap<String, ThreadPool>>,
+    auto_adjust_period: Mutex<Option<Duration>>,
+    auto_adjust_handler: Mutex<Option<AutoAdjustHandler>>,
+    auto_adjust_register: RwLock<HashSet<String>>,
+    routes: RwLock<HashMap<String, Vec<Route>>>,
+    default_key: RwLock<Option<String>>,
+    run_policy: RwLock<RunPolicy>,
+    steal_targets: RwLock<HashMap<String, Vec<String>>>,
+    shutdown_order: RwLock<Vec<String>>,
+    bg_config: BgThreadConfig,
+}
+
+/// The rule to forward the tagged jobs submitted to one pool to another pool.
+struct Route {
+    predicate: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+    to_key: String,
 }
 
 impl PoolStore {
-    #[inline]
-    fn inner() -> Result<&'static mut PoolStore, ErrorKind> {
-        unsafe {
-            if !CLOSING.load(Ordering::Acquire) {
-                MULTI_POOL.as_mut()
-            } else {
-                Err(ErrorKind::PermissionDenied)
+    /// Find the pool to run the jobs submitted with the key, which is the fallback pool if no pool
+    /// has been created with the key and the run policy allows it.
+    fn find<'a>(
+        &self,
+        store: &'a HashMap<String, ThreadPool>,
+        key: &str,
+    ) -> Option<&'a ThreadPool> {
+        store.get(key).or_else(|| {
+            if *self.run_policy.read() == RunPolicy::Reject {
+                return None;
             }
+
+            self.default_key
+                .read()
+                .as_ref()
+                .and_then(|default_key| store.get(default_key))
+        })
+    }
+
+    /// Find the pool the tagged job shall be forwarded to. Only the routes of the pool the job is
+    /// submitted to are checked, i.e. a job is forwarded at most once, so routes can't form a loop.
+    fn route(&self, key: &str, tag: &str) -> Option<String> {
+        self.routes.read().get(key).and_then(|routes| {
+            routes
+                .iter()
+                .find(|route| (route.predicate)(tag))
+                .map(|route| route.to_key.clone())
+        })
+    }
+
+    /// Let each pool with the stealing enabled steal from all other pools in the store. This shall
+    /// be called whenever a pool is added or removed.
+    fn refresh_stealing(&self) {
+        let store = self.store.read();
+        let mut targets = self.steal_targets.write();
+        targets.retain(|key, _| store.contains_key(key));
+
+        for (key, siblings) in targets.iter_mut() {
+            *siblings = store.keys().filter(|other| *other != key).cloned().collect();
+
+            if let Some(pool) = store.get(key) {
+                pool.set_sibling_queues(
+                    siblings
+                        .iter()
+                        .filter_map(|sibling| store.get(sibling).map(ThreadPool::job_queues))
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    #[inline]
+    fn inner() -> Result<&'static PoolStore, ErrorKind> {
+        if !CLOSING.load(Ordering::Acquire) {
+            MULTI_POOL.get().ok_or(ErrorKind::NotFound)
+        } else {
+            Err(ErrorKind::PermissionDenied)
         }
     }
 
-    fn take() -> Result<&'static mut PoolStore, ErrorKind> {
+    /// Check if the pools have been created and not yet closed.
+    #[inline]
+    fn is_some() -> bool {
+        PoolStore::inner().is_ok()
+    }
+
+    fn take() -> Result<&'static PoolStore, ErrorKind> {
         if CLOSING.compare_exchange_weak(false, true, Ordering::SeqCst, Ordering::Relaxed)
             == Ok(false)
         {
-            unsafe { MULTI_POOL.as_mut() }
+            MULTI_POOL.get().ok_or(ErrorKind::NotFound)
         } else {
             Err(ErrorKind::PermissionDenied)
         }
     }
 }
 
-impl Drop for PoolStore {
-    fn drop(&mut self) {
-        if !CLOSING.load(Ordering::Acquire) {
-            shut_down(false);
-        }
+/// Initialize the pools with the given keys and sizes. If the `default_key` is given, the jobs
+/// submitted with unknown keys will be run by the pool with the default key.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+/// use std::sync::mpsc;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 1);
+/// keys.insert(String::from("misc"), 1);
+/// index_mode::initialize(keys, Some(String::from("misc")))
+///     .expect("Unable to spawn the pool workers");
+///
+/// // the job for an unknown key is run by the default pool
+/// let (tx, rx) = mpsc::channel();
+/// index_mode::run_with(String::from("cpu"), move || tx.send(()).unwrap()).unwrap();
+/// rx.recv().unwrap();
+///
+/// // without a default pool, the caller will be told about the miss
+/// index_mode::set_default_pool(String::new());
+/// match index_mode::run_with(String::from("cpu"), || {}) {
+///     Err(index_mode::RunError::PoolNotFound) => {}
+///     _ => panic!("the job shall be rejected"),
+/// }
+///
+/// index_mode::close();
+/// ```
+#[inline]
+pub fn initialize<S>(
+    keys: std::collections::HashMap<String, usize, S>,
+    default_key: Option<String>,
+) -> io::Result<()>
+where
+    S: std::hash::BuildHasher,
+{
+    init_with_config(keys, Config::default())?;
+
+    if let Some(key) = default_key {
+        set_default_pool(key);
     }
+
+    Ok(())
 }
 
-impl Backoff for PoolStore {
-    fn spin_update(&self, new: i8) {
-        spin_update(&self.mutating, new);
-    }
+/// Set the pool to run the jobs submitted with unknown keys, or remove the default pool if the `key`
+/// is empty. This is the same as `set_fallback_pool`.
+#[inline]
+pub fn set_default_pool(key: String) {
+    set_fallback_pool(key);
+}
 
-    fn concede_update(&self, new: i8) -> bool {
-        concede_update(&self.mutating, new)
+/// Set the pool to run the jobs submitted with unknown keys, or remove the fallback pool if the
+/// `key` is empty. Whether the fallback pool is used is up to the `RunPolicy`.
+pub fn set_fallback_pool(key: String) {
+    if let Ok(pools) = PoolStore::inner() {
+        *pools.default_key.write() = if key.is_empty() { None } else { Some(key) };
     }
+}
 
-    #[inline(always)]
-    fn reset_lock(&self) {
-        reset_lock(&self.mutating)
+/// Set what to do with the jobs submitted with keys that no pool has been created with. See
+/// `RunPolicy` for the options.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::index_mode::{self, RunError, RunPolicy};
+/// use std::collections::HashMap;
+/// use std::sync::mpsc;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 1);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// // no fallback pool: the job is either rejected, or run on a dedicated thread
+/// let res = index_mode::run_with(String::from("cpu"), || {});
+/// assert!(matches!(res, Err(RunError::PoolNotFound)));
+///
+/// index_mode::set_run_policy(RunPolicy::SpawnDetached);
+/// let (tx, rx) = mpsc::channel();
+/// let done = tx.clone();
+/// index_mode::run_with(String::from("cpu"), move || done.send(()).unwrap()).unwrap();
+/// rx.recv().unwrap();
+///
+/// // a detached thread is never spawned by `try_run_with`
+/// let res = index_mode::try_run_with(String::from("cpu"), || {});
+/// assert!(matches!(res, Err(RunError::PoolNotFound)));
+///
+/// // with a fallback pool, the job is run by the fallback pool unless it's rejected outright
+/// index_mode::set_fallback_pool(String::from("io"));
+/// index_mode::set_run_policy(RunPolicy::Fallback);
+/// index_mode::try_run_with(String::from("cpu"), move || tx.send(()).unwrap()).unwrap();
+/// rx.recv().unwrap();
+///
+/// index_mode::set_run_policy(RunPolicy::Reject);
+/// let res = index_mode::run_with(String::from("cpu"), || {});
+/// assert!(matches!(res, Err(RunError::PoolNotFound)));
+///
+/// index_mode::close();
+/// ```
+pub fn set_run_policy(policy: RunPolicy) {
+    if let Ok(pools) = PoolStore::inner() {
+        *pools.run_policy.write() = policy;
     }
 }
 
-#[inline]
-pub fn initialize<S>(keys: std::collections::HashMap<String, usize, S>)
+/// Initialize the pools like `initialize`, and let all pools steal jobs from each other: when a
+/// worker finds nothing to do in its own pool, it will take a job queued in a sibling pool. This
+/// breaks the isolation between the pools, so it can be turned off for a pool afterwards with
+/// `toggle_pool_stealing`.
+pub fn initialize_with_stealing<S>(
+    keys: std::collections::HashMap<String, usize, S>,
+) -> io::Result<()>
 where
     S: std::hash::BuildHasher,
 {
-    init_with_config(keys, Config::default());
+    let pool_keys: Vec<String> = keys.keys().cloned().collect();
+    init_with_config(keys, Config::default())?;
+
+    for key in pool_keys {
+        toggle_pool_stealing(key, true);
+    }
+
+    Ok(())
 }
 
+/// Allow or forbid the idle workers of the pool with the given key to steal the jobs queued in the
+/// other pools. The stealing is off by default, since it breaks the isolation between the pools.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::index_mode;
+/// use std::collections::HashMap;
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("A"), 1);
+/// keys.insert(String::from("B"), 1);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// // keep the only worker of pool B busy
+/// let (gate_tx, gate_rx) = mpsc::channel::<()>();
+/// let (started_tx, started_rx) = mpsc::channel();
+/// index_mode::run_with(String::from("B"), move || {
+///     started_tx.send(()).unwrap();
+///     let _ = gate_rx.recv();
+/// }).unwrap();
+/// started_rx.recv().unwrap();
+///
+/// // turn on the stealing only now, or the idle worker of pool A could take the gate job
+/// index_mode::toggle_pool_stealing(String::from("A"), true);
+///
+/// // the job queued in pool B is taken by the idle worker of pool A
+/// let (tx, rx) = mpsc::channel();
+/// let done = tx.clone();
+/// index_mode::run_with(String::from("B"), move || done.send(1).unwrap()).unwrap();
+/// assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 1);
+///
+/// // no more helping hands once the stealing is off
+/// index_mode::toggle_pool_stealing(String::from("A"), false);
+/// index_mode::run_with(String::from("B"), move || tx.send(2).unwrap()).unwrap();
+/// assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+///
+/// drop(gate_tx);
+/// assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 2);
+/// index_mode::close();
+/// ```
+pub fn toggle_pool_stealing(key: String, enabled: bool) {
+    if let Ok(pools) = PoolStore::inner() {
+        if enabled {
+            pools.steal_targets.write().entry(key).or_default();
+        } else if pools.steal_targets.write().remove(&key).is_some() {
+            if let Some(pool) = pools.store.read().get(&key) {
+                pool.set_sibling_queues(Vec::new());
+            }
+        }
+
+        pools.refresh_stealing();
+    }
+}
+
+/// Initialize the pools with the given keys and sizes, which will be auto adjusted every `period`
+/// while keeping their sizes between `min_workers` and `max_workers`, if it's set.
 pub fn initialize_with_auto_adjustment<S>(
     keys: std::collections::HashMap<String, usize, S>,
     period: Option<Duration>,
-) where
+    min_workers: usize,
+    max_workers: Option<usize>,
+) -> io::Result<()>
+where
     S: std::hash::BuildHasher,
 {
     let mut config = Config::default();
-    config.set_refresh_period(period);
-    init_with_config(keys, config);
+    config
+        .set_refresh_period(period)
+        .set_min_workers(min_workers)
+        .set_max_workers(max_workers);
+    init_with_config(keys, config)
 }
 
-pub fn init_with_config<S>(keys: std::collections::HashMap<String, usize, S>, config: Config)
+/// Initialize the pools with the given keys and sizes. An error will be returned if the OS refuses
+/// to spawn any worker thread for one of the pools, and none of the pools will be created. If the
+/// pools have been initialized before, the error wraps `AlreadyInitialized` instead. A failed
+/// attempt doesn't count as initialized, so the pools can be initialized again later.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 2);
+///
+/// // the OS refuses to spawn the workers with such a huge stack
+/// let mut config = Config::default();
+/// config.set_worker_stack_size(usize::MAX / 4);
+///
+/// let err = index_mode::init_with_config(keys.clone(), config).unwrap_err();
+/// assert!(!err.get_ref().map_or(false, |err| err.is::<AlreadyInitialized>()));
+///
+/// // try again with the defaults, and the pools are up this time
+/// index_mode::init_with_config(keys.clone(), Config::default()).unwrap();
+/// assert!(index_mode::run_with(String::from("io"), || {}).is_ok());
+///
+/// // now the pools can't be initialized again
+/// let err = index_mode::init_with_config(keys, Config::default()).unwrap_err();
+/// assert!(err.get_ref().unwrap().is::<AlreadyInitialized>());
+///
+/// index_mode::close();
+/// ```
+pub fn init_with_config<S>(
+    keys: std::collections::HashMap<String, usize, S>,
+    config: Config,
+) -> io::Result<()>
 where
     S: std::hash::BuildHasher,
 {
     if keys.is_empty() {
-        return;
+        return Ok(());
     }
 
-    assert_eq!(
-        ONCE.state(),
-        OnceState::New,
-        "The pool has already been initialized..."
-    );
+    if ONCE.state() != OnceState::New {
+        return Err(AlreadyInitialized.into());
+    }
 
     // copy to more efficient structure
     let mut map = HashMap::with_capacity(keys.len());
@@ -113,41 +459,574 @@ where
         map.entry(k).or_insert(v);
     }
 
+    // spawn the pools before taking the ONCE, such that a failed attempt leaves it untouched
+    let store = create(map, &config)?;
+
+    // if someone else has beaten us to it, the result stays as is, and our pools are closed when
+    // the store is dropped
+    let mut result = Err(AlreadyInitialized.into());
     ONCE.call_once(|| {
-        create(map, config);
+        install(store, config);
+        result = Ok(());
     });
+
+    result
+}
+
+/// Submit a job to the pool with the given key, or to the fallback pool if no pool has been created
+/// with the key. What to do if there's no such pool is up to the `RunPolicy`: unless the policy is
+/// `SpawnDetached`, an error will be returned and the job will be dropped; use `run_with_or_spawn`
+/// to run such jobs on a dedicated thread instead.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 2);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// assert!(index_mode::run_with(String::from("io"), || {}).is_ok());
+///
+/// match index_mode::run_with(String::from("cpu"), || {}) {
+///     Err(index_mode::RunError::PoolNotFound) => {}
+///     _ => panic!("the job shall be rejected"),
+/// }
+///
+/// index_mode::close();
+///
+/// match index_mode::run_with(String::from("io"), || {}) {
+///     Err(index_mode::RunError::PoolClosing) => {}
+///     _ => panic!("the job shall be rejected"),
+/// }
+/// ```
+pub fn run_with<F: FnOnce() + Send + 'static>(key: String, f: F) -> Result<(), RunError> {
+    match dispatch(key, None, false, f) {
+        Err((RunError::PoolNotFound, Some(f))) if spawn_detached() => {
+            thread::spawn(f);
+            Ok(())
+        }
+        res => res.map_err(|(err, _)| err),
+    }
+}
+
+/// Submit a job to the priority queue of the pool with the given key, e.g. for the health checks
+/// that shall not wait behind a backlog of requests. How soon the priority jobs are picked up ahead
+/// of the normal ones depends on the `FetchStrategy` of the pool. The job is handled like in
+/// `run_with` otherwise.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+/// use std::sync::mpsc;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("web"), 2);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// let (tx, rx) = mpsc::channel();
+/// index_mode::run_with_priority(String::from("web"), move || tx.send("health").unwrap())
+///     .unwrap();
+/// assert_eq!(rx.recv().unwrap(), "health");
+///
+/// match index_mode::run_with_priority(String::from("db"), || {}) {
+///     Err(index_mode::RunError::PoolNotFound) => {}
+///     _ => panic!("the job shall be rejected"),
+/// }
+///
+/// index_mode::close();
+/// ```
+pub fn run_with_priority<F: FnOnce() + Send + 'static>(key: String, f: F) -> Result<(), RunError> {
+    match dispatch(key, None, true, f) {
+        Err((RunError::PoolNotFound, Some(f))) if spawn_detached() => {
+            thread::spawn(f);
+            Ok(())
+        }
+        res => res.map_err(|(err, _)| err),
+    }
+}
+
+/// Submit a job to the pool with the given key like `run_with`, but never run it on a dedicated
+/// thread, whatever the `RunPolicy` is. An error will be returned if no pool has taken the job.
+///
+/// The submissions only take the read lock of the pools registry, so they can be made from many
+/// threads at the same time.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::{mpsc, Arc};
+/// use std::thread;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 2);
+/// keys.insert(String::from("cpu"), 2);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// let count = Arc::new(AtomicUsize::new(0));
+/// let (tx, rx) = mpsc::channel();
+///
+/// let submitters: Vec<_> = (0..8)
+///     .map(|i| {
+///         let count = Arc::clone(&count);
+///         let tx = tx.clone();
+///
+///         thread::spawn(move || {
+///             let key = if i % 2 == 0 { "io" } else { "cpu" };
+///             for _ in 0..100 {
+///                 let (count, tx) = (Arc::clone(&count), tx.clone());
+///                 index_mode::try_run_with(key.to_string(), move || {
+///                     count.fetch_add(1, Ordering::SeqCst);
+///                     tx.send(()).unwrap();
+///                 })
+///                 .unwrap();
+///             }
+///         })
+///     })
+///     .collect();
+///
+/// submitters.into_iter().for_each(|s| s.join().unwrap());
+/// (0..800).for_each(|_| rx.recv().unwrap());
+///
+/// assert_eq!(count.load(Ordering::SeqCst), 800);
+/// index_mode::close();
+/// ```
+pub fn try_run_with<F: FnOnce() + Send + 'static>(key: String, f: F) -> Result<(), RunError> {
+    dispatch(key, None, false, f).map_err(|(err, _)| err)
+}
+
+/// Check if the jobs with unknown keys shall be run on dedicated threads.
+fn spawn_detached() -> bool {
+    PoolStore::inner()
+        .map(|pools| *pools.run_policy.read() == RunPolicy::SpawnDetached)
+        .unwrap_or(false)
+}
+
+/// Submit a job to the pool with the given key like `run_with`, but if the pools are closing, no
+/// pool can take the job, or the pool turns the job away, e.g. its queue is full, run it on a
+/// dedicated thread instead.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+/// use std::sync::mpsc;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 1);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// let (tx, rx) = mpsc::channel();
+/// index_mode::run_with_or_spawn(String::from("cpu"), move || tx.send(1).unwrap());
+///
+/// assert_eq!(rx.recv().unwrap(), 1);
+/// index_mode::close();
+/// ```
+///
+/// The job rejected by a pool with a full queue is run all the same:
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+/// use std::sync::mpsc;
+/// use std::thread;
+/// use std::time::{Duration, Instant};
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 1);
+///
+/// let mut config = Config::default();
+/// config.set_queue_capacity(Some(1)).set_none_blocking(true);
+/// index_mode::init_with_config(keys, config).expect("Unable to spawn the pool workers");
+///
+/// // hold the only worker, and fill up the queue behind it
+/// let (hold_tx, hold_rx) = mpsc::channel::<()>();
+/// index_mode::run_with(String::from("io"), move || { let _ = hold_rx.recv(); }).unwrap();
+///
+/// let deadline = Instant::now() + Duration::from_secs(5);
+/// while index_mode::stats("io").unwrap().active_workers == 0 && Instant::now() < deadline {
+///     thread::yield_now();
+/// }
+///
+/// let mut full = false;
+/// for _ in 0..16 {
+///     if index_mode::try_run_with(String::from("io"), || {}).is_err() {
+///         full = true;
+///         break;
+///     }
+/// }
+/// assert!(full);
+///
+/// // the worker is still held, so the job can only be run on its own thread
+/// let (tx, rx) = mpsc::channel();
+/// index_mode::run_with_or_spawn(String::from("io"), move || tx.send(1).unwrap());
+/// assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(1));
+///
+/// drop(hold_tx);
+/// index_mode::close();
+/// ```
+pub fn run_with_or_spawn<F: FnOnce() + Send + 'static>(key: String, f: F) {
+    // the pool drops the job it turns away, so keep a hold on the job to run it elsewhere
+    let job = Arc::new(Mutex::new(Some(f)));
+    let queued = Arc::clone(&job);
+
+    if dispatch(key, None, false, move || run_once(&queued)).is_err() {
+        thread::spawn(move || run_once(&job));
+    }
+}
+
+/// Run the job unless it's been run already.
+fn run_once<F: FnOnce()>(job: &Mutex<Option<F>>) {
+    let job = job.lock().take();
+    if let Some(f) = job {
+        f();
+    }
+}
+
+/// Submit a batch of jobs to the pool with the given key at once, or to the default pool if no pool
+/// has been created with the key. See `ThreadPool::exec_batch` for details.
+pub fn run_batch_with<I, F>(key: String, jobs: I) -> Result<(), RunError>
+where
+    I: IntoIterator<Item = F>,
+    F: FnOnce() + Send + 'static,
+{
+    let pools = PoolStore::inner().map_err(|e| {
+        if e == ErrorKind::PermissionDenied {
+            RunError::PoolClosing
+        } else {
+            RunError::PoolNotFound
+        }
+    })?;
+
+    let store = pools.store.read();
+    match pools.find(&store, &key) {
+        Some(pool) => pool.exec_batch(jobs).map_err(RunError::BatchFailed),
+        None => Err(RunError::PoolNotFound),
+    }
 }
 
-pub fn run_with<F: FnOnce() + Send + 'static>(key: String, f: F) {
+/// Submit a job with a tag to the pool with the given key. If the tag matches any route set on the
+/// pool, the job will be forwarded to the route's target pool instead.
+pub fn run_tagged<F: FnOnce() + Send + 'static>(
+    key: String,
+    tag: &str,
+    f: F,
+) -> Result<(), RunError> {
+    dispatch(key, Some(tag), false, f).map_err(|(err, _)| err)
+}
+
+/// Forward the jobs submitted to the `from_key` pool to the `to_key` pool, if the job's tag meets
+/// the `predicate`. The routes are checked in the order they're set, and untagged jobs are never
+/// forwarded.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::index_mode;
+/// use std::collections::HashMap;
+/// use std::sync::mpsc;
+/// use std::thread;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("A"), 1);
+/// keys.insert(String::from("B"), 1);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// index_mode::set_route(String::from("A"), |tag| tag == "heavy", String::from("B"));
+///
+/// let busy = |key: &str| index_mode::stats(key).unwrap().active_workers;
+/// let wait_busy = |key: &str| while busy(key) == 0 { thread::yield_now(); };
+///
+/// // the tagged job is forwarded to pool B
+/// let (heavy_tx, heavy_rx) = mpsc::channel::<()>();
+/// index_mode::run_tagged(String::from("A"), "heavy", move || { let _ = heavy_rx.recv(); }).unwrap();
+/// wait_busy("B");
+/// assert_eq!(busy("A"), 0);
+///
+/// // the untagged job stays on pool A
+/// let (light_tx, light_rx) = mpsc::channel::<()>();
+/// index_mode::run_with(String::from("A"), move || { let _ = light_rx.recv(); }).unwrap();
+/// wait_busy("A");
+///
+/// drop(heavy_tx);
+/// drop(light_tx);
+/// index_mode::close();
+/// ```
+pub fn set_route<P>(from_key: String, predicate: P, to_key: String)
+where
+    P: Fn(&str) -> bool + Send + Sync + 'static,
+{
+    if from_key.is_empty() || to_key.is_empty() || from_key == to_key {
+        return;
+    }
+
+    if let Ok(pools) = PoolStore::inner() {
+        pools.routes.write().entry(from_key).or_default().push(Route {
+            predicate: Arc::new(predicate),
+            to_key,
+        });
+    }
+}
+
+/// Remove all routes set on the pool with the given key.
+pub fn clear_routes(from_key: &str) {
+    if let Ok(pools) = PoolStore::inner() {
+        pools.routes.write().remove(from_key);
+    }
+}
+
+/// Submit the job to the pool, and hand the job back with the error if no pool can take it.
+fn dispatch<F: FnOnce() + Send + 'static>(
+    key: String,
+    tag: Option<&str>,
+    prioritized: bool,
+    f: F,
+) -> Result<(), (RunError, Option<F>)> {
     match PoolStore::inner() {
         Ok(pool) => {
-            // if pool has been created
-            if let Some(p) = pool.store.get_mut(&key) {
-                if p.exec(f, false).is_err() && is_debug_mode() {
-                    eprintln!("The execution of this job has failed...");
-                }
-            } else if is_debug_mode() {
-                eprintln!("Unable to identify the pool with given key: {}", key);
+            let key = tag
+                .and_then(|tag| pool.route(&key, tag))
+                .unwrap_or(key);
+
+            // if pool has been created, or fall back to the default pool
+            let store = pool.store.read();
+            if let Some(p) = pool.find(&store, &key) {
+                return p.exec(f, prioritized).map_err(|err| {
+                    if is_debug_mode() {
+                        log(
+                            Level::Warn,
+                            format_args!("The execution of this job has failed: {:?}", err),
+                        );
+                    }
+
+                    (RunError::from(err), None)
+                });
             }
+
+            if is_debug_mode() {
+                log(
+                    Level::Warn,
+                    format_args!("Unable to identify the pool with given key: {}", key),
+                );
+            }
+
+            Err((RunError::PoolNotFound, Some(f)))
         }
         Err(e) => {
-            // pool could have closed, just execute the job
-            thread::spawn(f);
-
             if is_debug_mode() {
-                eprintln!(
-                    "The pool is in invalid state: {:?}, the thread pool should be restarted...",
-                    e
+                log(
+                    Level::Warn,
+                    format_args!(
+                        "The pool is in invalid state: {:?}, the thread pool should be restarted...",
+                        e
+                    ),
                 );
             }
+
+            if e == ErrorKind::PermissionDenied {
+                Err((RunError::PoolClosing, Some(f)))
+            } else {
+                Err((RunError::PoolNotFound, Some(f)))
+            }
         }
-    };
+    }
+}
+
+/// Check how busy the pool with the given key is at the moment, or `None` if no such pool is running.
+pub fn stats(key: &str) -> Option<PoolStats> {
+    PoolStore::inner()
+        .ok()
+        .and_then(|pools| pools.store.read().get(key).map(ThreadPool::stats))
+}
+
+/// The number of workers alive in the pool with the given key, or `None` if no such pool is running.
+pub fn pool_worker_count(key: &str) -> Option<usize> {
+    PoolStore::inner()
+        .ok()
+        .and_then(|pools| pools.store.read().get(key).map(ThreadPool::worker_count))
 }
 
+/// The number of jobs waiting in the queues of the pool with the given key, or `None` if no such pool
+/// is running.
+pub fn pool_pending_jobs(key: &str) -> Option<usize> {
+    PoolStore::inner()
+        .ok()
+        .and_then(|pools| pools.store.read().get(key).map(ThreadPool::pending_jobs))
+}
+
+/// List the keys of the pools that are running, in no particular order, or an empty list if the
+/// pools have not been initialized or have been closed.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 1);
+/// keys.insert(String::from("cpu"), 2);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// let mut names = index_mode::list_pools();
+/// names.sort();
+/// assert_eq!(names, vec!["cpu", "io"]);
+///
+/// for name in names {
+///     println!("{}: {:?} workers", name, index_mode::pool_worker_count(&name));
+/// }
+///
+/// assert!(index_mode::pool_exists("io"));
+/// assert!(!index_mode::pool_exists("gpu"));
+///
+/// index_mode::close();
+/// assert!(index_mode::list_pools().is_empty());
+/// assert!(!index_mode::pool_exists("io"));
+/// ```
+pub fn list_pools() -> Vec<String> {
+    PoolStore::inner()
+        .map(|pools| pools.store.read().keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Check if a pool with the given key is running, without copying the keys like `list_pools`.
+pub fn pool_exists(key: &str) -> bool {
+    PoolStore::inner()
+        .map(|pools| pools.store.read().contains_key(key))
+        .unwrap_or(false)
+}
+
+/// Close all pools, and block until the jobs queued in them have been done. The pools listed by
+/// `set_shutdown_order` are closed first, one at a time in that order, then the rest of the pools.
 pub fn close() {
+    let order = PoolStore::inner()
+        .map(|pools| pools.shutdown_order.read().clone())
+        .unwrap_or_default();
+
+    close_ordered(order);
+}
+
+/// Close the pools one at a time in the given `order`, and then the pools not listed. Each pool is
+/// drained, i.e. its queues are empty and its workers are idle, before the next one starts closing,
+/// and the pools not closed yet still take the jobs, such that the jobs of an upstream pool can
+/// hand their follow-up jobs to the downstream pools while they're drained.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("ingest"), 1);
+/// keys.insert(String::from("index"), 1);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// let indexed = Arc::new(AtomicUsize::new(0));
+/// let lost = Arc::new(AtomicUsize::new(0));
+///
+/// // each ingested item is handed over to the index pool
+/// for _ in 0..10 {
+///     let (indexed, lost) = (Arc::clone(&indexed), Arc::clone(&lost));
+///     index_mode::run_with(String::from("ingest"), move || {
+///         thread::sleep(Duration::from_millis(2));
+///
+///         let handed = index_mode::try_run_with(String::from("index"), move || {
+///             indexed.fetch_add(1, Ordering::SeqCst);
+///         });
+///
+///         if handed.is_err() {
+///             lost.fetch_add(1, Ordering::SeqCst);
+///         }
+///     })
+///     .unwrap();
+/// }
+///
+/// // the producer goes first, such that the consumer takes all of its follow-up jobs
+/// index_mode::close_ordered(vec![String::from("ingest"), String::from("index")]);
+///
+/// assert_eq!(lost.load(Ordering::SeqCst), 0);
+/// assert_eq!(indexed.load(Ordering::SeqCst), 10);
+/// ```
+pub fn close_ordered(order: Vec<String>) {
+    close_in_order(&order);
     shut_down(false);
 }
 
+/// Set the order in which `close` closes the pools, see `close_ordered` for details. The pools not
+/// listed are closed after the listed ones.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("ingest"), 1);
+/// keys.insert(String::from("index"), 1);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// // the consumer is closed ahead of the producer, which is the wrong way round
+/// index_mode::set_shutdown_order(vec![String::from("index"), String::from("ingest")]);
+///
+/// let lost = Arc::new(AtomicUsize::new(0));
+/// for _ in 0..10 {
+///     let lost = Arc::clone(&lost);
+///     index_mode::run_with(String::from("ingest"), move || {
+///         thread::sleep(Duration::from_millis(5));
+///
+///         if index_mode::try_run_with(String::from("index"), || {}).is_err() {
+///             lost.fetch_add(1, Ordering::SeqCst);
+///         }
+///     })
+///     .unwrap();
+/// }
+///
+/// // the follow-up jobs of the items still queued in the producer have nowhere to go
+/// index_mode::close();
+/// assert!(lost.load(Ordering::SeqCst) > 0);
+/// ```
+pub fn set_shutdown_order(order: Vec<String>) {
+    if let Ok(pools) = PoolStore::inner() {
+        *pools.shutdown_order.write() = order;
+    }
+}
+
 pub fn force_close() {
     shut_down(true);
 }
@@ -159,94 +1038,236 @@ pub fn resize_pool(pool_key: String, size: usize) {
 
     thread::spawn(move || {
         if let Ok(pools) = PoolStore::inner() {
-            if let Some(pool_inner) = pools.store.get_mut(&pool_key) {
+            if let Some(pool_inner) = pools.store.write().get_mut(&pool_key) {
                 pool_inner.resize(size);
             }
         }
     });
 }
 
-pub fn remove_pool(key: String) -> Option<JoinHandle<()>> {
+/// Remove the pool with the given key from the store, and close it in the background. The pool
+/// will no longer be adjusted automatically, and the auto adjustment thread will be stopped if no
+/// other pool is in the auto mode. The returned handle yields the final state of the removed pool
+/// once it's closed, or `None` if no pool has been created with the key.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 2);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// index_mode::toggle_pool_auto_mode(String::from("io"), true);
+/// assert!(index_mode::is_pool_in_auto_mode(String::from("io")));
+/// assert!(index_mode::is_auto_adjustment_running());
+///
+/// index_mode::run_with(String::from("io"), || {}).unwrap();
+///
+/// let removed = index_mode::remove_pool(String::from("io")).unwrap().join().unwrap().unwrap();
+/// assert_eq!(removed.worker_count, 2);
+/// assert_eq!(removed.metrics.since_start_jobs_completed, 1);
+///
+/// assert!(!index_mode::is_pool_in_auto_mode(String::from("io")));
+/// assert!(!index_mode::is_auto_adjustment_running());
+///
+/// // the pool added back under the same key starts afresh
+/// index_mode::add_pool(String::from("io"), 1, None).unwrap().join().unwrap();
+/// assert!(!index_mode::is_pool_in_auto_mode(String::from("io")));
+/// assert_eq!(index_mode::stats("io").unwrap().worker_count, 1);
+///
+/// index_mode::close();
+/// ```
+pub fn remove_pool(key: String) -> Option<JoinHandle<Option<RemovedPool>>> {
     if key.is_empty() {
         return None;
     }
 
-    //TODO: remove from the auto_adjust_handlers as well...
-
     let handler = thread::spawn(move || {
-        if let Ok(pools) = PoolStore::inner() {
-            pools.concede_update(-1);
-            if let Some(mut pool_inner) = pools.store.remove(&key) {
-                pool_inner.close();
-            }
+        let pools = PoolStore::inner().ok()?;
 
-            pools.reset_lock();
+        // the pool shall not be adjusted any more, nor its successor under the same key
+        let is_empty = {
+            let mut register = pools.auto_adjust_register.write();
+            register.remove(&key) && register.is_empty()
+        };
+
+        if is_empty {
+            stop_auto_adjustment();
         }
+
+        // take the pool out of the store first, such that we won't block other pools while
+        // waiting for the removed pool to close.
+        let removed = pools.store.write().remove(&key);
+        pools.refresh_stealing();
+
+        removed.map(|mut pool_inner| {
+            let worker_count = pool_inner.worker_count();
+            pool_inner.close();
+
+            RemovedPool {
+                worker_count,
+                metrics: pool_inner.metrics(),
+            }
+        })
     });
 
     Some(handler)
 }
 
-pub fn add_pool(key: String, size: usize) -> Option<JoinHandle<()>> {
+/// Add a pool with the given key and size to the store, created with the `config` or the default
+/// configurations. If the pool already exists, it will be resized instead, and the `config` is
+/// ignored.
+///
+/// The pools can be added and removed while other threads are submitting jobs to them: a job is
+/// either taken by the pool, or rejected with `RunError::PoolNotFound` if the pool is gone.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("base"), 2);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+///
+/// let accepted = Arc::new(AtomicUsize::new(0));
+/// let ran = Arc::new(AtomicUsize::new(0));
+///
+/// // 4 threads keep adding and removing pools, while 4 threads submit jobs to them
+/// let threads: Vec<_> = (0..8)
+///     .map(|i| {
+///         let (accepted, ran) = (Arc::clone(&accepted), Arc::clone(&ran));
+///
+///         thread::spawn(move || {
+///             if i < 4 {
+///                 for _ in 0..20 {
+///                     let key = format!("churn-{}", i);
+///                     index_mode::add_pool(key.clone(), 1, None).unwrap().join().unwrap();
+///                     index_mode::remove_pool(key).unwrap().join().unwrap();
+///                 }
+///
+///                 return;
+///             }
+///
+///             for n in 0..200 {
+///                 let key = match n % 2 {
+///                     0 => String::from("base"),
+///                     _ => format!("churn-{}", (n / 2) % 4),
+///                 };
+///
+///                 let ran = Arc::clone(&ran);
+///                 let job = move || { ran.fetch_add(1, Ordering::SeqCst); };
+///
+///                 match index_mode::run_with(key, job) {
+///                     Ok(()) => { accepted.fetch_add(1, Ordering::SeqCst); }
+///                     Err(index_mode::RunError::PoolNotFound) => {}
+///                     Err(err) => panic!("unexpected error: {}", err),
+///                 }
+///             }
+///         })
+///     })
+///     .collect();
+///
+/// threads.into_iter().for_each(|t| t.join().unwrap());
+///
+/// // the removed pools have run their jobs before closing, and the rest are run on close
+/// index_mode::close();
+///
+/// assert!(accepted.load(Ordering::SeqCst) >= 400);
+/// assert_eq!(ran.load(Ordering::SeqCst), accepted.load(Ordering::SeqCst));
+/// ```
+pub fn add_pool(key: String, size: usize, config: Option<Config>) -> Option<JoinHandle<()>> {
     if key.is_empty() || size == 0 {
         return None;
     }
 
     let handler = thread::spawn(move || {
         if let Ok(pools) = PoolStore::inner() {
-            pools.concede_update(1);
+            let mut store = pools.store.write();
 
-            if let Some(pool_info) = pools.store.get_mut(&key) {
+            if let Some(pool_info) = store.get_mut(&key) {
                 if pool_info.get_size() != size {
                     pool_info.resize(size);
-                    return;
                 }
+
+                return;
             }
 
-            pools.store.insert(key, ThreadPool::new(size));
-            pools.reset_lock();
+            store.insert(key, ThreadPool::new_with_config(size, config.unwrap_or_default()));
+            drop(store);
+
+            // the stealing pools shall know about the new sibling
+            pools.refresh_stealing();
         }
     });
 
     Some(handler)
 }
 
-fn create<S>(keys: HashMap<String, usize, S>, config: Config)
+fn create<S>(
+    keys: HashMap<String, usize, S>,
+    config: &Config,
+) -> io::Result<HashMap<String, ThreadPool>>
 where
     S: std::hash::BuildHasher,
 {
-    let size = keys.len();
-    let mut store = HashMap::with_capacity(size);
+    let mut store = HashMap::with_capacity(keys.len());
 
     for (key, size) in keys {
-        if key.is_empty() || size == 0 {
+        if key.is_empty() || size == 0 || store.contains_key(&key) {
             continue;
         }
 
-        store
-            .entry(key)
-            .or_insert_with(|| ThreadPool::new_with_config(size, config.clone()));
+        // the pools created so far will be closed when the store is dropped
+        let pool = ThreadPool::try_new_with_config(size, config.clone())?;
+        store.insert(key, pool);
     }
 
-    unsafe {
-        // Put it in the heap so it can outlive this call
-        MULTI_POOL.set(PoolStore {
-            store,
-            mutating: AtomicI8::new(0),
-            auto_adjust_period: config.refresh_period(),
-            auto_adjust_handler: None,
-            auto_adjust_register: HashSet::with_capacity(size),
-        });
+    Ok(store)
+}
+
+fn install(store: HashMap<String, ThreadPool>, config: Config) {
+    let size = store.len();
+
+    // Put it in the static store so it can outlive this call
+    let created = MULTI_POOL
+        .set(PoolStore {
+            store: RwLock::new(store),
+            auto_adjust_period: Mutex::new(config.refresh_period()),
+            auto_adjust_handler: Mutex::new(None),
+            auto_adjust_register: RwLock::new(HashSet::with_capacity(size)),
+            routes: RwLock::new(HashMap::new()),
+            default_key: RwLock::new(None),
+            run_policy: RwLock::new(RunPolicy::default()),
+            steal_targets: RwLock::new(HashMap::new()),
+            shutdown_order: RwLock::new(Vec::new()),
+            bg_config: config.background_thread_config().clone(),
+        })
+        .is_ok();
+
+    if !created && is_debug_mode() {
+        log(Level::Warn, format_args!("The pool has already been initialized..."));
     }
 }
 
 pub fn start_auto_adjustment(period: Duration) {
     if let Ok(pools) = PoolStore::inner() {
-        if pools.auto_adjust_register.is_empty() {
+        if pools.auto_adjust_register.read().is_empty() {
             return;
         }
 
-        if pools.auto_adjust_handler.is_some() {
+        if pools.auto_adjust_handler.lock().is_some() {
             stop_auto_adjustment();
         }
 
@@ -257,31 +1278,32 @@ pub fn start_auto_adjustment(period: Duration) {
             period
         };
 
-        pools.auto_adjust_period = Some(actual_period);
-        pools.auto_adjust_handler = Some(thread::spawn(move || {
-            thread::sleep(actual_period);
-
-            loop {
-                trigger_auto_adjustment();
-                thread::sleep(actual_period);
-            }
-        }));
+        *pools.auto_adjust_period.lock() = Some(actual_period);
+        match AutoAdjustHandler::start(actual_period, &pools.bg_config, trigger_auto_adjustment) {
+            Ok(handler) => *pools.auto_adjust_handler.lock() = Some(handler),
+            Err(err) => log(
+                Level::Error,
+                format_args!("Unable to start the auto adjustment: {}", err),
+            ),
+        }
     }
 }
 
 pub fn stop_auto_adjustment() {
     if let Ok(pools) = PoolStore::inner() {
-        if let Some(handler) = pools.auto_adjust_handler.take() {
-            handler.join().unwrap_or_else(|e| {
-                eprintln!("Unable to join the thread: {:?}", e);
-            });
+        // release the lock before joining the auto adjustment thread
+        let handler = pools.auto_adjust_handler.lock().take();
+
+        if let Some(handler) = handler {
+            handler.stop();
         }
 
-        if !pools.auto_adjust_register.is_empty() {
-            pools.auto_adjust_register = HashSet::with_capacity(pools.store.len());
+        let mut register = pools.auto_adjust_register.write();
+        if !register.is_empty() {
+            *register = HashSet::with_capacity(pools.store.read().len());
         }
 
-        pools.auto_adjust_period = None;
+        *pools.auto_adjust_period.lock() = None;
     }
 }
 
@@ -297,57 +1319,142 @@ pub fn reset_auto_adjustment_period(period: Option<Duration>) {
 
 pub fn toggle_pool_auto_mode(key: String, auto_adjust: bool) {
     if let Ok(pool) = PoolStore::inner() {
-        if !pool.store.contains_key(&key) {
+        if !pool.store.read().contains_key(&key) {
             return;
         }
 
-        if pool.auto_adjust_register.is_empty() && !auto_adjust {
+        if pool.auto_adjust_register.read().is_empty() && !auto_adjust {
             return;
         }
 
-        if let Some(pool_info) = pool.store.get_mut(&key) {
+        if let Some(pool_info) = pool.store.write().get_mut(&key) {
             pool_info.toggle_auto_scale(auto_adjust);
         }
 
         if auto_adjust {
-            let to_launch_handler = pool.auto_adjust_register.is_empty();
+            let to_launch_handler = {
+                let mut register = pool.auto_adjust_register.write();
+                let was_empty = register.is_empty();
 
-            pool.auto_adjust_register.insert(key);
+                register.insert(key);
+                was_empty
+            };
 
             if to_launch_handler {
-                if let Some(period) = pool.auto_adjust_period {
-                    start_auto_adjustment(period);
-                } else {
-                    start_auto_adjustment(Duration::from_secs(10));
-                }
+                let period = *pool.auto_adjust_period.lock();
+                start_auto_adjustment(period.unwrap_or_else(|| Duration::from_secs(10)));
             }
         } else {
-            pool.auto_adjust_register.remove(&key);
-            if pool.auto_adjust_register.is_empty() {
+            let is_empty = {
+                let mut register = pool.auto_adjust_register.write();
+
+                register.remove(&key);
+                register.is_empty()
+            };
+
+            if is_empty {
                 stop_auto_adjustment();
             }
         }
     }
 }
 
+/// Check if the pools have been initialized and not yet closed, such that the jobs submitted through
+/// `run_with` will be taken by the pools.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::collections::HashMap;
+///
+/// assert!(!index_mode::is_initialized());
+///
+/// let mut keys = HashMap::new();
+/// keys.insert(String::from("io"), 2);
+/// index_mode::initialize(keys, None).expect("Unable to spawn the pool workers");
+/// assert!(index_mode::is_initialized());
+///
+/// index_mode::close();
+/// assert!(!index_mode::is_initialized());
+/// ```
+#[inline]
+pub fn is_initialized() -> bool {
+    PoolStore::is_some()
+}
+
 pub fn is_pool_in_auto_mode(key: String) -> bool {
     if let Ok(pool) = PoolStore::inner() {
-        return pool.auto_adjust_register.contains(&key);
+        return pool.auto_adjust_register.read().contains(&key);
     }
 
     false
 }
 
+/// Check if the background thread adjusting the pools in the auto mode is running.
+pub fn is_auto_adjustment_running() -> bool {
+    PoolStore::inner()
+        .map(|pools| pools.auto_adjust_handler.lock().is_some())
+        .unwrap_or(false)
+}
+
 fn trigger_auto_adjustment() {
     if let Ok(pools) = PoolStore::inner() {
-        if pools.auto_adjust_register.is_empty() {
-            return;
-        }
+        let mut stale = Vec::new();
 
-        for key in pools.auto_adjust_register.iter() {
-            if let Some(pool) = pools.store.get_mut(key) {
-                pool.auto_adjust();
+        {
+            let register = pools.auto_adjust_register.read();
+            if register.is_empty() {
+                return;
             }
+
+            let mut store = pools.store.write();
+            for key in register.iter() {
+                match store.get_mut(key) {
+                    Some(pool) => pool.auto_adjust(),
+                    None => stale.push(key.clone()),
+                }
+            }
+        }
+
+        // the pool may have been removed while it's being toggled into the auto mode; the handler
+        // can't be stopped from its own thread, so it idles on an empty register until stopped.
+        if !stale.is_empty() {
+            let mut register = pools.auto_adjust_register.write();
+            stale.iter().for_each(|key| {
+                register.remove(key);
+            });
+        }
+    }
+}
+
+/// Take the pools out of the store one at a time in the given order, and close each of them, which
+/// waits for its queued jobs to be done, before moving on to the next one.
+fn close_in_order(order: &[String]) {
+    let pools = match PoolStore::inner() {
+        Ok(pools) => pools,
+        Err(_) => return,
+    };
+
+    for key in order {
+        // the pool shall not be adjusted any more
+        let is_empty = {
+            let mut register = pools.auto_adjust_register.write();
+            register.remove(key) && register.is_empty()
+        };
+
+        if is_empty {
+            stop_auto_adjustment();
+        }
+
+        // don't hold the store while closing the pool, such that the jobs being drained can still
+        // submit their follow-up jobs to the pools closing later
+        let removed = pools.store.write().remove(key);
+        if let Some(mut pool) = removed {
+            pools.refresh_stealing();
+            pool.close();
         }
     }
 }
@@ -359,7 +1466,13 @@ fn shut_down(forced: bool) {
         }
         OnceState::Done => {
             if let Ok(pool_inner) = PoolStore::take() {
-                pool_inner.store.values_mut().for_each(|pool| {
+                // the pools are out of reach from now on, no need to keep adjusting them
+                let handler = pool_inner.auto_adjust_handler.lock().take();
+                if let Some(handler) = handler {
+                    handler.stop();
+                }
+
+                pool_inner.store.write().values_mut().for_each(|pool| {
                     if !forced {
                         pool.close();
                     } else {
@@ -1,31 +1,197 @@
+use std::future::Future;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr::{self, NonNull};
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::vec;
 
-use crate::config::{Config, ConfigStatus, TimeoutPolicy};
-use crate::debug::is_debug_mode;
+use crate::builder::PoolBuilder;
+use crate::cancel::{self, CancelHandle, CancellationToken};
+use crate::config::{BgThreadConfig, Config, ConfigStatus, DispatchBackend, TimeoutPolicy};
+use crate::debug::{is_debug_mode, log, Level};
+use crate::dispatcher::Dispatcher;
 use crate::manager::*;
+#[cfg(feature = "persist-metrics")]
+use crate::metrics::MetricsFlusher;
+use crate::metrics::{PoolMetrics, PoolMetricsSnapshot, PoolStats};
 use crate::model::*;
-
+use crate::scaling::{DefaultScalingPolicy, ScalingPolicy, THRESHOLD};
+use crate::scope::Scope;
+use crate::timer::{JobTimer, TaskId};
+use crate::watchdog::JobWatchdog;
+use crate::worker::current_worker_id;
+#[cfg(feature = "stack-capture")]
+use crate::stack::{self, CaptureError};
+
+use async_task::Task;
 use crossbeam_channel as channel;
-use channel::{SendError, SendTimeoutError, Sender, TryRecvError, TrySendError};
+use channel::{RecvError, RecvTimeoutError, SendError, SendTimeoutError, Sender, TrySendError};
+use parking_lot::RwLock;
 
 const RETRY_LIMIT: u8 = 4;
 const CHAN_CAP: usize = 16;
-const THRESHOLD: usize = 1024;
-const AUTO_EXTEND_TRIGGER_SIZE: usize = 2;
+
+/// How many chunks per worker a batch of jobs is split into, such that the busier workers won't
+/// hold up the whole batch.
+const BATCH_CHUNKS_PER_WORKER: usize = 4;
 
 /// Enumeration to indicate possible reasons a job execution request is rejected. User will need to
 /// resubmit the job again, since closure's state may have been stale at the execution error.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::num::NonZeroUsize;
+///
+/// let mut pool = ThreadPool::new(NonZeroUsize::new(1).unwrap());
+///
+/// // the job panics while the caller is waiting for its result
+/// match pool.sync_block(|| -> usize { panic!("boom") }) {
+///     Err(ExecutionError::Panicked) => {}
+///     _ => panic!("the job shall have panicked"),
+/// }
+///
+/// // the worker survives the panic and keeps taking jobs
+/// assert_eq!(pool.sync_block(|| 42).unwrap(), 42);
+///
+/// pool.close();
+///
+/// let err = pool.exec(|| {}, false).unwrap_err();
+/// assert!(matches!(err, ExecutionError::PoolClosed));
+/// assert_eq!(err.to_string(), "the pool is closed or closing");
+/// ```
+///
+/// The errors of the `crossbeam_channel` queues convert into the execution errors, such that the
+/// jobs handing their results back through a channel can bubble up the failures with `?`.
+///
+/// ```
+/// extern crate crossbeam_channel;
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::num::NonZeroUsize;
+/// use std::time::Duration;
+///
+/// fn fetch(pool: &ThreadPool) -> Result<usize, ExecutionError> {
+///     let (tx, rx) = crossbeam_channel::bounded(1);
+///     pool.exec(move || { let _ = tx.send(42); }, false)?;
+///
+///     Ok(rx.recv_timeout(Duration::from_secs(5))?)
+/// }
+///
+/// let pool = ThreadPool::new(NonZeroUsize::new(1).unwrap());
+/// assert_eq!(fetch(&pool).unwrap(), 42);
+///
+/// // the sender is dropped without sending the result
+/// let (tx, rx) = crossbeam_channel::bounded::<usize>(1);
+/// drop(tx);
+/// let err = ExecutionError::from(rx.recv().unwrap_err());
+/// assert!(matches!(err, ExecutionError::Disconnected));
+/// ```
+///
+/// The jobs are turned away rather than queued up for nothing once all workers have died.
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::any::Any;
+/// use std::thread;
+///
+/// // the hook takes the worker down with the panic
+/// fn fatal(_id: usize, _err: &(dyn Any + Send)) {
+///     panic!("the worker can't carry on");
+/// }
+///
+/// let mut behaviors = StatusBehaviors::default();
+/// behaviors.set_on_panic(fatal);
+///
+/// let mut config = Config::new();
+/// config.set_worker_behavior(behaviors);
+///
+/// let mut pool = ThreadPool::new_with_config(1, config);
+/// pool.exec(|| panic!("boom"), false).unwrap();
+///
+/// while pool.worker_count() > 0 {
+///     thread::yield_now();
+/// }
+///
+/// assert!(matches!(pool.exec(|| {}, false), Err(ExecutionError::PoolDead)));
+///
+/// // bring up a new worker to get the pool going again
+/// pool.extend(1);
+/// assert_eq!(pool.sync_block(|| 42).unwrap(), 42);
+/// ```
+///
+/// The job turned away by a full queue is handed back as it was submitted, such that it's only
+/// reported to the job hooks once it's actually queued.
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::mpsc;
+/// use std::thread;
+///
+/// static STARTED: AtomicUsize = AtomicUsize::new(0);
+///
+/// let mut hooks = JobBehaviors::new();
+/// hooks.set_on_job_started(|_id, _wait| { STARTED.fetch_add(1, Ordering::SeqCst); });
+///
+/// let mut config = Config::new();
+/// config.set_queue_capacity(Some(1)).set_job_behavior(hooks);
+///
+/// let pool = ThreadPool::new_with_config(1, config);
+///
+/// // keep the only worker busy, and fill up the queues
+/// let (tx, rx) = mpsc::channel::<()>();
+/// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+/// while pool.stats().active_workers == 0 {
+///     thread::yield_now();
+/// }
+///
+/// pool.exec(|| {}, true).unwrap();
+/// pool.exec(|| {}, false).unwrap();
+///
+/// let (done_tx, done_rx) = mpsc::channel();
+/// let err = pool.exec(move || done_tx.send(42).unwrap(), false).unwrap_err();
+/// assert_eq!(err.to_string(), "the job queue is full");
+///
+/// match err {
+///     ExecutionError::QueueFull(job) => job.run(),
+///     _ => panic!("the job shall be handed back"),
+/// }
+///
+/// // the job is run in place, and not reported as a pool job
+/// assert_eq!(done_rx.recv().unwrap(), 42);
+///
+/// drop(tx);
+/// pool.drain();
+/// assert_eq!(STARTED.load(Ordering::SeqCst), 3);
+/// ```
 #[derive(Debug)]
 pub enum ExecutionError {
     /// The job can't be executed because the queue is full when the new job is submitted and no new
     /// worker becomes available before predetermined timeout period.
     Timeout,
 
-    /// The pool hasn't been initialized (i.e. lazy created), or all workers have been terminated, such
+    /// The pool is closed or being closed, and takes no more jobs.
+    PoolClosed,
+
+    /// The job has panicked before producing its result.
+    Panicked,
+
+    /// The pool hasn't been initialized (i.e. lazy created), or all workers have been removed, such
     /// that there is no working threads to execute the job
     Uninitialized,
 
@@ -34,6 +200,185 @@ pub enum ExecutionError {
 
     /// Pool's internal states have been corrupted
     PoolPoisoned,
+
+    /// The shared pool has been closed or has not been initialized, and the job has been run on a
+    /// dedicated thread instead. The caller may want to restart the pool before submitting more jobs.
+    PoolGone,
+
+    /// The pool has a bounded queue capacity and the job queue is full. The job is handed back, such
+    /// that the caller can resubmit it later, or run it in place.
+    QueueFull(RejectedJob),
+
+    /// The worker with the id that the job is pinned to has quit or is quitting, or there's no such
+    /// worker in the pool. The job is not run by any other worker.
+    WorkerGone(usize),
+
+    /// The shared pool is paused and takes no new jobs until it's resumed, while the jobs taken
+    /// before the pause are still run.
+    PoolPaused,
+
+    /// All workers of the pool have died, e.g. from a panicking `on_panic` hook, and the job would
+    /// never be run. The pool shall be resized or recreated to take new jobs.
+    PoolDead,
+
+    /// The thread to run the job can't be spawned, e.g. the OS refuses to create more threads.
+    SpawnFailed(io::Error),
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            ExecutionError::Timeout => "timed out waiting for the job to be queued or done",
+            ExecutionError::PoolClosed => "the pool is closed or closing",
+            ExecutionError::Panicked => "the job has panicked",
+            ExecutionError::Uninitialized => "the pool has no worker to run the job",
+            ExecutionError::Disconnected => "the job queue is disconnected",
+            ExecutionError::PoolPoisoned => "the pool states are corrupted",
+            ExecutionError::PoolGone => "the shared pool is gone, the job is run on its own thread",
+            ExecutionError::QueueFull(_) => "the job queue is full",
+            ExecutionError::WorkerGone(id) => return write!(f, "the worker {} is gone", id),
+            ExecutionError::PoolPaused => "the pool is paused and takes no new jobs",
+            ExecutionError::PoolDead => "all workers of the pool have died",
+            ExecutionError::SpawnFailed(err) => {
+                return write!(f, "unable to spawn the thread for the job: {}", err)
+            }
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl Error for ExecutionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExecutionError::SpawnFailed(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<T> From<SendError<T>> for ExecutionError {
+    fn from(_: SendError<T>) -> Self {
+        ExecutionError::Disconnected
+    }
+}
+
+impl From<TrySendError<Message>> for ExecutionError {
+    fn from(err: TrySendError<Message>) -> Self {
+        match err {
+            TrySendError::Full(message) => ExecutionError::QueueFull(RejectedJob::new(message)),
+            TrySendError::Disconnected(_) => ExecutionError::Disconnected,
+        }
+    }
+}
+
+/// The job turned away by the pool with `ExecutionError::QueueFull`, as it was submitted. It can be
+/// resubmitted once the queue has the space, or be run in place.
+pub struct RejectedJob(Message);
+
+impl RejectedJob {
+    fn new(message: Message) -> Self {
+        RejectedJob(message.unstamped())
+    }
+
+    /// Run the job on the current thread. The jobs of a batch are run one after another.
+    pub fn run(self) {
+        if let Some(job) = self.0.into_job() {
+            job.call_box();
+        }
+    }
+}
+
+impl fmt::Debug for RejectedJob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RejectedJob")
+    }
+}
+
+impl<T> From<SendTimeoutError<T>> for ExecutionError {
+    fn from(err: SendTimeoutError<T>) -> Self {
+        match err {
+            SendTimeoutError::Timeout(_) => ExecutionError::Timeout,
+            SendTimeoutError::Disconnected(_) => ExecutionError::Disconnected,
+        }
+    }
+}
+
+impl From<RecvError> for ExecutionError {
+    fn from(_: RecvError) -> Self {
+        ExecutionError::Disconnected
+    }
+}
+
+impl From<RecvTimeoutError> for ExecutionError {
+    fn from(err: RecvTimeoutError) -> Self {
+        match err {
+            RecvTimeoutError::Timeout => ExecutionError::Timeout,
+            RecvTimeoutError::Disconnected => ExecutionError::Disconnected,
+        }
+    }
+}
+
+/// The error of initializing the `shared_mode` or the `index_mode` pools more than once, which is
+/// wrapped in an `io::Error` of the `AlreadyExists` kind. The pools can't be initialized again even
+/// after they're closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlreadyInitialized;
+
+impl fmt::Display for AlreadyInitialized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the pool has already been initialized")
+    }
+}
+
+impl Error for AlreadyInitialized {}
+
+impl From<AlreadyInitialized> for io::Error {
+    fn from(err: AlreadyInitialized) -> Self {
+        io::Error::new(io::ErrorKind::AlreadyExists, err)
+    }
+}
+
+/// The error of a batch submission: why the pool has rejected the batch, and how many jobs of the
+/// batch have not been queued.
+#[derive(Debug)]
+pub struct BatchError {
+    pub error: ExecutionError,
+    pub rejected: usize,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} jobs of the batch are not queued: {}", self.rejected, self.error)
+    }
+}
+
+impl Error for BatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The priority levels of the jobs. Higher priority jobs are picked up first, though the workers
+/// will still pick up a lower priority job every now and then, such that it won't starve when higher
+/// priority jobs are continuously submitted. Jobs of the same priority are run in the submission
+/// order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// The id of the job queue for the priority level.
+    fn queue_id(self) -> u8 {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
 }
 
 /// The standalone thread pool, which gives users more controls on the pool and where the hosted pool
@@ -64,18 +409,21 @@ pub enum ExecutionError {
 ///     let priority = if id % 2 == 0 { true } else { false };
 ///
 ///     // API `exec` can set if a job needs to be prioritized for execution or not.
-///     pool.exec(|| {
+///     pool.exec(move || {
 ///         thread::sleep(Duration::from_secs(1));
 ///         println!("thread {} has slept for around 1 second ... ", id);
 ///     }, priority);
 /// }
 /// ```
 pub struct ThreadPool {
-    /// The worker manager struct, hide details of managing workers.
-    manager: Manager,
+    /// The worker manager struct, hide details of managing workers. The manager is guarded by the
+    /// lock such that jobs can be submitted to a shared pool, where workers may be lazily created or
+    /// automatically added when the pool is under pressure.
+    manager: RwLock<Manager>,
 
-    /// The job queue deliverer. chan.0 is the priority channel, chan.1 is the normal channel.
-    chan: (Sender<Message>, Sender<Message>),
+    /// The job queue deliverer. chan.0 is the priority channel, chan.1 is the normal channel, and
+    /// chan.2 is the low priority channel.
+    chan: (Sender<Message>, Sender<Message>, Sender<Message>),
 
     /// Initial pool size. This will be used to create worker threads when the pool is lazy created,
     /// among a few other things
@@ -102,9 +450,23 @@ pub struct ThreadPool {
     /// around for a while, and eventually retire after being idle for a period of time.
     auto_scale: bool,
 
+    /// The custom policy to decide the pool size on `auto_adjust`. If not set, the built-in heuristic
+    /// will be used.
+    scaling_policy: Option<Arc<dyn ScalingPolicy>>,
+
+    /// The bounds of the pool size when it's scaled, either on `auto_adjust` or under pressure: the
+    /// pool won't shrink below `min_workers`, or grow beyond `max_workers` if it's set, whatever the
+    /// scaling policy says.
+    min_workers: usize,
+    max_workers: Option<usize>,
+
     /// Determine if the job handover shall return immediately, i.e. in `non-blocking` mode.
     non_blocking: bool,
 
+    /// The capacity of each job queue, if set by the user. In this mode, `exec` will reject the jobs
+    /// with `ExecutionError::QueueFull` instead of blocking the caller when the queue is full.
+    queue_capacity: Option<usize>,
+
     /// Set the timeout period before we retry or give up executing the submitted job. If leaving this
     /// field for `None`, the job submission will block the caller until a thread worker is freed up
     /// in blocking mode, or return with error immediately if in the non-blocking mode (i.e.
@@ -118,25 +480,108 @@ pub struct ThreadPool {
     ///     3. LossyRetry -> If we choose to drop oldest tasks when the pool is full, such that we
     ///                      will not block the channels;
     timeout_policy: TimeoutPolicy,
+
+    /// The callback to take the error of a job that has failed all its attempts in `exec_retry`.
+    retry_failure: Option<RetryFailure>,
+
+    /// The hooks to trace the jobs, if any of them is set.
+    job_behaviors: Option<JobBehaviors>,
+
+    /// The job counters shared by all workers of the pool.
+    metrics: Arc<PoolMetrics>,
+
+    /// The local queues of the workers, to check if they're empty without the manager lock.
+    local_queues: StealRegistry,
+
+    /// The background thread to hold the delayed jobs and to check on the overdue jobs, started with
+    /// the pool if the job timeout is set, or else with the first delayed job.
+    timer: OnceLock<JobTimer>,
+
+    /// The settings of the background threads started on demand.
+    bg_config: BgThreadConfig,
+
+    /// How many items each job of `map` takes at a time, or `None` to split the items by the size
+    /// of the pool.
+    map_chunk_size: Option<usize>,
+
+    /// The number of workers found stuck with the overdue jobs, which will be replaced by the new
+    /// workers when taking the next job, if the pool is allowed to auto scale.
+    stuck_workers: Arc<AtomicUsize>,
+
+    /// Set once the pool is closed, such that the dispatchers handed out will reject the jobs.
+    closed: Arc<AtomicBool>,
+
+    /// The maintenance thread that persists the metrics, if configured.
+    #[cfg(feature = "persist-metrics")]
+    metrics_flusher: Option<MetricsFlusher>,
 }
 
 impl ThreadPool {
-    /// Create a `ThreadPool` with default configurations
-    pub fn new(size: usize) -> ThreadPool {
-        Self::create_pool(size, Config::default(), false)
+    /// Create a `ThreadPool` with default configurations. If the OS refuses to spawn any worker
+    /// thread at the moment, the pool will fall back to the lazy created mode, and retry spawning
+    /// the workers on the first `exec` call. The size is a `NonZeroUsize`, such that a pool of zero
+    /// workers, which used to be quietly turned into one worker, is caught where it's made.
+    pub fn new(size: NonZeroUsize) -> ThreadPool {
+        Self::new_with_config(size.get(), Config::default())
     }
 
-    /// Create a `ThreadPool` with supplied configurations
+    /// Create a `ThreadPool` with supplied configurations. If the OS refuses to spawn any worker
+    /// thread at the moment, the pool will fall back to the lazy created mode, and retry spawning
+    /// the workers on the first `exec` call.
     pub fn new_with_config(size: usize, config: Config) -> ThreadPool {
+        Self::try_new_with_config(size, config.clone()).unwrap_or_else(|err| {
+            if is_debug_mode() {
+                log(
+                    Level::Warn,
+                    format_args!(
+                        "Unable to spawn the workers with {:?}, falling back to lazy creation: {}",
+                        config,
+                        err
+                    ),
+                );
+            }
+
+            Self::build_with_config(size, config)
+        })
+    }
+
+    /// Create a `ThreadPool` with default configurations, or return the error if the OS refuses to
+    /// spawn any worker thread. If only some of the workers can be spawned, the pool will run with
+    /// the workers that have been created.
+    pub fn try_new(size: usize) -> io::Result<ThreadPool> {
+        Self::create_pool(size, Config::default(), false)
+    }
+
+    /// Create a `ThreadPool` with supplied configurations, or return the error if the OS refuses to
+    /// spawn any worker thread. If only some of the workers can be spawned, the pool will run with
+    /// the workers that have been created.
+    pub fn try_new_with_config(size: usize, config: Config) -> io::Result<ThreadPool> {
         Self::create_pool(size, config, false)
     }
 
+    /// Start configuring a `ThreadPool` with the chainable `PoolBuilder`, as an alternative to
+    /// setting up the `Config` for `new_with_config`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    ///
+    /// let pool = ThreadPool::builder().size(4).name("io").build();
+    /// assert_eq!(pool.get_size(), 4);
+    /// ```
+    pub fn builder() -> PoolBuilder {
+        PoolBuilder::new()
+    }
+
     /// Create the `ThreadPool` with default pool configuration settings. When ready to activate the
     /// pool, invoke the `activate_pool` API before submitting jobs for execution. You can also
     /// call `exec` API to automatically activate the pool, however, calling the alternative immutable
     /// API `execute` will always lead to an error.
     pub fn build(size: usize) -> ThreadPool {
-        Self::create_pool(size, Config::default(), true)
+        Self::build_with_config(size, Config::default())
     }
 
     /// Create the `ThreadPool` with provided pool configuration settings. When ready to activate the
@@ -144,13 +589,13 @@ impl ThreadPool {
     /// call `exec` API to automatically activate the pool, however, calling the alternative immutable
     /// API `execute` will always lead to an error.
     pub fn build_with_config(size: usize, config: Config) -> ThreadPool {
-        Self::create_pool(size, config, true)
+        Self::create_pool(size, config, true).expect("lazy created pool shall not spawn any worker")
     }
 
     /// If the pool is lazy created, user is responsible for activating the pool before submitting jobs
-    /// for execution. API `exec` will initialized the pool since it takes the mutable `self`, and hence
-    /// is able to initialize the pool. However, fail to explicitly initialize the pool could cause
-    /// unidentified behaviors.
+    /// for execution. API `exec` will initialize the pool if that's not done yet, while the API
+    /// `execute` will not, hence fail to explicitly initialize the pool could cause unidentified
+    /// behaviors.
     ///
     /// # Examples
     ///
@@ -177,13 +622,13 @@ impl ThreadPool {
     /// // do parallel stuff.
     /// for id in 0..10 {
     ///     // API `exec` can also activate the pool automatically if it's lazy-created.
-    ///     pool.exec(|| {
+    ///     pool.exec(move || {
     ///         thread::sleep(Duration::from_secs(1));
     ///         println!("thread {} has been waken after 1 seconds ... ", id);
     ///     }, true);
     /// }
     /// ```
-    pub fn activate(&mut self) -> &mut Self {
+    pub fn activate(&self) -> &Self {
         debug_assert!(
             self.init_size > 0,
             "The initial pool size must be equal or larger than 1 ..."
@@ -192,25 +637,39 @@ impl ThreadPool {
         let status = self.status.load();
 
         // No need or can't activate ...
-        if status != FLAG_HIBERNATING || status != FLAG_LAZY_INIT {
+        if status != FLAG_HIBERNATING && status != FLAG_LAZY_INIT {
             return self;
         }
 
         // Wake up everybody
         if status == FLAG_HIBERNATING {
-            // call to unhibernate, the `unhibernate` API will handle the status updates
-            self.unhibernate();
+            // the `wake_up_pool` API will handle the status updates
+            self.wake_up_pool();
             return self;
         }
 
-        // Only case to handle: the pool is lazy created, than we need to add workers now.
-        let workers_count = self.manager.workers_count();
+        // Only case to handle: the pool is lazy created, than we need to add workers now. Hold the
+        // write lock while checking such that concurrent submissions won't double the workers.
+        let mut manager = self.manager.write();
+        let workers_count = manager.workers_count();
         if workers_count < self.init_size {
             // lazy init the pool at the first job, or regenerate workers when all are purged
-            self.manager
-                .add_workers(self.init_size - workers_count, true, self.status.clone());
+            let res = manager.fill_workers(self.init_size, self.status.clone());
+
+            if let Err(err) = res {
+                if is_debug_mode() {
+                    log(Level::Warn, format_args!("Unable to activate the pool: {}", err));
+                }
+
+                // stay in the lazy mode such that we can retry on the next job
+                if manager.workers_count() == 0 {
+                    return self;
+                }
+            }
         }
 
+        drop(manager);
+
         // update the status after activation.
         self.set_status(FLAG_NORMAL);
         self
@@ -237,177 +696,2075 @@ impl ThreadPool {
         self.auto_scale = auto_scale;
     }
 
-    /// `exec` will dispatch a closure to a free thread to be executed. If no thread are free at the
-    /// moment and the pool setting allow adding new workers at pressure, some temporary workers will
-    /// be created and added to the pool for executing the accumulated pending jobs; otherwise, this
-    /// API will work the same way as the alternative: `execute`, that it will block the caller until
-    /// a worker becomes available, or the job queue timed out, where the job will be dropped and the
-    /// caller needs to send the job to queue for execution again, if it's needed.
-    ///
-    /// For highly competitive environments, such as using the pool to enable async operations for a
-    /// web server, disabling `auto_scale` could cause starvation deadlock. However, a server can only
-    /// have a limited resources for disposal, we can't create unlimited workers, there will be a
-    /// limit where we have to either choose to drop the job, or put it into a queue for later execution,
-    /// though that will mostly lie on caller's discretion.
-    ///
-    /// In addition, this API will take a `prioritized` parameter, which will allow more urgent job
-    /// to be taken by the workers sooner than jobs without a priority.
-    ///
-    /// Note that if the `ThreadPool` is created using delayed pool initialization, i.e. created using
-    /// either `build` or `build_with_config` APIs, then the pool will be initialized at
-    /// the first time a job is to be executed.
+    /// Register the background task that idle workers will donate their cycles to. A worker that has
+    /// been idle longer than the configured idle task threshold will invoke the task with a small
+    /// time budget, and get back to the job queues as soon as new jobs arrive. At most one worker
+    /// will be running the idle task at any given time.
     ///
     /// # Examples
     ///
     /// ```
     /// extern crate threads_pool;
-    /// use threads_pool as pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
     /// use std::thread;
     /// use std::time::Duration;
     ///
-    /// let mut pool = pool::ThreadPool::build(4);
+    /// struct Compaction(AtomicUsize);
     ///
-    /// for id in 0..10 {
-    ///     pool.exec(|| {
-    ///         thread::sleep(Duration::from_secs(1));
-    ///         println!("thread {} has been waken after 1 seconds ... ", id);
-    ///     }, true);
+    /// impl IdleTask for Compaction {
+    ///     fn run_some(&self, _budget: Duration, should_stop: &dyn Fn() -> bool) {
+    ///         if !should_stop() {
+    ///             self.0.fetch_add(1, Ordering::SeqCst);
+    ///         }
+    ///     }
     /// }
+    ///
+    /// let mut config = Config::default();
+    /// config.set_idle_task_threshold(Duration::from_millis(10));
+    ///
+    /// let pool = ThreadPool::new_with_config(2, config);
+    /// let task = Arc::new(Compaction(AtomicUsize::new(0)));
+    /// pool.set_idle_task(task.clone());
+    ///
+    /// // the idle pool shall make some progress on the background work
+    /// thread::sleep(Duration::from_millis(200));
+    /// assert!(task.0.load(Ordering::SeqCst) > 0);
     /// ```
-    pub fn exec<F: FnOnce() + Send + 'static>(
-        &mut self,
-        f: F,
-        prioritized: bool,
-    ) -> Result<(), ExecutionError> {
-        let status = self.status.load();
-
-        // we're closing the pool, take no more new jobs
-        if status == FLAG_CLOSING || status == FLAG_FORCE_CLOSE {
-            return Err(ExecutionError::Disconnected);
-        }
-
-        // if at the hibernation or lazy init mode, activate the pool first
-        if status == FLAG_HIBERNATING || status == FLAG_LAZY_INIT {
-            self.activate();
-        }
-
-        // still no worker to take the job? unexpected and should return error
-        let worker_count = self.manager.workers_count();
-        if worker_count == 0 {
-            return Err(ExecutionError::Uninitialized);
-        }
-
-        // if we can auto scale the pool, set the retry stack limit
-        let retry = if self.auto_scale { 1 } else { 0 };
-
-        // send the job for execution
-        self.dispatch(Message::SingleJob(Box::new(f)), retry, prioritized)
-            .map(|busy| {
-                if busy && self.auto_scale {
-                    // auto scale by adding more workers to take the job
-                    if let Some(target) = self.amortized_new_size(self.init_size) {
-                        self.manager
-                            .add_workers(target - worker_count, false, self.status.clone());
-                    }
-                }
-            })
-            .map_err(|err| match err {
-                SendTimeoutError::Timeout(_) => ExecutionError::Timeout,
-                SendTimeoutError::Disconnected(_) => ExecutionError::Disconnected,
-            })
-    }
-
-    /// Similar to `exec`, yet this is the simplified version taking an immutable version of the pool.
-    /// The job priority will be automatically evaluated based on the queue length, and for pool
-    /// under pressure, we will try to balance the queue.
     ///
-    /// This method will be no-op if the pool is lazy-created (i.e. created via the `build` functions)
-    /// and `activate` has not been called on it yet. If you are not sure if the pool has been
-    /// activated or not, alter to use `exec` instead, which will initiate the pool if that's not
-    /// done yet.
+    /// A busy idle task gives way to the jobs right away, and never holds back the shutdown:
     ///
-    /// # Examples
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::Arc;
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// // spin through the whole budget unless told to stop
+    /// struct Warming;
+    ///
+    /// impl IdleTask for Warming {
+    ///     fn run_some(&self, budget: Duration, should_stop: &dyn Fn() -> bool) {
+    ///         let start = Instant::now();
+    ///         while start.elapsed() < budget && !should_stop() {}
+    ///     }
+    /// }
+    ///
+    /// let mut config = Config::default();
+    /// config.set_idle_task_threshold(Duration::from_millis(1));
+    ///
+    /// let mut pool = ThreadPool::new_with_config(2, config);
+    /// pool.set_idle_task(Arc::new(Warming));
+    /// thread::sleep(Duration::from_millis(100));
+    ///
+    /// // a burst of jobs interrupts the idle task, and the jobs are picked up within a few ms
+    /// let (tx, rx) = mpsc::channel();
+    /// for _ in 0..16 {
+    ///     let tx = tx.clone();
+    ///     let submitted = Instant::now();
+    ///     pool.exec(move || tx.send(submitted.elapsed()).unwrap(), false).unwrap();
+    /// }
+    ///
+    /// let slowest = rx.iter().take(16).max().unwrap();
+    /// assert!(slowest < Duration::from_millis(50), "pickup took {:?}", slowest);
+    ///
+    /// // back to idle, then close the pool: the idle task can't delay the shutdown
+    /// thread::sleep(Duration::from_millis(100));
+    ///
+    /// let start = Instant::now();
+    /// pool.close();
+    /// assert!(start.elapsed() < Duration::from_millis(100), "close took {:?}", start.elapsed());
+    /// ```
+    ///
+    /// A panicking idle task doesn't lock the other workers out of the background work:
     ///
     /// ```
     /// extern crate threads_pool;
-    /// use threads_pool as pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
     /// use std::thread;
     /// use std::time::Duration;
     ///
-    /// let pool = pool::ThreadPool::build(4);
+    /// struct Flaky(AtomicUsize);
     ///
-    /// for id in 0..10 {
-    ///     pool.execute(|| {
-    ///         thread::sleep(Duration::from_secs(1));
-    ///         println!("thread {} has been waken after 1 seconds ... ", id);
-    ///     });
+    /// impl IdleTask for Flaky {
+    ///     fn run_some(&self, _budget: Duration, _should_stop: &dyn Fn() -> bool) {
+    ///         if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+    ///             panic!("the first round fails");
+    ///         }
+    ///     }
     /// }
-    /// ```
-    pub fn execute<F: FnOnce() + Send + 'static>(&self, f: F) -> Result<(), ExecutionError> {
+    ///
+    /// let mut config = Config::default();
+    /// config.set_idle_task_threshold(Duration::from_millis(10));
+    ///
+    /// let pool = ThreadPool::new_with_config(2, config);
+    /// let task = Arc::new(Flaky(AtomicUsize::new(0)));
+    /// pool.set_idle_task(task.clone());
+    ///
+    /// thread::sleep(Duration::from_millis(200));
+    /// assert!(task.0.load(Ordering::SeqCst) > 1);
+    /// ```
+    ///
+    /// The panic is caught and reported like one from a job, and the worker carries on:
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// struct Broken;
+    ///
+    /// impl IdleTask for Broken {
+    ///     fn run_some(&self, _budget: Duration, _should_stop: &dyn Fn() -> bool) {
+    ///         panic!("the idle task always fails");
+    ///     }
+    /// }
+    ///
+    /// static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// fn caught(_id: usize, _err: &(dyn std::any::Any + Send)) {
+    ///     CAUGHT.fetch_add(1, Ordering::SeqCst);
+    /// }
+    ///
+    /// let mut config = Config::default();
+    /// config.set_idle_task_threshold(Duration::from_millis(1));
+    /// config.set_on_panic(caught);
+    ///
+    /// let pool = ThreadPool::new_with_config(2, config);
+    /// pool.set_idle_task(Arc::new(Broken));
+    ///
+    /// // each worker would have died on its first donation if the panic wasn't caught
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while CAUGHT.load(Ordering::SeqCst) < 8 && Instant::now() < deadline {
+    ///     thread::sleep(Duration::from_millis(5));
+    /// }
+    ///
+    /// assert!(CAUGHT.load(Ordering::SeqCst) >= 8);
+    /// assert_eq!(pool.stats().workers_alive, 2);
+    ///
+    /// // and the pool still runs the jobs
+    /// pool.clear_idle_task();
+    /// let (tx, rx) = mpsc::channel();
+    /// pool.exec(move || tx.send(42).unwrap(), false).unwrap();
+    /// assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(42));
+    /// ```
+    pub fn set_idle_task(&self, task: Arc<dyn IdleTask>) {
+        self.manager.read().set_idle_task(Some(task));
+    }
+
+    /// Remove the registered idle task, if any.
+    pub fn clear_idle_task(&self) {
+        self.manager.read().set_idle_task(None);
+    }
+
+    /// Switch the way the workers take jobs from the pool, without restarting the pool. The workers
+    /// stop moving the jobs to their local deques first, and the call blocks until they have run the
+    /// jobs left there, before the workers are switched over to the new backend. The jobs waiting in
+    /// the shared queues are served by both backends, and will be taken up by the new backend, so no
+    /// job is lost across the switch. New jobs are held back while the local deques are drained.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// let mut config = Config::new();
+    /// config.set_dispatch_backend(DispatchBackend::Channel);
+    ///
+    /// let pool = Arc::new(ThreadPool::new_with_config(4, config));
+    /// assert_eq!(pool.dispatch_backend(), DispatchBackend::Channel);
+    ///
+    /// let done = Arc::new(AtomicUsize::new(0));
+    /// let producer = {
+    ///     let (pool, done) = (Arc::clone(&pool), Arc::clone(&done));
+    ///     thread::spawn(move || {
+    ///         for _ in 0..3000 {
+    ///             let done = Arc::clone(&done);
+    ///             pool.exec(move || { done.fetch_add(1, Ordering::SeqCst); }, false).unwrap();
+    ///         }
+    ///     })
+    /// };
+    ///
+    /// // switch back and forth while the jobs keep coming
+    /// while done.load(Ordering::SeqCst) < 1000 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// pool.switch_backend(DispatchBackend::Deque);
+    /// assert_eq!(pool.dispatch_backend(), DispatchBackend::Deque);
+    ///
+    /// while done.load(Ordering::SeqCst) < 2000 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// // the jobs in the local deques have been run by the time the pool is back on the channels
+    /// pool.switch_backend(DispatchBackend::Channel);
+    /// assert_eq!(pool.dispatch_backend(), DispatchBackend::Channel);
+    ///
+    /// producer.join().unwrap();
+    ///
+    /// pool.drain();
+    /// assert_eq!(done.load(Ordering::SeqCst), 3000);
+    /// ```
+    pub fn switch_backend(&self, backend: DispatchBackend) {
+        // hold back the new jobs while switching
+        let manager = self.manager.write();
+        if manager.dispatch_backend() == backend {
+            return;
+        }
+
+        // stop refilling the local deques, such that they can be drained
+        manager.set_dispatch_backend(DispatchBackend::Channel);
+
+        if manager.workers_count() > 0
+            && self.status.load() & (FLAG_CLOSING | FLAG_FORCE_CLOSE | FLAG_HIBERNATING) == 0
+        {
+            // wait for the workers to run out of the jobs in their local deques, which is checked
+            // again each time a worker has taken a job
+            self.metrics.wait_idle_until(None, || self.local_queues.all_empty());
+        }
+
+        manager.set_dispatch_backend(backend);
+    }
+
+    /// The way the workers take jobs from the pool at the moment, see `switch_backend`.
+    pub fn dispatch_backend(&self) -> DispatchBackend {
+        self.manager.read().dispatch_backend()
+    }
+
+    /// The receiving ends of the pool's job queues, for the sibling pools to steal jobs from.
+    pub(crate) fn job_queues(&self) -> JobQueues {
+        self.manager.read().job_queues()
+    }
+
+    /// Let the idle workers steal jobs from the given job queues of the sibling pools.
+    pub(crate) fn set_sibling_queues(&self, queues: Vec<JobQueues>) {
+        self.manager.read().set_sibling_queues(queues);
+    }
+
+    /// The number of jobs waiting in the job queues of the pool, regardless of their priorities.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::mpsc;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(1).unwrap());
+    /// let (tx, rx) = mpsc::channel::<()>();
+    ///
+    /// // occupy the only worker, then queue up more jobs behind it
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// while pool.stats().active_workers == 0 {
+    ///     std::thread::yield_now();
+    /// }
+    ///
+    /// pool.exec_with_priority(|| {}, Priority::High).unwrap();
+    /// pool.exec_with_priority(|| {}, Priority::Normal).unwrap();
+    /// pool.exec_with_priority(|| {}, Priority::Low).unwrap();
+    /// assert_eq!(pool.pending_jobs(), 3);
+    ///
+    /// drop(tx);
+    /// ```
+    pub fn pending_jobs(&self) -> usize {
+        self.chan.0.len() + self.chan.1.len() + self.chan.2.len()
+    }
+
+    /// Check how busy the pool is at the moment: the number of workers, how many of them are running
+    /// a job, and the depth of the job queues. This can be used to drive custom scaling decisions
+    /// instead of the built-in `auto_adjust`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::mpsc;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// let (tx, rx) = mpsc::channel::<()>();
+    ///
+    /// // occupy one worker until we hang up
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// while pool.stats().active_workers == 0 {
+    ///     std::thread::yield_now();
+    /// }
+    ///
+    /// let stats = pool.stats();
+    /// assert_eq!(stats.worker_count, 2);
+    /// assert_eq!(stats.workers_alive, 2);
+    /// assert_eq!(stats.active_workers, 1);
+    ///
+    /// drop(tx);
+    ///
+    /// // the counters of the jobs run so far, and how long the workers have been idle since
+    /// pool.wait_idle();
+    /// std::thread::sleep(std::time::Duration::from_millis(50));
+    ///
+    /// let stats = pool.stats();
+    /// assert_eq!(stats.jobs_pending, 0);
+    /// assert!(stats.jobs_completed >= 1);
+    /// assert!(stats.avg_idle_ms >= 50);
+    /// ```
+    pub fn stats(&self) -> PoolStats {
+        let manager = self.manager.read();
+
+        PoolStats {
+            worker_count: manager.workers_count(),
+            active_workers: self.metrics.active_workers(),
+            queued_normal: self.chan.1.len(),
+            queued_priority: self.chan.0.len(),
+            queued_low: self.chan.2.len(),
+            workers_alive: manager.live_workers_count(),
+            jobs_pending: self.pending_jobs(),
+            jobs_completed: self.metrics.jobs_completed(),
+            avg_idle_ms: manager.job_clocks().average_idle().as_millis() as u64,
+        }
+    }
+
+    /// Check if the pool has any worker alive to run the jobs. A supervisor can use this to find out
+    /// a pool whose workers have all quit, and rebuild it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    ///
+    /// let mut pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// assert!(pool.is_healthy());
+    /// assert_eq!(pool.worker_count(), 2);
+    ///
+    /// // the lazily built pool has no worker until it's activated
+    /// let lazy = ThreadPool::build(2);
+    /// assert!(!lazy.is_healthy());
+    ///
+    /// pool.clear();
+    /// assert!(!pool.is_healthy());
+    /// assert_eq!(pool.worker_count(), 0);
+    /// ```
+    #[inline]
+    pub fn is_healthy(&self) -> bool {
+        self.worker_count() > 0
+    }
+
+    /// Block the caller until all jobs submitted so far have been done, while keeping the pool alive
+    /// for more jobs afterwards, unlike `close`. This is the same as `PoolManager::drain`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// let done = Arc::new(AtomicUsize::new(0));
+    ///
+    /// for _ in 0..10 {
+    ///     let done = Arc::clone(&done);
+    ///     pool.exec(move || {
+    ///         thread::sleep(Duration::from_millis(2));
+    ///         done.fetch_add(1, Ordering::SeqCst);
+    ///     }, false).unwrap();
+    /// }
+    ///
+    /// pool.wait_idle();
+    /// assert_eq!(done.load(Ordering::SeqCst), 10);
+    ///
+    /// // the pool is still open for business
+    /// let done_too = Arc::clone(&done);
+    /// pool.exec(move || { done_too.fetch_add(1, Ordering::SeqCst); }, false).unwrap();
+    /// pool.wait_idle();
+    /// assert_eq!(done.load(Ordering::SeqCst), 11);
+    /// ```
+    #[inline]
+    pub fn wait_idle(&self) {
+        self.wait_drained(None);
+    }
+
+    /// Block the caller like `wait_idle`, but for no longer than the `timeout`. Return `true` if all
+    /// jobs submitted so far have been done, or `false` if the timeout is reached first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::mpsc;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(1).unwrap());
+    /// let (tx, rx) = mpsc::channel::<()>();
+    ///
+    /// // the only worker is held up until we hang up
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// assert!(!pool.wait_idle_timeout(Duration::from_millis(20)));
+    ///
+    /// drop(tx);
+    /// assert!(pool.wait_idle_timeout(Duration::from_secs(5)));
+    /// ```
+    pub fn wait_idle_timeout(&self, timeout: Duration) -> bool {
+        self.wait_drained(Some(Instant::now() + timeout))
+    }
+
+    /// Check if the pool has nothing to do at the moment: no job is waiting in the job queues or in
+    /// the workers' local queues, and no worker is running a job. The jobs held by the timer for a
+    /// later time are not counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::{mpsc, Arc};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// assert!(pool.is_idle());
+    ///
+    /// let (tx, rx) = mpsc::channel::<()>();
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// while pool.stats().active_workers == 0 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// assert!(!pool.is_idle());
+    /// drop(tx);
+    ///
+    /// // the pool is never seen idle while a job has been taken off the queue but is not yet done
+    /// let done = Arc::new(AtomicUsize::new(0));
+    /// for round in 1..=500 {
+    ///     let counter = Arc::clone(&done);
+    ///     pool.exec(move || { counter.fetch_add(1, Ordering::SeqCst); }, false).unwrap();
+    ///
+    ///     assert!(pool.wait_for_idle(Duration::from_secs(5)));
+    ///     assert_eq!(done.load(Ordering::SeqCst), round);
+    /// }
+    /// ```
+    pub fn is_idle(&self) -> bool {
+        // checked in the order the jobs move along, from the queues to the workers' hands
+        self.pending_jobs() == 0 && self.is_settled()
+    }
+
+    /// Check if no job is waiting in the workers' local queues or in their hands, and no worker is
+    /// running a job. A job taken off the queues counts as being taken from right before the take,
+    /// until it's started, such that it can't slip through between the checks.
+    fn is_settled(&self) -> bool {
+        self.local_queues.all_empty()
+            && self.metrics.taking_workers() == 0
+            && self.metrics.active_workers() == 0
+    }
+
+    /// Block the caller until the pool is idle, see `is_idle`, or until the `timeout` has passed.
+    /// Return `true` if the pool has become idle. Unlike `wait_idle_timeout`, which only waits for
+    /// the jobs submitted so far, this also waits for the jobs that the running jobs submit, and
+    /// the jobs those submit in turn, until the whole cascade has drained.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::{mpsc, Arc};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// fn fan_out(pool: Arc<ThreadPool>, done: Arc<AtomicUsize>, depth: usize) {
+    ///     thread::sleep(Duration::from_millis(1));
+    ///     done.fetch_add(1, Ordering::SeqCst);
+    ///
+    ///     if depth > 0 {
+    ///         for _ in 0..2 {
+    ///             let (next, done) = (Arc::clone(&pool), Arc::clone(&done));
+    ///             pool.exec(move || fan_out(next, done, depth - 1), false).unwrap();
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let pool = Arc::new(ThreadPool::new(NonZeroUsize::new(2).unwrap()));
+    /// let done = Arc::new(AtomicUsize::new(0));
+    ///
+    /// // each job submits 2 more jobs, 4 levels deep
+    /// let (next, count) = (Arc::clone(&pool), Arc::clone(&done));
+    /// pool.exec(move || fan_out(next, count, 4), false).unwrap();
+    ///
+    /// assert!(pool.wait_for_idle(Duration::from_secs(5)));
+    /// assert_eq!(done.load(Ordering::SeqCst), 31);
+    ///
+    /// // the only worker is held up until we hang up
+    /// let (tx, rx) = mpsc::channel::<()>();
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// assert!(!pool.wait_for_idle(Duration::from_millis(20)));
+    ///
+    /// drop(tx);
+    /// assert!(pool.wait_for_idle(Duration::from_secs(5)));
+    /// ```
+    pub fn wait_for_idle(&self, timeout: Duration) -> bool {
+        self.metrics.wait_idle_until(Some(Instant::now() + timeout), || self.is_idle())
+    }
+
+    /// Capture the stack of a worker that's running a job, which is useful to find out where a stuck
+    /// worker is stuck at without attaching a debugger. This is a best-effort diagnostics only
+    /// available on Linux with the `stack-capture` feature. Please check the safety constraints on
+    /// the dedicated signal in the crate's documentation before turning this on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::mpsc;
+    ///
+    /// #[inline(never)]
+    /// fn stuck_outer(rx: mpsc::Receiver<()>) {
+    ///     stuck_inner(rx);
+    /// }
+    ///
+    /// #[inline(never)]
+    /// fn stuck_inner(rx: mpsc::Receiver<()>) {
+    ///     let _ = rx.recv();
+    /// }
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// let (tx, rx) = mpsc::channel();
+    /// pool.exec(move || stuck_outer(rx), false).unwrap();
+    ///
+    /// while pool.stats().active_workers == 0 {
+    ///     std::thread::yield_now();
+    /// }
+    ///
+    /// let first = pool.get_first_worker_id().unwrap();
+    /// let last = pool.get_last_worker_id().unwrap();
+    /// let mut captured = Vec::new();
+    /// let mut idle = 0;
+    ///
+    /// for id in first..=last {
+    ///     match pool.capture_worker_stack(id) {
+    ///         Ok(frames) => captured = frames,
+    ///         Err(CaptureError::WorkerIdle) => idle += 1,
+    ///         Err(err) => panic!("unexpected capture error: {:?}", err),
+    ///     }
+    /// }
+    ///
+    /// if cfg!(target_os = "linux") {
+    ///     assert_eq!(idle, 1);
+    ///     assert!(captured.iter().any(|frame| frame.contains("stuck_inner")));
+    ///     assert!(captured.iter().any(|frame| frame.contains("stuck_outer")));
+    /// }
+    ///
+    /// assert!(matches!(
+    ///     pool.capture_worker_stack(last + 100),
+    ///     Err(CaptureError::UnknownWorker)
+    /// ));
+    ///
+    /// tx.send(()).unwrap();
+    /// ```
+    ///
+    /// In the debug mode, the job watchdog and `close_timeout` also capture the stacks of the stuck
+    /// workers, and include them in their reports:
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::{mpsc, Arc, Mutex};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// #[inline(never)]
+    /// fn stuck_outer(rx: mpsc::Receiver<()>) {
+    ///     stuck_inner(rx);
+    /// }
+    ///
+    /// #[inline(never)]
+    /// fn stuck_inner(rx: mpsc::Receiver<()>) {
+    ///     let _ = rx.recv();
+    /// }
+    ///
+    /// let logs = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = Arc::clone(&logs);
+    /// set_logger(move |_level, msg| sink.lock().unwrap().push(msg.to_string()));
+    /// set_debug(true);
+    ///
+    /// let mut config = Config::new();
+    /// config.set_job_timeout(Duration::from_millis(20));
+    ///
+    /// let mut pool = ThreadPool::new_with_config(1, config);
+    /// let (tx, rx) = mpsc::channel();
+    /// pool.exec(move || stuck_outer(rx), false).unwrap();
+    ///
+    /// // the job is long overdue by now, and is still stuck when the pool gives up on closing
+    /// thread::sleep(Duration::from_millis(200));
+    /// assert!(pool.close_timeout(Duration::from_millis(20)).is_err());
+    ///
+    /// if cfg!(target_os = "linux") {
+    ///     let logs = logs.lock().unwrap();
+    ///     let reports: Vec<_> = logs.iter().filter(|msg| msg.contains("stuck_inner")).collect();
+    ///
+    ///     assert_eq!(reports.len(), 2);
+    ///     assert!(reports[0].contains("has been running the job for"));
+    ///     assert!(reports[1].contains("still running after the close timeout"));
+    ///     assert!(reports.iter().all(|report| report.contains("stuck_outer")));
+    /// }
+    ///
+    /// let _ = tx.send(());
+    /// ```
+    #[cfg(feature = "stack-capture")]
+    pub fn capture_worker_stack(&self, worker_id: usize) -> Result<Vec<String>, CaptureError> {
+        let slot = self
+            .manager
+            .read()
+            .stack_slot(worker_id)
+            .ok_or(CaptureError::UnknownWorker)?;
+
+        stack::capture(&slot)
+    }
+
+    /// Take a snapshot of the job counters and the job latency histogram of the pool, along with
+    /// the totals for capacity planning: how long the workers have been idle, and how deep the job
+    /// queues have grown.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    ///
+    /// // both workers are idle until the first jobs come in
+    /// thread::sleep(Duration::from_millis(50));
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// for _ in 0..20 {
+    ///     let tx = tx.clone();
+    ///     pool.exec(move || {
+    ///         thread::sleep(Duration::from_millis(2));
+    ///         tx.send(()).unwrap();
+    ///     }, false).unwrap();
+    /// }
+    ///
+    /// rx.iter().take(20).for_each(drop);
+    /// thread::sleep(Duration::from_millis(10));
+    ///
+    /// let metrics = pool.metrics();
+    /// assert_eq!(metrics.since_start_jobs_completed, 20);
+    /// assert!(metrics.since_start_idle_time >= Duration::from_millis(50));
+    /// assert!(metrics.since_start_peak_queue_depth > 1);
+    /// ```
+    pub fn metrics(&self) -> PoolMetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// `exec` will dispatch a closure to a free thread to be executed. If no thread are free at the
+    /// moment and the pool setting allow adding new workers at pressure, some temporary workers will
+    /// be created and added to the pool for executing the accumulated pending jobs; otherwise, this
+    /// API will work the same way as the alternative: `execute`, that it will block the caller until
+    /// a worker becomes available, or the job queue timed out, where the job will be dropped and the
+    /// caller needs to send the job to queue for execution again, if it's needed.
+    ///
+    /// For highly competitive environments, such as using the pool to enable async operations for a
+    /// web server, disabling `auto_scale` could cause starvation deadlock. However, a server can only
+    /// have a limited resources for disposal, we can't create unlimited workers, there will be a
+    /// limit where we have to either choose to drop the job, or put it into a queue for later execution,
+    /// though that will mostly lie on caller's discretion.
+    ///
+    /// In addition, this API will take a `prioritized` parameter, which will allow more urgent job
+    /// to be taken by the workers sooner than jobs without a priority.
+    ///
+    /// Note that if the `ThreadPool` is created using delayed pool initialization, i.e. created using
+    /// either `build` or `build_with_config` APIs, then the pool will be initialized at
+    /// the first time a job is to be executed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    /// use threads_pool as pool;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let mut pool = pool::ThreadPool::build(4);
+    ///
+    /// for id in 0..10 {
+    ///     pool.exec(move || {
+    ///         thread::sleep(Duration::from_secs(1));
+    ///         println!("thread {} has been waken after 1 seconds ... ", id);
+    ///     }, true);
+    /// }
+    /// ```
+    pub fn exec<F: FnOnce() + Send + 'static>(
+        &self,
+        f: F,
+        prioritized: bool,
+    ) -> Result<(), ExecutionError> {
+        let priority = if prioritized { Some(Priority::High) } else { None };
+        self.submit(Box::new(f), priority, false)
+    }
+
+    /// Similar to `exec`, but always block the caller until the job queue has the space for the new
+    /// job, even if the pool has a bounded queue capacity, or has been configured to timeout or return
+    /// immediately on a full queue.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::{mpsc, Arc};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let mut config = Config::new();
+    /// config.set_queue_capacity(Some(1));
+    ///
+    /// let pool = Arc::new(ThreadPool::new_with_config(1, config));
+    ///
+    /// // keep the only worker busy, and then fill up the queues
+    /// let (tx, rx) = mpsc::channel::<()>();
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// while pool.stats().active_workers == 0 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// pool.exec(|| {}, true).unwrap();
+    /// pool.exec(|| {}, false).unwrap();
+    ///
+    /// // the full queue pushes back instead of blocking the caller, and hands the job back
+    /// let (done_tx, done_rx) = mpsc::channel();
+    /// let rejected = match pool.exec(move || done_tx.send(()).unwrap(), false) {
+    ///     Err(ExecutionError::QueueFull(job)) => job,
+    ///     _ => panic!("the job shall be rejected"),
+    /// };
+    ///
+    /// // the blocking submission will wait until the queue has the space
+    /// let blocked = {
+    ///     let pool = Arc::clone(&pool);
+    ///     thread::spawn(move || pool.exec_blocking(|| {}, false))
+    /// };
+    ///
+    /// thread::sleep(Duration::from_millis(50));
+    /// assert!(!blocked.is_finished());
+    ///
+    /// drop(tx);
+    /// assert!(blocked.join().unwrap().is_ok());
+    ///
+    /// // now there's the space for the rejected job
+    /// pool.exec_blocking(move || rejected.run(), false).unwrap();
+    /// done_rx.recv().unwrap();
+    /// ```
+    pub fn exec_blocking<F: FnOnce() + Send + 'static>(
+        &self,
+        f: F,
+        prioritized: bool,
+    ) -> Result<(), ExecutionError> {
+        let priority = if prioritized { Some(Priority::High) } else { None };
+        self.submit(Box::new(f), priority, true)
+    }
+
+    /// Create a scope to submit the jobs that can borrow the data from the caller's stack, which are
+    /// not required to be `'static`. The call will block until all jobs submitted through the scope
+    /// are done, even if the scope body panics. If the scope body or any of the jobs panics, the
+    /// panic will be re-thrown after all jobs are done. Don't call this from a job running in the
+    /// same pool, otherwise the pool may deadlock if all workers are waiting for their scoped jobs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(4).unwrap());
+    /// let numbers: Vec<u64> = (1..=1000).collect();
+    /// let mut sums = vec![0u64; 10];
+    ///
+    /// pool.scope(|scope| {
+    ///     for (chunk, sum) in numbers.chunks(100).zip(sums.iter_mut()) {
+    ///         scope.exec(move || *sum = chunk.iter().sum()).unwrap();
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(sums.iter().sum::<u64>(), 500500);
+    /// ```
+    pub fn scope<'s, F, R>(&'s self, f: F) -> R
+    where
+        F: FnOnce(&Scope<'s>) -> R,
+    {
+        Scope::run(self, f)
+    }
+
+    /// Run the future on the pool, and the returned receiver will get the output of the future once
+    /// it's completed. The future is polled by the pool workers: each time it's woken up, a job to
+    /// poll it again will be queued into the normal queue. If the pool is closed before the future
+    /// is completed, the future will be dropped and the receiver will be disconnected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::future::Future;
+    /// use std::num::NonZeroUsize;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll};
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// // a timer that wakes up the task from another thread when it's due
+    /// struct Delay {
+    ///     due: Instant,
+    ///     armed: bool,
+    /// }
+    ///
+    /// impl Future for Delay {
+    ///     type Output = ();
+    ///
+    ///     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+    ///         if Instant::now() >= self.due {
+    ///             return Poll::Ready(());
+    ///         }
+    ///
+    ///         if !self.armed {
+    ///             self.armed = true;
+    ///
+    ///             let (due, waker) = (self.due, cx.waker().clone());
+    ///             thread::spawn(move || {
+    ///                 thread::sleep(due.saturating_duration_since(Instant::now()));
+    ///                 waker.wake();
+    ///             });
+    ///         }
+    ///
+    ///         Poll::Pending
+    ///     }
+    /// }
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(4).unwrap());
+    ///
+    /// let results: Vec<_> = (0..100)
+    ///     .map(|i| {
+    ///         pool.spawn_future(async move {
+    ///             let due = Instant::now() + Duration::from_millis(10);
+    ///             Delay { due, armed: false }.await;
+    ///             i
+    ///         })
+    ///     })
+    ///     .collect();
+    ///
+    /// let sum: usize = results.into_iter().map(|rx| rx.recv().unwrap()).sum();
+    /// assert_eq!(sum, 4950);
+    /// ```
+    pub fn spawn_future<F, R>(&self, fut: F) -> channel::Receiver<R>
+    where
+        F: Future<Output = R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = channel::bounded(1);
+
+        let f = async move {
+            // the caller may have dropped the receiver, which is fine
+            tx.send(fut.await).unwrap_or_default();
+        };
+
+        let chan = self.chan.1.clone();
+        let schedule = move |task: Task<()>| {
+            let job: Job = Box::new(move || {
+                task.run();
+            });
+
+            // don't block the waker, which can be a pool worker itself; if the pool is gone, the
+            // task is dropped with the message, and so is the result sender.
+            if let Err(TrySendError::Full(message)) = chan.try_send(Message::SingleJob(job)) {
+                let chan = chan.clone();
+                thread::spawn(move || chan.send(message).unwrap_or_default());
+            }
+        };
+
+        let (task, _handle) = async_task::spawn(f, schedule, ());
+
+        // the first poll goes through the regular submission, such that a lazy or hibernating pool
+        // will be activated for it
+        let res = self.submit(Box::new(move || { task.run(); }), None, false);
+        if res.is_err() && is_debug_mode() {
+            log(Level::Warn, format_args!("Unable to submit the future to the pool: {:?}", res));
+        }
+
+        rx
+    }
+
+    /// Submit a batch of jobs at once, which saves the per-job queueing overhead when submitting a
+    /// large number of tiny jobs. The jobs are split into a few chunks per worker, and each chunk is
+    /// queued as one message into the normal queue and run by one worker in order. If the pool
+    /// rejects a chunk, e.g. when its bounded queue is full, the error will carry the number of the
+    /// jobs that are not queued, which are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(4).unwrap());
+    /// let count = Arc::new(AtomicUsize::new(0));
+    ///
+    /// let jobs = (0..10_000).map(|_| {
+    ///     let count = Arc::clone(&count);
+    ///     move || { count.fetch_add(1, Ordering::SeqCst); }
+    /// });
+    ///
+    /// pool.exec_batch(jobs).unwrap();
+    /// pool.drain();
+    ///
+    /// assert_eq!(count.load(Ordering::SeqCst), 10_000);
+    /// ```
+    pub fn exec_batch<I, F>(&self, jobs: I) -> Result<(), BatchError>
+    where
+        I: IntoIterator<Item = F>,
+        F: FnOnce() + Send + 'static,
+    {
+        self.exec_batch_with_priority(jobs, Priority::Normal)
+    }
+
+    /// Same as `exec_batch`, but queue the batch at the given priority level, see
+    /// `exec_with_priority` for how the levels are served.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::{mpsc, Arc, Mutex};
+    /// use std::thread;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(1).unwrap());
+    /// let done = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// // keep the only worker busy while we queue up the batches
+    /// let (tx, rx) = mpsc::channel::<()>();
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// while pool.stats().active_workers == 0 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// for (level, priority) in vec![("low", Priority::Low), ("high", Priority::High)] {
+    ///     let jobs = (0..100).map(|i| {
+    ///         let done = Arc::clone(&done);
+    ///         move || done.lock().unwrap().push((level, i))
+    ///     });
+    ///
+    ///     pool.exec_batch_with_priority(jobs, priority).unwrap();
+    /// }
+    ///
+    /// drop(tx);
+    /// pool.wait_idle();
+    ///
+    /// // the high priority batch is run first, and each batch is run in order
+    /// let done = done.lock().unwrap();
+    /// let expected: Vec<_> = ["high", "low"]
+    ///     .iter()
+    ///     .flat_map(|level| (0..100).map(move |i| (*level, i)))
+    ///     .collect();
+    ///
+    /// assert_eq!(*done, expected);
+    /// ```
+    pub fn exec_batch_with_priority<I, F>(
+        &self,
+        jobs: I,
+        priority: Priority,
+    ) -> Result<(), BatchError>
+    where
+        I: IntoIterator<Item = F>,
+        F: FnOnce() + Send + 'static,
+    {
+        let jobs: Vec<Job> = jobs.into_iter().map(|f| Box::new(f) as Job).collect();
+        let total = jobs.len();
+        let chunk_size = (total / (self.get_size().max(1) * BATCH_CHUNKS_PER_WORKER)).max(1);
+
+        let mut queued = 0;
+        let mut jobs = jobs.into_iter();
+
+        while queued < total {
+            let mut chunk: Vec<Job> = jobs.by_ref().take(chunk_size).collect();
+            let size = chunk.len();
+
+            let message = if size == 1 {
+                Message::SingleJob(chunk.remove(0))
+            } else {
+                Message::ChainedJobs(chunk)
+            };
+
+            if let Err(error) = self.submit_message(message, Some(priority), false) {
+                return Err(BatchError {
+                    error,
+                    rejected: total - queued,
+                });
+            }
+
+            queued += size;
+        }
+
+        Ok(())
+    }
+
+    /// Submit a job that may fail, which will be run again by the worker after sleeping for the
+    /// `backoff` period, until it succeeds or has been run for `attempts` times. The last error will
+    /// then be passed to the retry failure callback of the pool config, if it's set and takes the
+    /// errors of the type `E`, see `Config::set_retry_failure`. The job is dropped as soon as it's
+    /// done, before the callback is run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// static GAVE_UP: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut config = Config::new();
+    /// config.set_retry_failure(|tries: usize| {
+    ///     GAVE_UP.store(tries, Ordering::SeqCst);
+    /// });
+    ///
+    /// let pool = ThreadPool::new_with_config(2, config);
+    /// let backoff = Duration::from_millis(1);
+    ///
+    /// // the flaky job succeeds at the 3rd attempt
+    /// let tries = Arc::new(AtomicUsize::new(0));
+    /// let flaky = Arc::clone(&tries);
+    /// pool.exec_retry(move || {
+    ///     match flaky.fetch_add(1, Ordering::SeqCst) + 1 {
+    ///         n if n < 3 => Err(n),
+    ///         _ => Ok(()),
+    ///     }
+    /// }, 5, backoff).unwrap();
+    ///
+    /// // the broken job never succeeds
+    /// let broken = Arc::new(AtomicUsize::new(0));
+    /// let counter = Arc::clone(&broken);
+    /// pool.exec_retry(move || Err(counter.fetch_add(1, Ordering::SeqCst) + 1), 4, backoff).unwrap();
+    ///
+    /// while GAVE_UP.load(Ordering::SeqCst) == 0 {
+    ///     thread::sleep(backoff);
+    /// }
+    ///
+    /// pool.drain();
+    /// assert_eq!(tries.load(Ordering::SeqCst), 3);
+    /// assert_eq!(broken.load(Ordering::SeqCst), 4);
+    /// assert_eq!(GAVE_UP.load(Ordering::SeqCst), 4);
+    /// ```
+    pub fn exec_retry<F, E>(
+        &self,
+        f: F,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<(), ExecutionError>
+    where
+        F: Fn() -> Result<(), E> + Send + 'static,
+        E: Send + 'static,
+    {
+        let on_failure = self.retry_failure.clone();
+
+        let job = move || {
+            let mut attempt = 1;
+            let err = loop {
+                match f() {
+                    Ok(()) => return,
+                    Err(err) if attempt >= attempts => break err,
+                    Err(_) => {
+                        attempt += 1;
+                        thread::sleep(backoff);
+                    }
+                }
+            };
+
+            // we're done with the job, release whatever it holds before reporting the failure
+            drop(f);
+
+            if let Some(on_failure) = on_failure {
+                on_failure(Box::new(err));
+            }
+        };
+
+        self.submit(Box::new(job), None, false)
+    }
+
+    /// Submit a job that can be cancelled through the returned handle. If the job is cancelled before
+    /// a worker starts it, the job will be skipped, including when the queued jobs are drained on
+    /// closing the pool; otherwise the running job can check its token to quit early.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::{mpsc, Arc};
+    /// use std::thread;
+    ///
+    /// let mut config = Config::new();
+    /// config.set_queue_capacity(Some(1024));
+    ///
+    /// let pool = ThreadPool::new_with_config(1, config);
+    /// let runs = Arc::new(AtomicUsize::new(0));
+    ///
+    /// // keep the only worker busy while we queue up the jobs
+    /// let (tx, rx) = mpsc::channel::<()>();
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// while pool.stats().active_workers == 0 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// let handles: Vec<CancelHandle> = (0..1000)
+    ///     .map(|_| {
+    ///         let runs = Arc::clone(&runs);
+    ///         pool.exec_cancellable(move |token| {
+    ///             if !token.is_cancelled() {
+    ///                 runs.fetch_add(1, Ordering::SeqCst);
+    ///             }
+    ///         })
+    ///         .unwrap()
+    ///     })
+    ///     .collect();
+    ///
+    /// handles.iter().step_by(2).for_each(CancelHandle::cancel);
+    ///
+    /// drop(tx);
+    /// pool.drain();
+    ///
+    /// assert_eq!(runs.load(Ordering::SeqCst), 500);
+    /// assert!(handles.iter().all(CancelHandle::is_finished));
+    /// assert_eq!(handles.iter().filter(|handle| handle.was_skipped()).count(), 500);
+    /// ```
+    pub fn exec_cancellable<F>(&self, f: F) -> Result<CancelHandle, ExecutionError>
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let (job, handle) = cancel::cancellable(f);
+        self.submit(Box::new(job), None, false).map(|_| handle)
+    }
+
+    /// Apply the function to each item in parallel, and collect the results in the order of the
+    /// items. The items are split into chunks, a few per worker by default, or of the size set by
+    /// `Config::set_map_chunk_size`, and each chunk is queued as one job. The call will block until
+    /// all of them are done. Don't call this from a job running in the same pool, otherwise the
+    /// pool may deadlock if all workers are waiting for the results.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool rejects any of the jobs, e.g. when it's closing. If the function panics
+    /// on any of the items, the items not yet started are skipped, and the panic is resumed in the
+    /// caller once the running jobs are done.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(4).unwrap());
+    /// let squares = pool.map(0..10_000u64, |x| x * x);
+    ///
+    /// assert_eq!(squares.len(), 10_000);
+    /// assert!(squares.iter().enumerate().all(|(i, &sq)| sq == (i * i) as u64));
+    ///
+    /// // the panic of the bad item reaches the caller
+    /// let res = panic::catch_unwind(AssertUnwindSafe(|| {
+    ///     pool.map(0..1_000, |x| if x == 500 { panic!("bad item") } else { x })
+    /// }));
+    ///
+    /// let err = res.unwrap_err();
+    /// assert_eq!(err.downcast_ref::<&str>(), Some(&"bad item"));
+    ///
+    /// // and the pool is still good to use
+    /// assert_eq!(pool.map(vec![1, 2, 3], |x| x + 1), vec![2, 3, 4]);
+    /// ```
+    pub fn map<I, T, R, F>(&self, items: I, f: F) -> Vec<R>
+    where
+        I: IntoIterator<Item = T>,
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+    {
+        let (tx, rx) = channel::unbounded();
+        let total = match self.map_chunks(items, f, move |index, res| {
+            tx.send((index, res)).unwrap_or_default()
+        }) {
+            Ok(total) => total,
+            Err(err) => panic!("Unable to submit the map jobs: {}", err),
+        };
+
+        let mut results: Vec<Option<R>> = (0..total).map(|_| None).collect();
+        let mut failure = None;
+
+        // the senders are gone once all jobs are done or dropped
+        for (index, res) in rx.iter() {
+            match res {
+                Ok(res) => results[index] = Some(res),
+                Err(payload) => {
+                    failure.get_or_insert(payload);
+                }
+            }
+        }
+
+        if let Some(payload) = failure {
+            panic::resume_unwind(payload);
+        }
+
+        results
+            .into_iter()
+            .map(|res| res.expect("The map job has been dropped before returning its result"))
+            .collect()
+    }
+
+    /// Similar to `map`, but return right after the jobs are queued. The results are sent to the
+    /// returned receiver as `(index, result)` pairs in the order they're done, and the receiver is
+    /// disconnected once all jobs are done. If the function panics on an item, the item and those
+    /// not yet started have no results. If the pool rejects a job, the error is returned, while the
+    /// jobs queued before it will still run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    ///
+    /// let mut config = Config::new();
+    /// config.set_map_chunk_size(Some(10));
+    ///
+    /// let pool = ThreadPool::new_with_config(4, config);
+    /// let rx = pool.map_async(0..100usize, |x| x * 2).unwrap();
+    ///
+    /// let mut results: Vec<(usize, usize)> = rx.iter().collect();
+    /// results.sort();
+    ///
+    /// assert_eq!(results.len(), 100);
+    /// assert!(results.iter().all(|&(index, res)| res == index * 2));
+    /// ```
+    pub fn map_async<I, T, R, F>(
+        &self,
+        items: I,
+        f: F,
+    ) -> Result<channel::Receiver<(usize, R)>, ExecutionError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+    {
+        let (tx, rx) = channel::unbounded();
+        self.map_chunks(items, f, move |index, res: thread::Result<R>| {
+            if let Ok(res) = res {
+                tx.send((index, res)).unwrap_or_default();
+            }
+        })?;
+
+        Ok(rx)
+    }
+
+    /// Queue the items in chunks, and hand the result of each item to the `sink` with its index.
+    /// Once an item panics, the items not yet started are skipped. Return the number of the items.
+    fn map_chunks<I, T, R, F, S>(&self, items: I, f: F, sink: S) -> Result<usize, ExecutionError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+        S: Fn(usize, thread::Result<R>) + Send + Sync + 'static,
+    {
+        let items: Vec<T> = items.into_iter().collect();
+        let total = items.len();
+        let chunk_size = self.map_chunk_size.unwrap_or_else(|| {
+            (total / (self.get_size().max(1) * BATCH_CHUNKS_PER_WORKER)).max(1)
+        });
+
+        let shared = Arc::new((f, sink, AtomicBool::new(false)));
+        let mut items = items.into_iter().enumerate();
+
+        loop {
+            let chunk: Vec<(usize, T)> = items.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                return Ok(total);
+            }
+
+            let shared = Arc::clone(&shared);
+            let job = move || {
+                let (f, sink, failed) = &*shared;
+
+                for (index, item) in chunk {
+                    if failed.load(Ordering::Acquire) {
+                        return;
+                    }
+
+                    let res = panic::catch_unwind(AssertUnwindSafe(|| f(item)));
+                    if res.is_err() {
+                        failed.store(true, Ordering::Release);
+                    }
+
+                    sink(index, res);
+                }
+            };
+
+            self.submit(Box::new(job), Some(Priority::Normal), true)?;
+        }
+    }
+
+    /// Similar to `exec`, but the job will be queued with the given priority. Unlike calling `exec`
+    /// without priority, which may squeeze the job into the priority queue if the pool is not busy,
+    /// the job will always be queued at its own priority level.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::{mpsc, Arc, Mutex};
+    /// use std::thread;
+    ///
+    /// let pool = Arc::new(ThreadPool::new(NonZeroUsize::new(1).unwrap()));
+    /// let done = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// // keep the only worker busy while we queue up the jobs
+    /// let (tx, rx) = mpsc::channel::<()>();
+    /// pool.exec(move || { let _ = rx.recv(); }, false).unwrap();
+    /// while pool.stats().active_workers == 0 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// for (name, priority) in vec![
+    ///     ("low-1", Priority::Low),
+    ///     ("normal-1", Priority::Normal),
+    ///     ("high-1", Priority::High),
+    ///     ("low-2", Priority::Low),
+    ///     ("normal-2", Priority::Normal),
+    ///     ("high-2", Priority::High),
+    /// ] {
+    ///     let done = Arc::clone(&done);
+    ///     pool.exec_with_priority(move || done.lock().unwrap().push(name), priority).unwrap();
+    /// }
+    ///
+    /// drop(tx);
+    /// while done.lock().unwrap().len() < 6 {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// assert_eq!(
+    ///     *done.lock().unwrap(),
+    ///     vec!["high-1", "high-2", "normal-1", "normal-2", "low-1", "low-2"]
+    /// );
+    ///
+    /// // low priority jobs still get their turn under a constant stream of high priority jobs
+    /// let low_done = Arc::new(AtomicBool::new(false));
+    /// let flood = {
+    ///     let (pool, low_done) = (Arc::clone(&pool), Arc::clone(&low_done));
+    ///     thread::spawn(move || {
+    ///         while !low_done.load(Ordering::SeqCst) {
+    ///             pool.exec_with_priority(|| thread::yield_now(), Priority::High).unwrap();
+    ///         }
+    ///     })
+    /// };
+    ///
+    /// let flag = Arc::clone(&low_done);
+    /// pool.exec_with_priority(move || flag.store(true, Ordering::SeqCst), Priority::Low).unwrap();
+    ///
+    /// flood.join().unwrap();
+    /// assert!(low_done.load(Ordering::SeqCst));
+    /// ```
+    pub fn exec_with_priority<F: FnOnce() + Send + 'static>(
+        &self,
+        f: F,
+        priority: Priority,
+    ) -> Result<(), ExecutionError> {
+        self.submit(Box::new(f), Some(priority), false)
+    }
+
+    /// Run the job on the worker with the given id alone, e.g. to reuse the thread-local states the
+    /// worker has set up for the jobs before. The pinned jobs bypass the job queues: they're taken
+    /// by the worker ahead of the queued jobs, and no sibling can steal them. If the worker has
+    /// quit or is quitting, `ExecutionError::WorkerGone` will be returned and the job is dropped,
+    /// rather than run by another worker. A job that is taken is always run, even if the worker
+    /// quits right afterwards, unless the pool is force closed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::{mpsc, Arc};
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let mut config = Config::new();
+    /// config.set_thread_name_prefix("pinned");
+    ///
+    /// let mut pool = ThreadPool::new_with_config(4, config);
+    /// let id = pool.get_last_worker_id().unwrap();
+    /// let (tx, rx) = mpsc::channel();
+    ///
+    /// for _ in 0..100 {
+    ///     let tx = tx.clone();
+    ///     pool.exec_on(id, move || {
+    ///         tx.send(thread::current().name().map(String::from)).unwrap();
+    ///     })
+    ///     .unwrap();
+    /// }
+    ///
+    /// let expected = format!("pinned-worker-{}", id);
+    /// assert!(rx.iter().take(100).all(|name| name.as_ref() == Some(&expected)));
+    ///
+    /// pool.kill_worker(id);
+    /// let res = pool.exec_on(id, || {});
+    /// assert!(matches!(res, Err(ExecutionError::WorkerGone(gone)) if gone == id));
+    ///
+    /// // the jobs pinned while the pool shrinks on idle are either run, or turned away
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_privileged_worker_count(1)
+    ///     .set_max_idle(Some(Duration::from_millis(1)));
+    ///
+    /// let pool = ThreadPool::new_with_config(2, config);
+    /// let id = pool.get_last_worker_id().unwrap();
+    /// let ran = Arc::new(AtomicUsize::new(0));
+    /// let mut accepted = 0;
+    ///
+    /// for gap in 0..1000 {
+    ///     let ran = Arc::clone(&ran);
+    ///     match pool.exec_on(id, move || { ran.fetch_add(1, Ordering::SeqCst); }) {
+    ///         Ok(()) => accepted += 1,
+    ///         Err(ExecutionError::WorkerGone(_)) => break,
+    ///         Err(err) => panic!("Unexpected error: {}", err),
+    ///     }
+    ///
+    ///     thread::sleep(Duration::from_millis(gap));
+    /// }
+    ///
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while ran.load(Ordering::SeqCst) < accepted && Instant::now() < deadline {
+    ///     thread::sleep(Duration::from_millis(1));
+    /// }
+    ///
+    /// assert_eq!(pool.worker_count(), 1);
+    /// assert_eq!(ran.load(Ordering::SeqCst), accepted);
+    /// ```
+    pub fn exec_on<F: FnOnce() + Send + 'static>(
+        &self,
+        worker_id: usize,
+        f: F,
+    ) -> Result<(), ExecutionError> {
+        if self.status.closing() {
+            return Err(ExecutionError::PoolClosed);
+        }
+
+        let (message, count) = match self.job_behaviors.as_ref() {
+            Some(behaviors) => behaviors.instrument(Message::SingleJob(Box::new(f))),
+            None => (Message::SingleJob(Box::new(f)), 1),
+        };
+
+        let job = message.into_job().ok_or(ExecutionError::WorkerGone(worker_id))?;
+        self.manager
+            .read()
+            .pin_job(worker_id, job)
+            .map_err(|_| ExecutionError::WorkerGone(worker_id))?;
+
+        if let Some(behaviors) = self.job_behaviors.as_ref() {
+            behaviors.enqueued(count, self.pending_jobs());
+        }
+
+        Ok(())
+    }
+
+    /// Submit the job to be run after the `delay`, e.g. to retry a failed job later, or to debounce
+    /// the events. The job is held by a background timer thread, which is started with the first
+    /// delayed job unless the job timeout has started it already, and put onto the normal job queue
+    /// once it's due. The delayed jobs still waiting when the pool is closed are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::mpsc;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// let (tx, rx) = mpsc::channel();
+    /// let start = Instant::now();
+    ///
+    /// for (name, delay) in vec![("late", 60), ("early", 20), ("now", 0)] {
+    ///     let tx = tx.clone();
+    ///     pool.exec_after(move || tx.send(name).unwrap(), Duration::from_millis(delay)).unwrap();
+    /// }
+    ///
+    /// assert_eq!(rx.iter().take(3).collect::<Vec<_>>(), vec!["now", "early", "late"]);
+    /// assert!(start.elapsed() >= Duration::from_millis(60));
+    /// ```
+    pub fn exec_after<F: FnOnce() + Send + 'static>(
+        &self,
+        f: F,
+        delay: Duration,
+    ) -> Result<(), ExecutionError> {
+        self.exec_at(f, Instant::now() + delay)
+    }
+
+    /// Submit the job to be run once the `due` time comes, like `exec_after`. A job due in the past
+    /// is queued right away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::mpsc;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let mut pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// let (tx, rx) = mpsc::channel();
+    /// let due = Instant::now() + Duration::from_millis(30);
+    ///
+    /// pool.exec_at(move || tx.send(Instant::now()).unwrap(), due).unwrap();
+    /// assert!(rx.recv().unwrap() >= due);
+    ///
+    /// // the jobs not yet due are dropped with the pool
+    /// pool.exec_at(|| panic!("never run"), Instant::now() + Duration::from_secs(60)).unwrap();
+    /// pool.close();
+    /// assert!(matches!(pool.exec_at(|| {}, Instant::now()), Err(ExecutionError::PoolClosed)));
+    /// ```
+    pub fn exec_at<F: FnOnce() + Send + 'static>(
+        &self,
+        f: F,
+        due: Instant,
+    ) -> Result<(), ExecutionError> {
+        self.timer()?.schedule(Box::new(f), due);
+        Ok(())
+    }
+
+    /// Run the job every `period` until it's cancelled with `cancel_recurring`, or the pool is
+    /// closed. The first run comes one `period` from now. The ticks are driven by the same timer
+    /// thread as `exec_after`, and if the last run of the job is still queued or running when the
+    /// next tick comes, the tick is skipped instead of piling up the runs in the queue. A zero
+    /// `period` is taken as 1 millisecond.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// let ticks = Arc::new(AtomicUsize::new(0));
+    ///
+    /// let counter = Arc::clone(&ticks);
+    /// let id = pool.schedule_recurring(move || {
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    /// }, Duration::from_millis(10)).unwrap();
+    ///
+    /// thread::sleep(Duration::from_millis(100));
+    /// assert!(pool.cancel_recurring(id));
+    /// assert!(!pool.cancel_recurring(id));
+    ///
+    /// // let the last queued run finish
+    /// thread::sleep(Duration::from_millis(20));
+    /// let count = ticks.load(Ordering::SeqCst);
+    /// assert!(count >= 3);
+    ///
+    /// thread::sleep(Duration::from_millis(50));
+    /// assert_eq!(ticks.load(Ordering::SeqCst), count);
+    ///
+    /// // a slow job doesn't pile up the runs: only the ticks coming after a run is done count
+    /// let runs = Arc::new(AtomicUsize::new(0));
+    /// let counter = Arc::clone(&runs);
+    /// let id = pool.schedule_recurring(move || {
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    ///     thread::sleep(Duration::from_millis(50));
+    /// }, Duration::from_millis(5)).unwrap();
+    ///
+    /// thread::sleep(Duration::from_millis(160));
+    /// pool.cancel_recurring(id);
+    /// assert!(runs.load(Ordering::SeqCst) <= 4);
+    /// ```
+    pub fn schedule_recurring<F: Fn() + Send + 'static>(
+        &self,
+        f: F,
+        period: Duration,
+    ) -> Result<TaskId, ExecutionError> {
+        let period = period.max(Duration::from_millis(1));
+        Ok(self.timer()?.schedule_recurring(Box::new(f), period))
+    }
+
+    /// Stop the recurring task started by `schedule_recurring`. A run of the task that has been
+    /// queued already is not called off. Return `false` if the task is not found, e.g. it has been
+    /// cancelled already, or the pool is closed.
+    pub fn cancel_recurring(&self, id: TaskId) -> bool {
+        self.timer
+            .get()
+            .is_some_and(|timer| timer.cancel_recurring(id))
+    }
+
+    /// Get the timer for the delayed jobs, and start it if it's not running yet.
+    fn timer(&self) -> Result<&JobTimer, ExecutionError> {
+        // the closing flags can come along with the others, e.g. the sleeping workers
+        if self.status.closing() {
+            return Err(ExecutionError::PoolClosed);
+        }
+
+        let status = self.status.load();
+
+        // make sure there're workers to take the job once it's due
+        if status == FLAG_HIBERNATING || status == FLAG_LAZY_INIT {
+            self.activate();
+        }
+
+        if let Some(timer) = self.timer.get() {
+            return Ok(timer);
+        }
+
+        // if someone else has beaten us to it, our timer is stopped when dropped
+        let timer = JobTimer::start(
+            self.chan.1.clone(),
+            self.job_behaviors.clone(),
+            Arc::clone(&self.metrics),
+            &self.bg_config,
+        )
+        .map_err(ExecutionError::SpawnFailed)?;
+
+        Ok(self.timer.get_or_init(|| timer))
+    }
+
+    /// Submit the job at the given priority level, or let the pool decide the queue if it's `None`.
+    /// If `blocking`, wait for the space in the queue regardless of the pool's dispatch settings.
+    pub(crate) fn submit(
+        &self,
+        job: Job,
+        priority: Option<Priority>,
+        blocking: bool,
+    ) -> Result<(), ExecutionError> {
+        self.submit_message(Message::SingleJob(job), priority, blocking)
+    }
+
+    fn submit_message(
+        &self,
+        message: Message,
+        priority: Option<Priority>,
+        blocking: bool,
+    ) -> Result<(), ExecutionError> {
+        let behaviors = match self.job_behaviors.as_ref() {
+            Some(behaviors) => behaviors,
+            None => {
+                self.send_message(message, priority, blocking)?;
+                self.metrics.record_queue_depth(self.pending_jobs());
+                return Ok(());
+            }
+        };
+
+        let (message, count) = behaviors.instrument(message);
+        self.send_message(message, priority, blocking)?;
+
+        let pending = self.pending_jobs();
+        self.metrics.record_queue_depth(pending);
+        behaviors.enqueued(count, pending);
+
+        Ok(())
+    }
+
+    fn send_message(
+        &self,
+        message: Message,
+        priority: Option<Priority>,
+        blocking: bool,
+    ) -> Result<(), ExecutionError> {
+        // we're closing the pool, take no more new jobs
+        if self.status.closing() {
+            return Err(ExecutionError::PoolClosed);
+        }
+
+        let status = self.status.load();
+
+        // if at the hibernation or lazy init mode, activate the pool first
+        if status == FLAG_HIBERNATING || status == FLAG_LAZY_INIT {
+            self.activate();
+        }
+
+        // the idle workers above the min size may have quit, bring one back to take the job
+        if self.metrics.take_retired() {
+            self.respawn_worker();
+        }
+
+        // still no worker to take the job? unexpected and should return error
+        let (worker_count, has_live_workers) = {
+            let manager = self.manager.read();
+            (manager.workers_count(), manager.has_live_workers())
+        };
+
+        if worker_count == 0 {
+            return Err(ExecutionError::Uninitialized);
+        }
+
+        // the job would sit in the queue forever if all workers have died
+        if !has_live_workers {
+            return Err(ExecutionError::PoolDead);
+        }
+
+        // if we can auto scale the pool, set the retry stack limit
+        let retry = if self.auto_scale { 1 } else { 0 };
+
+        // send the job for execution
+        let chan_id = match priority {
+            Some(priority) => priority.queue_id(),
+            None => self.queue_id(false),
+        };
+
+        let res = if blocking {
+            let chan = self.sender(chan_id);
+            self.send(chan, message)
+                .map(|_| chan.is_full())
+                .map_err(ExecutionError::from)
+        } else if self.queue_capacity.is_some() {
+            // push back on the caller if the bounded queue is full
+            self.try_dispatch(message, chan_id)
+        } else {
+            self.dispatch_to(message, retry, chan_id)
+                .map_err(ExecutionError::from)
+        };
+
+        // a bounded queue turning the job away is under pressure as well, so scale up all the same
+        let busy = match res.as_ref() {
+            Ok(busy) => *busy,
+            Err(ExecutionError::QueueFull(_)) => true,
+            Err(_) => return res.map(|_| ()),
+        };
+
+        if self.auto_scale {
+            self.replace_stuck_workers();
+
+            if busy {
+                // auto scale by adding more workers to take the job
+                let target = self
+                    .amortized_new_size(self.pending_jobs())
+                    .map(|target| self.bounded_size(target))
+                    .filter(|target| *target > worker_count);
+
+                if let Some(target) = target {
+                    let res = self.manager.write().add_workers(
+                        target.saturating_sub(worker_count),
+                        false,
+                        self.status.clone(),
+                    );
+
+                    if res.is_err() && is_debug_mode() {
+                        log(
+                            Level::Warn,
+                            format_args!("Unable to add more workers to the pool under pressure"),
+                        );
+                    }
+                }
+            }
+        }
+
+        res.map(|_| ())
+    }
+
+    /// Similar to `exec`, yet this is the simplified version which will neither activate nor scale the
+    /// pool. The job priority will be automatically evaluated based on the queue length, and for pool
+    /// under pressure, we will try to balance the queue.
+    ///
+    /// This method will be no-op if the pool is lazy-created (i.e. created via the `build` functions)
+    /// and `activate` has not been called on it yet. If you are not sure if the pool has been
+    /// activated or not, alter to use `exec` instead, which will initiate the pool if that's not
+    /// done yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    /// use threads_pool as pool;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let pool = pool::ThreadPool::build(4);
+    ///
+    /// for id in 0..10 {
+    ///     pool.execute(move || {
+    ///         thread::sleep(Duration::from_secs(1));
+    ///         println!("thread {} has been waken after 1 seconds ... ", id);
+    ///     });
+    /// }
+    /// ```
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, f: F) -> Result<(), ExecutionError> {
         // we're closing, taking no more jobs.
         if self.status.closing() {
-            return Err(ExecutionError::Disconnected);
+            return Err(ExecutionError::PoolClosed);
+        }
+
+        // no worker to take the job
+        let (worker_count, has_live_workers) = {
+            let manager = self.manager.read();
+            (manager.workers_count(), manager.has_live_workers())
+        };
+
+        if worker_count < 1 {
+            return Err(ExecutionError::Uninitialized);
+        }
+
+        if !has_live_workers {
+            return Err(ExecutionError::PoolDead);
+        }
+
+        // send the job to the queue for execution. note that if we're in hibernation, the queue
+        // will still take the new job, though no worker will be awaken to take the job.
+        let prioritized = self.chan.1.is_empty() && !self.chan.0.is_full();
+
+        self.dispatch(Message::SingleJob(Box::new(f)), 0, prioritized)
+            .map(|_| {})
+            .map_err(ExecutionError::from)
+    }
+
+    /// Create a `Dispatcher` to submit the jobs to this pool, which can be cloned and handed to the
+    /// producer threads without sharing the pool itself. See `Dispatcher` for details.
+    pub fn dispatcher(&self) -> Dispatcher {
+        Dispatcher::new(
+            (self.chan.0.clone(), self.chan.1.clone(), self.chan.2.clone()),
+            self.job_behaviors.clone(),
+            Arc::clone(&self.metrics),
+            Arc::clone(&self.closed),
+        )
+    }
+
+    pub fn sync_block<R, F>(&self, f: F) -> Result<R, ExecutionError>
+    where
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let (tx, rx) = channel::bounded(1);
+
+        let clo = Box::new(move || match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(res) => tx.send(Ok(res)).unwrap_or_default(),
+            Err(err) => {
+                // tell the caller, then let the worker handle the panic as usual
+                tx.send(Err(ExecutionError::Panicked)).unwrap_or_default();
+                panic::resume_unwind(err);
+            }
+        });
+
+        if self.dispatch(Message::SingleJob(clo), 4, false).is_err() {
+            return Err(ExecutionError::Timeout);
+        }
+
+        // timeout after 8 seconds of no responses ...
+        rx.recv_timeout(Duration::from_secs(8))?
+    }
+
+    fn dispatch(
+        &self,
+        message: Message,
+        retry: u8,
+        with_priority: bool,
+    ) -> Result<bool, SendTimeoutError<Message>> {
+        let behaviors = match self.job_behaviors.as_ref() {
+            Some(behaviors) => behaviors,
+            None => {
+                let busy = self.dispatch_to(message, retry, self.queue_id(with_priority))?;
+                self.metrics.record_queue_depth(self.pending_jobs());
+                return Ok(busy);
+            }
+        };
+
+        let (message, count) = behaviors.instrument(message);
+        let busy = self.dispatch_to(message, retry, self.queue_id(with_priority))?;
+
+        let pending = self.pending_jobs();
+        self.metrics.record_queue_depth(pending);
+        behaviors.enqueued(count, pending);
+
+        Ok(busy)
+    }
+
+    /// Pick the work queue where we shall put the new job into.
+    fn queue_id(&self, with_priority: bool) -> u8 {
+        if with_priority
+            || (self.chan.1.is_empty() && self.chan.0.len() <= self.upgrade_threshold)
+        {
+            // squeeze the work into the priority chan first even if some normal work is in queue
+            0
+        } else {
+            // normal work and then priority queue is full
+            1
+        }
+    }
+
+    /// Send a sentinel job through each of the job queues, and wait for the jobs queued ahead of
+    /// them to be done. Return `false` if the deadline is passed first, or if some queued jobs are
+    /// left behind because the pool can't run them at all.
+    fn wait_drained(&self, deadline: Option<Instant>) -> bool {
+        // a job of this pool would be waiting for itself to finish, give up right away
+        let worker_id = current_worker_id();
+        if worker_id > 0 && self.manager.read().worker_ids().contains(&worker_id) {
+            if is_debug_mode() {
+                log(
+                    Level::Warn,
+                    format_args!("Worker {} can't wait for its own pool to drain", worker_id),
+                );
+            }
+
+            return false;
         }
 
-        // no worker to take the job
-        if self.manager.workers_count() < 1 {
-            return Err(ExecutionError::Uninitialized);
+        let status = self.status.load();
+        if status & (FLAG_CLOSING | FLAG_FORCE_CLOSE | FLAG_HIBERNATING) > 0
+            || self.manager.read().workers_count() == 0
+        {
+            return self.pending_jobs() == 0;
         }
 
-        // send the job to the queue for execution. note that if we're in hibernation, the queue
-        // will still take the new job, though no worker will be awaken to take the job.
-        let prioritized = self.chan.1.is_empty() && !self.chan.0.is_full();
+        let remains = || deadline.map(|d| d.saturating_duration_since(Instant::now()));
 
-        self.dispatch(Message::SingleJob(Box::new(f)), 0, prioritized)
-            .map(|_| {})
-            .map_err(|err| match err {
-                SendTimeoutError::Timeout(_) => ExecutionError::Timeout,
-                SendTimeoutError::Disconnected(_) => ExecutionError::Disconnected,
-            })
-    }
+        // the sentinels will be run after all jobs queued ahead of them have been taken
+        let (tx, rx) = channel::bounded(3);
+        for chan_id in 0..3 {
+            let tx = tx.clone();
+            let sentinel = Message::SingleJob(Box::new(move || {
+                tx.send(()).unwrap_or_default();
+            }));
 
-    pub fn sync_block<R, F>(&self, f: F) -> Result<R, ExecutionError>
-    where
-        R: Send + 'static,
-        F: FnOnce() -> R + Send + 'static,
-    {
-        let curr = thread::current();
-        let (tx, rx) = channel::bounded(1);
+            let sent = match remains() {
+                Some(timeout) => self.sender(chan_id).send_timeout(sentinel, timeout),
+                None => self.send(self.sender(chan_id), sentinel),
+            };
 
-        let clo = Box::new(move || {
-            tx.send(f()).unwrap_or_default();
-            curr.unpark();
-        });
+            if let Err(err) = sent {
+                // the sentinels are gone with the workers if the queues are disconnected
+                return matches!(err, SendTimeoutError::Disconnected(_)) && self.pending_jobs() == 0;
+            }
+        }
 
-        if self.dispatch(Message::SingleJob(clo), 4, false).is_err() {
-            return Err(ExecutionError::Timeout);
+        drop(tx);
+        for _ in 0..3 {
+            let received = match remains() {
+                Some(timeout) => rx.recv_timeout(timeout).map_err(|err| err.is_timeout()),
+                None => rx.recv().map_err(|_| false),
+            };
+
+            match received {
+                Ok(()) => {}
+                Err(true) => return false,
+                // the sentinels are gone with the workers, nothing to wait for
+                Err(false) => return self.pending_jobs() == 0,
+            }
         }
 
-        // timeout after 8 seconds of no responses ...
-        thread::park_timeout(Duration::from_secs(8));
+        // wait for the jobs taken off the queues before the sentinels to be done
+        self.metrics.wait_idle_until(deadline, || self.is_settled())
+    }
 
-        rx.try_recv().map_err(|err| match err {
-            TryRecvError::Empty => ExecutionError::Timeout,
-            TryRecvError::Disconnected => ExecutionError::Disconnected,
-        })
+    fn sender(&self, chan_id: u8) -> &Sender<Message> {
+        match chan_id {
+            0 => &self.chan.0,
+            1 => &self.chan.1,
+            _ => &self.chan.2,
+        }
     }
 
-    fn dispatch(
+    /// Send the job to the queue without waiting, and reject it if the queue is full.
+    fn try_dispatch(&self, message: Message, chan_id: u8) -> Result<bool, ExecutionError> {
+        let chan = self.sender(chan_id);
+
+        match chan.try_send(message) {
+            Ok(()) => Ok(chan.is_full()),
+            Err(TrySendError::Full(message)) => {
+                // bring any offline workers back online to work through the backlog
+                self.wake_workers();
+                Err(ExecutionError::QueueFull(RejectedJob::new(message)))
+            }
+            Err(TrySendError::Disconnected(_)) => Err(ExecutionError::Disconnected),
+        }
+    }
+
+    fn dispatch_to(
         &self,
         message: Message,
         retry: u8,
-        with_priority: bool,
+        chan_id: u8,
     ) -> Result<bool, SendTimeoutError<Message>> {
-        // pick the work queue where we shall put this new job into
-        let (chan, chan_id) = if with_priority
-            || (self.chan.1.is_empty() && self.chan.0.len() <= self.upgrade_threshold)
-        {
-            // squeeze the work into the priority chan first even if some normal work is in queue
-            (&self.chan.0, 0)
-        } else {
-            // normal work and then priority queue is full
-            (&self.chan.1, 1)
-        };
+        let chan = self.sender(chan_id);
 
         let res = match self.queue_timeout {
             Some(period) => {
@@ -428,32 +2785,74 @@ impl ThreadPool {
         res.map(|_| chan.is_full())
     }
 
-    fn amortized_new_size(&self, queue_length: usize) -> Option<usize> {
-        if queue_length == 0 {
-            return None;
-        }
-
-        let worker_count = self.manager.workers_count();
-        if queue_length > AUTO_EXTEND_TRIGGER_SIZE && worker_count <= self.auto_extend_threshold {
-            // The workers size may be larger than the threshold, but that's okay since we won't
-            // add more workers from this point on, unless some workers are killed.
-            Some(worker_count + queue_length)
-        } else if queue_length == 0 && worker_count > self.init_size {
-            if worker_count == (self.init_size + 1) {
-                Some(self.init_size)
-            } else {
-                Some(((worker_count + self.init_size) / 2) as usize)
+    /// Add a worker for each worker that the watchdog has found stuck with an overdue job. The new
+    /// workers will quit once they've been idle for long enough, like other auto scaled workers.
+    fn replace_stuck_workers(&self) {
+        let stuck = self.stuck_workers.swap(0, Ordering::AcqRel);
+        if stuck == 0 {
+            return;
+        }
+
+        let res = self
+            .manager
+            .write()
+            .add_workers(stuck, false, self.status.clone());
+
+        if res.is_err() && is_debug_mode() {
+            log(
+                Level::Warn,
+                format_args!("Unable to replace the {} workers stuck with the overdue jobs", stuck),
+            );
+        }
+    }
+
+    /// Add a worker in place of the one that has quit after being idle, if the pool has fewer
+    /// workers than it's created with.
+    fn respawn_worker(&self) {
+        // the auto scaled workers retire for good, so don't hold up the job with the write lock
+        // unless the pool has fallen below its size
+        if self.manager.read().live_workers_count() >= self.init_size {
+            return;
+        }
+
+        let mut manager = self.manager.write();
+        manager.worker_cleanup();
+
+        if manager.workers_count() >= self.init_size {
+            return;
+        }
+
+        if let Err(err) = manager.add_workers(1, false, self.status.clone()) {
+            if is_debug_mode() {
+                log(
+                    Level::Warn,
+                    format_args!("Unable to respawn the worker for the new job: {}", err),
+                );
             }
-        } else {
-            None
         }
     }
 
-    fn set_status(&mut self, status: u8) {
+    fn default_scaling_policy(&self) -> DefaultScalingPolicy {
+        DefaultScalingPolicy::with_threshold(self.init_size, self.auto_extend_threshold)
+    }
+
+    /// Keep the size the pool is scaled to within the `min_workers` and `max_workers`.
+    fn bounded_size(&self, target: usize) -> usize {
+        let target = target.max(self.min_workers);
+        self.max_workers
+            .map_or(target, |max| target.min(max.max(self.min_workers)))
+    }
+
+    fn amortized_new_size(&self, queue_length: usize) -> Option<usize> {
+        let worker_count = self.manager.read().workers_count();
+        self.default_scaling_policy().amortized_size(queue_length, worker_count)
+    }
+
+    fn set_status(&self, status: u8) {
         self.status.store(status);
     }
 
-    fn update_status(&mut self, old: u8, new: u8) -> bool {
+    fn update_status(&self, old: u8, new: u8) -> bool {
         if old == new {
             return true;
         }
@@ -461,35 +2860,108 @@ impl ThreadPool {
         self.status.compare_exchange(old, new)
     }
 
+    fn wake_up_pool(&self) {
+        // only wake everyone up if we're in the right status
+        if self.update_status(FLAG_HIBERNATING, FLAG_NORMAL) {
+            // take the chance to remove the workers that have quit
+            self.manager.write().worker_cleanup();
+            self.wake_workers();
+        }
+    }
+
     fn shut_down(&mut self, forced: bool) {
+        let runnable = self.can_drain();
+        self.closed.store(true, Ordering::Release);
+
         if !forced {
             self.set_status(FLAG_CLOSING);
         } else {
             self.set_status(FLAG_FORCE_CLOSE);
         }
 
+        self.clear_timer();
+
         if is_debug_mode() {
-            println!(
-                "Remainder work before shutdown signal: {}",
-                self.chan.0.len() + self.chan.1.len()
+            log(
+                Level::Info,
+                format_args!("Remainder work before shutdown signal: {}", self.get_queue_length()),
             );
         }
 
+        // the closing pool takes no new jobs, let the workers run through the queued ones before
+        // telling them to quit
+        if !forced && runnable {
+            self.metrics.wait_idle_until(None, || self.is_drained());
+        }
+
         self.clear();
+        self.stop_background();
+    }
+
+    /// Stop the timer and the metrics flusher threads of the closing pool. The job watchdog keeps
+    /// checking on the timer thread until the workers are done, so this goes after the workers.
+    fn stop_background(&mut self) {
+        if let Some(mut timer) = self.timer.take() {
+            timer.stop();
+        }
+
+        self.stop_metrics_flusher();
+    }
+
+    /// Drop the delayed jobs and the recurring tasks, none shall be queued to the closing pool.
+    fn clear_timer(&self) {
+        if let Some(timer) = self.timer.get() {
+            timer.clear();
+        }
+    }
+
+    /// Check if the queued jobs will ever be run, i.e. the pool has workers and is not hibernating.
+    fn can_drain(&self) -> bool {
+        self.status.load() & (FLAG_HIBERNATING | FLAG_LAZY_INIT) == 0
+            && self.manager.read().workers_count() > 0
+    }
+
+    /// Check if the closing pool is done with the queued jobs, or has no worker left to run them.
+    fn is_drained(&self) -> bool {
+        self.is_idle() || self.worker_count() == 0
     }
 
-    fn create_pool(size: usize, config: Config, lazy_built: bool) -> ThreadPool {
+    #[cfg(feature = "persist-metrics")]
+    fn stop_metrics_flusher(&mut self) {
+        if let Some(mut flusher) = self.metrics_flusher.take() {
+            flusher.stop();
+        }
+    }
+
+    #[cfg(not(feature = "persist-metrics"))]
+    fn stop_metrics_flusher(&mut self) {}
+
+    fn create_pool(size: usize, config: Config, lazy_built: bool) -> io::Result<ThreadPool> {
         let pool_size = match size {
             _ if size < 1 => 1,
             _ if size > THRESHOLD => THRESHOLD,
             _ => size,
         };
 
-        let (tx, rx) = channel::bounded(CHAN_CAP);
-        let (pri_tx, pri_rx) = channel::bounded(CHAN_CAP);
+        let queue_capacity = config.queue_capacity().map(|cap| cap.max(1));
+        let chan_cap = queue_capacity.unwrap_or(CHAN_CAP);
+
+        let (tx, rx) = channel::bounded(chan_cap);
+        let (pri_tx, pri_rx) = channel::bounded(chan_cap);
+        let (low_tx, low_rx) = channel::bounded(chan_cap);
 
         let non_blocking = config.non_blocking();
         let policy = config.timeout_policy();
+        let scaling_policy = config.scaling_policy().cloned();
+        let (min_workers, max_workers) = (config.min_workers(), config.max_workers());
+        let retry_failure = config.retry_failure().cloned();
+        let job_behaviors = Some(config.job_behavior())
+            .filter(|behaviors| !behaviors.is_empty())
+            .cloned();
+        let job_timeout = config.job_timeout();
+        let on_job_timeout = config.on_job_timeout().cloned();
+        let bg_config = config.background_thread_config().clone();
+        let map_chunk_size = config.map_chunk_size();
 
         let flag = PoolStatus::new(if !lazy_built {
             FLAG_NORMAL
@@ -497,20 +2969,85 @@ impl ThreadPool {
             FLAG_LAZY_INIT
         });
 
-        let manager = Manager::build(config, pool_size, flag.clone(), pri_rx, rx, lazy_built);
+        #[cfg(feature = "persist-metrics")]
+        let (metrics, metrics_flusher) = match config.metrics_persistence() {
+            Some((path, interval)) => {
+                let metrics = Arc::new(MetricsFlusher::load(path));
+                let flusher = MetricsFlusher::start(
+                    path.clone(),
+                    *interval,
+                    Arc::clone(&metrics),
+                    config.background_thread_config(),
+                )?;
+
+                (metrics, Some(flusher))
+            }
+            None => (Arc::new(PoolMetrics::new()), None),
+        };
+
+        #[cfg(not(feature = "persist-metrics"))]
+        let metrics = Arc::new(PoolMetrics::new());
+
+        let manager = Manager::build(
+            config,
+            pool_size,
+            flag.clone(),
+            (pri_rx, rx, low_rx),
+            Arc::clone(&metrics),
+            lazy_built,
+        )?;
+
+        let stuck_workers = Arc::new(AtomicUsize::new(0));
+        let local_queues = manager.local_queues();
+        let timer = OnceLock::new();
+
+        if let Some(timeout) = job_timeout {
+            let watchdog = JobWatchdog::new(
+                manager.job_clocks(),
+                timeout,
+                on_job_timeout,
+                (Arc::clone(&metrics), Arc::clone(&stuck_workers)),
+            );
+
+            let job_timer = JobTimer::start(
+                tx.clone(),
+                job_behaviors.clone(),
+                Arc::clone(&metrics),
+                &bg_config,
+            )?;
+
+            let period = watchdog.period();
+            job_timer.schedule_check(Arc::new(move || watchdog.check()), period);
+            let _ = timer.set(job_timer);
+        }
 
-        ThreadPool {
-            manager,
-            chan: (pri_tx, tx),
+        Ok(ThreadPool {
+            manager: RwLock::new(manager),
+            chan: (pri_tx, tx, low_tx),
             init_size: pool_size,
-            upgrade_threshold: CHAN_CAP / 2,
+            upgrade_threshold: chan_cap / 2,
             auto_extend_threshold: THRESHOLD,
             status: flag,
             auto_scale: false,
+            scaling_policy,
+            min_workers,
+            max_workers,
             non_blocking,
+            queue_capacity,
             queue_timeout: None,
             timeout_policy: policy,
-        }
+            retry_failure,
+            job_behaviors,
+            metrics,
+            local_queues,
+            timer,
+            bg_config,
+            map_chunk_size,
+            stuck_workers,
+            closed: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "persist-metrics")]
+            metrics_flusher,
+        })
     }
 }
 
@@ -524,12 +3061,12 @@ impl Hibernation for ThreadPool {
     /// Put the pool into hibernation mode. In this mode, all workers will park itself after finishing
     /// the current job to reduce CPU usage.
     ///
-    /// The pool will be prompted back to normal mode on 2 occasions:
-    /// 1) calling the `unhibernate` API to wake up the pool, or 2) sending a new job through the
-    /// `exec` API, which will automatically assume an unhibernation desire, wake self up, take and
-    /// execute the incoming job. Though if you call the immutable API `execute`, the job will be
-    /// queued yet not executed. Be aware that if the queue is full, the new job will be dropped and
-    /// an execution error will be returned in this case.
+    /// The pool will be prompted back to normal mode on 2 occasions: 1) calling the `unhibernate`
+    /// API to wake up the pool, or 2) sending a new job through the `exec` API, which will
+    /// automatically assume an unhibernation desire, wake self up, take and execute the incoming
+    /// job. Though if you call the immutable API `execute`, the job will be queued yet not executed.
+    /// Be aware that if the queue is full, the new job will be dropped and an execution error will
+    /// be returned in this case.
     ///
     /// It is recommended to explicitly call `unhibernate` when the caller want to wake up the pool,
     /// to avoid side effect or undefined behaviors.
@@ -543,12 +3080,7 @@ impl Hibernation for ThreadPool {
     /// Cautious: calling this API will set the status flag to normal, which may conflict with actions
     /// that would set status flag otherwise.
     fn unhibernate(&mut self) {
-        // only wake everyone up if we're in the right status
-        if self.update_status(FLAG_HIBERNATING, FLAG_NORMAL) {
-            // take the chance to clean up the graveyard
-            self.manager.worker_cleanup();
-            self.wake_workers();
-        }
+        self.wake_up_pool();
     }
 
     /// Check if the pool is in hibernation mode.
@@ -606,7 +3138,10 @@ pub trait PoolManager {
     fn kill_worker(&mut self, id: usize);
     fn clear(&mut self);
     fn close(&mut self);
+    fn close_timeout(&mut self, timeout: Duration) -> Result<(), Vec<usize>>;
     fn force_close(&mut self);
+    fn drain(&self);
+    fn reap(&mut self) -> usize;
 }
 
 impl PoolManager for ThreadPool {
@@ -618,8 +3153,15 @@ impl PoolManager for ThreadPool {
             return;
         }
 
-        // manager will update the graveyard
-        self.manager.extend_by(more, self.status.clone());
+        // keep whatever workers have been spawned if the OS refuses to give us more threads.
+        if let Err(err) = self.manager.write().extend_by(more, self.status.clone()) {
+            if is_debug_mode() {
+                log(
+                    Level::Warn,
+                    format_args!("Unable to extend the pool by {} workers: {}", more, err),
+                );
+            }
+        }
     }
 
     /// Manually shrink the size of the pool and release system resources. If another operation that's
@@ -629,22 +3171,62 @@ impl PoolManager for ThreadPool {
             return;
         }
 
-        // manager will update the graveyard
-        let workers = self.manager.shrink_by(less);
+        let workers = self.manager.write().shrink_by(less);
         if self.chan.0.send(Message::Terminate(workers)).is_err() && is_debug_mode() {
-            eprintln!("Failed to send the termination message to workers");
+            log(Level::Warn, format_args!("Failed to send the termination message to workers"));
         }
     }
 
     /// Resize the pool to the desired size. This will either trigger a pool extension or contraction.
     /// Note that if another pool-size changing operation is undergoing, the effect may be cancelled
     /// out if we're moving towards the same direction (adding pool size, or reducing pool size).
+    /// The workers that have quit on idle are reaped first, and don't count towards the size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_privileged_worker_count(2)
+    ///     .set_max_idle(Some(Duration::from_millis(50)));
+    ///
+    /// let mut pool = ThreadPool::new_with_config(2, config);
+    ///
+    /// // resizing leaves no dead workers behind
+    /// for i in 0..50 {
+    ///     pool.resize(if i % 2 == 0 { 8 } else { 2 });
+    ///
+    ///     let stats = pool.stats();
+    ///     assert_eq!(stats.worker_count, stats.workers_alive);
+    /// }
+    ///
+    /// // the unprivileged workers quit after idling, but are still on the books
+    /// pool.resize(6);
+    /// while pool.worker_count() > 2 {
+    ///     thread::sleep(Duration::from_millis(10));
+    /// }
+    ///
+    /// assert_eq!(pool.stats().worker_count, 6);
+    /// assert_eq!(pool.reap(), 4);
+    /// assert_eq!(pool.stats().worker_count, 2);
+    /// ```
     fn resize(&mut self, target: usize) {
         if target == 0 {
             return;
         }
 
-        let worker_count = self.manager.workers_count();
+        // the workers that have quit on idle don't count towards the size
+        let worker_count = {
+            let mut manager = self.manager.write();
+            manager.worker_cleanup();
+            manager.workers_count()
+        };
 
         match target {
             x if x > worker_count => self.extend(x - worker_count),
@@ -656,11 +3238,52 @@ impl PoolManager for ThreadPool {
     /// Automatically adjust the pool size according to criteria: if the pool is idling and we've
     /// previously added temporary workers, we will tell them to cease work before designated expiration
     /// time; if the pool is overwhelmed and need more workers to handle jobs, we will add more threads
-    /// to the pool.
+    /// to the pool. The criteria can be overridden by setting a `ScalingPolicy` to the pool config,
+    /// and the new size is always kept within the `min_workers` and `max_workers` of the config.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::Arc;
+    ///
+    /// // keep one spare worker around, up to 8 workers
+    /// struct OneSpare;
+    ///
+    /// impl ScalingPolicy for OneSpare {
+    ///     fn desired_size(&self, stats: &PoolStats, _current: usize) -> usize {
+    ///         (stats.active_workers + 1).min(8)
+    ///     }
+    /// }
+    ///
+    /// let mut config = Config::new();
+    /// config.set_scaling_policy(Arc::new(OneSpare));
+    ///
+    /// let mut pool = ThreadPool::new_with_config(4, config);
+    /// pool.auto_adjust();
+    /// assert_eq!(pool.get_size(), 1);
+    /// ```
     fn auto_adjust(&mut self) {
-        if let Some(target) = self.amortized_new_size(self.get_queue_length()) {
-            self.resize(target);
+        // the workers that have purged themselves shall not count towards the pool size
+        self.reap();
+
+        let stats = self.stats();
+        let target = match self.scaling_policy.as_ref() {
+            Some(policy) => policy.desired_size(&stats, stats.worker_count),
+            None => self
+                .default_scaling_policy()
+                .desired_size(&stats, stats.worker_count),
+        };
+
+        // 0 means leaving the pool untouched, otherwise keep the new size within the bounds
+        if target == 0 {
+            return;
         }
+
+        let target = self.bounded_size(target);
+        self.resize(target);
     }
 
     /// Let extended workers to expire when idling for too long.
@@ -671,12 +3294,12 @@ impl PoolManager for ThreadPool {
             0usize
         };
 
-        self.manager.worker_auto_expire(actual_life);
+        self.manager.write().worker_auto_expire(actual_life);
     }
 
     /// Remove a thread worker from the pool with the given worker id.
     fn kill_worker(&mut self, id: usize) {
-        if self.manager.dismiss_worker(id).is_none() {
+        if self.manager.write().dismiss_worker(id).is_none() {
             // can't find the worker with the given id, quit now.
             return;
         }
@@ -688,11 +3311,14 @@ impl PoolManager for ThreadPool {
             .is_err()
             && is_debug_mode()
         {
-            eprintln!("Failed to send the termination message to worker: {}", id);
+            log(
+                Level::Warn,
+                format_args!("Failed to send the termination message to worker: {}", id),
+            );
         }
 
         if is_debug_mode() {
-            println!("Worker {} is told to be terminated...", id);
+            log(Level::Info, format_args!("Worker {} is told to be terminated...", id));
         }
     }
 
@@ -702,7 +3328,7 @@ impl PoolManager for ThreadPool {
     /// and go away on program exit.
     fn clear(&mut self) {
         let status = self.status.load();
-        let reset = if status != FLAG_FORCE_CLOSE || status != FLAG_CLOSING {
+        let reset = if !self.status.closing() {
             // must update the flag if we've not in proper status
             self.set_status(FLAG_REST);
             true
@@ -712,7 +3338,7 @@ impl PoolManager for ThreadPool {
         };
 
         // remove the workers in sync mode
-        self.manager.remove_all(true);
+        self.manager.write().remove_all(true);
 
         // reset the flag if required
         if reset {
@@ -722,15 +3348,228 @@ impl PoolManager for ThreadPool {
 
     /// Signal the threads in the pool that we're closing, but allow them to finish all jobs in the queue
     /// before exiting.
+    ///
+    /// The quit signal is broadcast to all workers and every parked worker is woken up before any
+    /// of them is joined, so an idle pool closes within about one idle-parking period (10ms by
+    /// default), regardless of its size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    ///
+    /// fn threads() -> Option<usize> {
+    ///     let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    ///     let line = status.lines().find(|line| line.starts_with("Threads:"))?;
+    ///     line["Threads:".len()..].trim().parse().ok()
+    /// }
+    ///
+    /// let before = threads();
+    ///
+    /// for _ in 0..1000 {
+    ///     let mut pool = ThreadPool::new(NonZeroUsize::new(4).unwrap());
+    ///     pool.close();
+    /// }
+    ///
+    /// // all workers are joined, none is left behind (only checked where procfs is available)
+    /// assert_eq!(threads(), before);
+    /// ```
     fn close(&mut self) {
         self.shut_down(false);
     }
 
+    /// Signal the threads in the pool that we're closing, and wait at most `timeout` for them to
+    /// finish the jobs in the queue. The workers still running after the timeout will be detached,
+    /// and their ids are returned in the error. The delayed jobs and the recurring tasks are called
+    /// off, like on `close`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let mut pool = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// let ticks = Arc::new(AtomicUsize::new(0));
+    ///
+    /// let counter = Arc::clone(&ticks);
+    /// let id = pool.schedule_recurring(move || {
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    /// }, Duration::from_millis(5)).unwrap();
+    ///
+    /// let late = Arc::new(AtomicUsize::new(0));
+    /// let counter = Arc::clone(&late);
+    /// pool.exec_after(move || { counter.fetch_add(1, Ordering::SeqCst); }, Duration::from_millis(50))
+    ///     .unwrap();
+    ///
+    /// thread::sleep(Duration::from_millis(30));
+    /// assert!(pool.close_timeout(Duration::from_secs(1)).is_ok());
+    ///
+    /// // the timer is gone with the workers, even though the pool value is still around
+    /// let count = ticks.load(Ordering::SeqCst);
+    /// thread::sleep(Duration::from_millis(100));
+    /// assert_eq!(ticks.load(Ordering::SeqCst), count);
+    /// assert_eq!(late.load(Ordering::SeqCst), 0);
+    /// assert!(!pool.cancel_recurring(id));
+    /// ```
+    fn close_timeout(&mut self, timeout: Duration) -> Result<(), Vec<usize>> {
+        let deadline = Instant::now() + timeout;
+        let runnable = self.can_drain();
+        self.closed.store(true, Ordering::Release);
+        self.set_status(FLAG_CLOSING);
+        self.clear_timer();
+
+        if runnable {
+            self.metrics.wait_idle_until(Some(deadline), || self.is_drained());
+        }
+
+        let stuck = self.manager.write().remove_all_timeout(deadline);
+        self.stop_background();
+        if stuck.is_empty() {
+            return Ok(());
+        }
+
+        if is_debug_mode() {
+            log(
+                Level::Warn,
+                format_args!("Workers still running after the close timeout: {:?}", stuck),
+            );
+        }
+
+        Err(stuck)
+    }
+
     /// Signal the threads that they must quit now, and all queued jobs in the queue will be de-factor
-    /// discarded since we're closing the pool.
+    /// discarded since we're closing the pool. Each pool carries its own status flags, so the other
+    /// pools in the process are not affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::mpsc;
+    ///
+    /// let mut doomed = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    /// let survivor = ThreadPool::new(NonZeroUsize::new(2).unwrap());
+    ///
+    /// doomed.force_close();
+    /// assert!(doomed.exec(|| {}, false).is_err());
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// survivor.exec(move || tx.send(42).unwrap(), false).unwrap();
+    /// assert_eq!(rx.recv().unwrap(), 42);
+    /// ```
     fn force_close(&mut self) {
         self.shut_down(true);
     }
+
+    /// Block the caller until all jobs submitted before the call have been executed, e.g. before
+    /// shrinking or closing the pool. This is done by sending a sentinel job through each of the job
+    /// queues, and then waiting for the workers to finish the jobs they've taken off the queues.
+    ///
+    /// Since the pool doesn't tell the jobs submitted before and after the call apart, jobs submitted
+    /// concurrently may also be waited for. The call returns immediately if the pool has no worker,
+    /// or is hibernating or closing, where the queued jobs won't be run anyway.
+    ///
+    /// A job can't wait for its own pool to drain, since the job itself is one of the jobs to wait
+    /// for. The call returns immediately when it's made from a worker of the pool instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let mut pool = ThreadPool::new(NonZeroUsize::new(4).unwrap());
+    /// let done = Arc::new(AtomicUsize::new(0));
+    ///
+    /// for id in 0..40 {
+    ///     let done = Arc::clone(&done);
+    ///     pool.exec(move || {
+    ///         thread::sleep(Duration::from_millis(5));
+    ///         done.fetch_add(1, Ordering::SeqCst);
+    ///     }, id % 2 == 0).unwrap();
+    /// }
+    ///
+    /// pool.drain();
+    /// assert_eq!(done.load(Ordering::SeqCst), 40);
+    ///
+    /// // safe to shrink the pool now, no queued job is left behind
+    /// pool.resize(1);
+    ///
+    /// // draining the pool from one of its own jobs doesn't deadlock
+    /// let pool = Arc::new(pool);
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// let inner = Arc::clone(&pool);
+    ///
+    /// pool.exec(move || {
+    ///     inner.drain();
+    ///     tx.send(inner.wait_idle_timeout(Duration::from_secs(1))).unwrap();
+    /// }, false).unwrap();
+    ///
+    /// assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(false));
+    /// ```
+    fn drain(&self) {
+        self.wait_drained(None);
+    }
+
+    /// Remove the workers that have retired themselves after being idle for longer than the
+    /// `max_idle`, join their threads and run their drop behaviors, then return how many of them
+    /// are removed. The pool does this by itself whenever it adds workers, and in `auto_adjust`
+    /// before it sizes up the pool, so this is only needed to keep the `worker_count` of the
+    /// `stats` accurate in the meantime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// static BEFORE_DROP: AtomicUsize = AtomicUsize::new(0);
+    /// static AFTER_DROP: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut config = Config::new();
+    /// config
+    ///     .set_privileged_worker_count(1)
+    ///     .set_max_idle(Some(Duration::from_millis(50)));
+    /// config.set_before_drop(|_| { BEFORE_DROP.fetch_add(1, Ordering::SeqCst); });
+    /// config.set_after_drop(|_| { AFTER_DROP.fetch_add(1, Ordering::SeqCst); });
+    ///
+    /// let mut pool = ThreadPool::new_with_config(3, config);
+    /// thread::sleep(Duration::from_millis(200));
+    ///
+    /// // the idle workers are gone, but still on the books until they're reaped
+    /// assert_eq!(pool.worker_count(), 1);
+    /// assert_eq!(pool.get_size(), 3);
+    ///
+    /// assert_eq!(pool.reap(), 2);
+    /// assert_eq!(pool.get_size(), 1);
+    /// assert_eq!(BEFORE_DROP.load(Ordering::SeqCst), 2);
+    /// assert_eq!(AFTER_DROP.load(Ordering::SeqCst), 2);
+    /// ```
+    fn reap(&mut self) -> usize {
+        self.manager.write().worker_cleanup()
+    }
 }
 
 pub trait PoolState {
@@ -742,17 +3581,19 @@ pub trait PoolState {
     fn get_first_worker_id(&self) -> Option<usize>;
     fn get_last_worker_id(&self) -> Option<usize>;
     fn get_next_worker_id(&self, id: usize) -> Option<usize>;
+    fn worker_ids(&self) -> Vec<usize>;
+    fn worker_count(&self) -> usize;
 }
 
 impl PoolState for ThreadPool {
     #[inline]
     fn get_size(&self) -> usize {
-        self.manager.workers_count()
+        self.manager.read().workers_count()
     }
 
     #[inline]
     fn get_queue_length(&self) -> usize {
-        self.chan.0.len() + self.chan.1.len()
+        self.pending_jobs()
     }
 
     #[inline]
@@ -767,8 +3608,11 @@ impl PoolState for ThreadPool {
 
     fn set_queue_size_threshold(&mut self, threshold: usize) {
         if threshold > THRESHOLD && is_debug_mode() {
-            eprintln!(
-                "WARNING: You're trying to set the queue size larger than the soft maximum threshold of 100000, this could cause drop of performance"
+            log(
+                Level::Warn,
+                format_args!(
+                    "WARNING: You're trying to set the queue size larger than the soft maximum threshold of 100000, this could cause drop of performance"
+                ),
             );
         }
 
@@ -780,25 +3624,81 @@ impl PoolState for ThreadPool {
     }
 
     fn get_first_worker_id(&self) -> Option<usize> {
-        match self.manager.first_worker_id() {
+        match self.manager.read().first_worker_id() {
             0 => None,
             id => Some(id),
         }
     }
 
     fn get_last_worker_id(&self) -> Option<usize> {
-        match self.manager.last_worker_id() {
+        match self.manager.read().last_worker_id() {
             0 => None,
             id => Some(id),
         }
     }
 
+    /// Get the id of the worker next to the given one, in the ascending order of the worker ids. The
+    /// given worker needs not to be alive, e.g. it can be killed during the walk.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    ///
+    /// let mut pool = ThreadPool::new(NonZeroUsize::new(3).unwrap());
+    /// pool.extend(2);
+    ///
+    /// let ids = pool.worker_ids();
+    /// assert_eq!(ids.len(), 5);
+    ///
+    /// // kill a worker in the middle
+    /// pool.kill_worker(ids[2]);
+    /// assert_eq!(pool.worker_count(), 4);
+    ///
+    /// let mut walked = Vec::new();
+    /// let mut next = pool.get_first_worker_id();
+    ///
+    /// while let Some(id) = next {
+    ///     walked.push(id);
+    ///     next = pool.get_next_worker_id(id);
+    /// }
+    ///
+    /// assert_eq!(walked, vec![ids[0], ids[1], ids[3], ids[4]]);
+    /// assert_eq!(pool.get_next_worker_id(ids[2]), Some(ids[3]));
+    /// assert_eq!(pool.get_last_worker_id(), Some(ids[4]));
+    /// assert_eq!(pool.get_next_worker_id(ids[4]), None);
+    ///
+    /// // the ids near the end are still found once the ids outnumber the workers
+    /// pool.kill_worker(ids[0]);
+    /// pool.kill_worker(ids[4]);
+    /// assert_eq!(pool.worker_count(), 2);
+    ///
+    /// assert_eq!(pool.get_first_worker_id(), Some(ids[1]));
+    /// assert_eq!(pool.get_next_worker_id(ids[1]), Some(ids[3]));
+    /// assert_eq!(pool.get_next_worker_id(ids[3]), None);
+    /// ```
     fn get_next_worker_id(&self, current_id: usize) -> Option<usize> {
-        match self.manager.next_worker_id(current_id) {
+        match self.manager.read().next_worker_id(current_id) {
             0 => None,
             id => Some(id),
         }
     }
+
+    /// Get the ids of all workers in the pool, in the ascending order.
+    fn worker_ids(&self) -> Vec<usize> {
+        self.manager.read().worker_ids()
+    }
+
+    /// Get the number of workers in the pool that are actually alive. Unlike `get_size`, the workers
+    /// that have retired themselves after idling for too long, or have quit otherwise, are left out
+    /// even if the pool hasn't cleaned them up yet.
+    #[inline]
+    fn worker_count(&self) -> usize {
+        self.manager.read().live_workers_count()
+    }
 }
 
 trait DispatchFlavors {
@@ -856,12 +3756,12 @@ impl DispatchFlavors for ThreadPool {
                             // is dropped, it should be fine since we need hands to get things
                             // done at the moment. Balancing or releasing resources can happen
                             // later.
-                            self.manager.drop_many(chan.1, retry as usize);
+                            self.manager.read().drop_many(chan.1, retry as usize);
                         }
                         TimeoutPolicy::DirectRun => {
                             // directly run the job; the termination message will not be
                             // sent in this workflow, so we shall not worry about that.
-                            if let Message::SingleJob(job) = retry_message {
+                            if let Some(job) = retry_message.into_job() {
                                 job.call_box();
                             }
 
@@ -909,7 +3809,7 @@ impl DispatchFlavors for ThreadPool {
 
                 if let TimeoutPolicy::LossyRetry = self.timeout_policy {
                     // drop a message and try again, just once
-                    self.manager.drop_one(chan.1);
+                    self.manager.read().drop_one(chan.1);
 
                     // send the message again and hopefully no one else take the space
                     chan.0.try_send(msg).map_err(|err| match err {
@@ -927,7 +3827,7 @@ impl DispatchFlavors for ThreadPool {
     fn wake_workers(&self) {
         if self.status.has_hibernate_workers() {
             // wake up workers now
-            self.manager.wake_up();
+            self.manager.read().wake_up();
             self.status.toggle_flag(FLAG_SLEEP_WORKERS, false);
         }
     }
@@ -936,8 +3836,11 @@ impl DispatchFlavors for ThreadPool {
 impl Drop for ThreadPool {
     fn drop(&mut self) {
         if is_debug_mode() {
-            println!(
-                "Shutting down this individual pool, sending terminate message to all workers."
+            log(
+                Level::Info,
+                format_args!(
+                    "Shutting down this individual pool, sending terminate message to all workers."
+                ),
             );
         }
 
@@ -964,24 +3867,15 @@ impl PoolStatus {
         PoolStatus(unsafe { NonNull::new_unchecked(Box::into_raw(wrapper)) })
     }
 
-    fn closing(&self) -> bool {
-        unsafe {
-            // FLAG_CLOSING = 1, FLAG_FORCE_CLOSE == 2
-            self.0
-                .as_ref()
-                .fetch_and(FLAG_CLOSING | FLAG_FORCE_CLOSE, Ordering::Acquire)
-                > 0
-        }
+    // only peek at the flags: a `fetch_and` would wipe out the other flags of the pool on the way
+    pub(crate) fn closing(&self) -> bool {
+        // FLAG_CLOSING = 1, FLAG_FORCE_CLOSE == 2
+        self.load() & (FLAG_CLOSING | FLAG_FORCE_CLOSE) > 0
     }
 
     fn has_hibernate_workers(&self) -> bool {
-        unsafe {
-            // FLAG_HIBERNATING = 4, FLAG_SLEEP_WORKERS = 32
-            self.0
-                .as_ref()
-                .fetch_and(FLAG_HIBERNATING | FLAG_SLEEP_WORKERS, Ordering::Acquire)
-                > 0
-        }
+        // FLAG_HIBERNATING = 4, FLAG_SLEEP_WORKERS = 32
+        self.load() & (FLAG_HIBERNATING | FLAG_SLEEP_WORKERS) > 0
     }
 
     fn calc_new_stat(&self, old: u8, flag: u8, toggle_on: bool) -> Result<u8, ()> {
@@ -1015,7 +3909,7 @@ impl PoolStatus {
     }
 
     pub(crate) fn toggle_flag(&self, flag: u8, toggle_on: bool) {
-        assert!(flag % 2 == 0 || flag == 1, "forbidden to set multiple flags at the same time");
+        assert!(flag.is_multiple_of(2) || flag == 1, "forbidden to set multiple flags at the same time");
 
         unsafe {
             let mut old: u8 = self.0.as_ref().load(Ordering::Acquire);
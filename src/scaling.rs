@@ -0,0 +1,212 @@
+use std::time::{Duration, Instant};
+
+use crate::metrics::PoolStats;
+use parking_lot::Mutex;
+
+/// The max number of workers the default policy will grow the pool to.
+pub(crate) const THRESHOLD: usize = 1024;
+
+/// The queue length beyond which the default policy will add more workers.
+const AUTO_EXTEND_TRIGGER_SIZE: usize = 2;
+
+/// Decide how many workers the pool shall have when `auto_adjust` is called, e.g. periodically by
+/// the auto adjustment thread in the `shared_mode` and the `index_mode`. Set a custom policy through
+/// `Config::set_scaling_policy` to override the built-in heuristic.
+pub trait ScalingPolicy: Send + Sync {
+    /// Return the desired number of workers given the live statistics of the pool and its current
+    /// size. Returning the `current` size, or 0, leaves the pool untouched. The pending jobs are in
+    /// `stats.jobs_pending`, and how busy the workers are in `stats.idle_ratio()`.
+    fn desired_size(&self, stats: &PoolStats, current: usize) -> usize;
+}
+
+/// The built-in heuristic: if more than a couple of jobs are queued up, grow the pool by the queue
+/// length, unless the pool is already beyond the extension threshold. Otherwise the pool keeps its
+/// size, and the extra workers are left to quit on their own once they've been idle for longer than
+/// the `max_idle` of the config, if it's set.
+#[derive(Clone, Copy, Debug)]
+pub struct DefaultScalingPolicy {
+    init_size: usize,
+    extend_threshold: usize,
+}
+
+impl DefaultScalingPolicy {
+    pub fn new(init_size: usize) -> Self {
+        Self::with_threshold(init_size, THRESHOLD)
+    }
+
+    pub fn with_threshold(init_size: usize, extend_threshold: usize) -> Self {
+        DefaultScalingPolicy {
+            init_size,
+            extend_threshold,
+        }
+    }
+
+    /// The amortized new size of the pool, or `None` if the pool shall keep its size.
+    pub(crate) fn amortized_size(&self, queue_length: usize, worker_count: usize) -> Option<usize> {
+        if queue_length == 0 {
+            return None;
+        }
+
+        if queue_length > AUTO_EXTEND_TRIGGER_SIZE && worker_count <= self.extend_threshold {
+            // The workers size may be larger than the threshold, but that's okay since we won't
+            // add more workers from this point on, unless some workers are killed.
+            Some(worker_count + queue_length)
+        } else if queue_length == 0 && worker_count > self.init_size {
+            if worker_count == (self.init_size + 1) {
+                Some(self.init_size)
+            } else {
+                Some((worker_count + self.init_size) / 2)
+            }
+        } else {
+            None
+        }
+    }
+}
+
+impl ScalingPolicy for DefaultScalingPolicy {
+    fn desired_size(&self, stats: &PoolStats, current: usize) -> usize {
+        self.amortized_size(stats.queued_normal + stats.queued_priority + stats.queued_low, current)
+            .unwrap_or(current)
+    }
+}
+
+/// The tunables of the auto scaling, set it through `Config::set_auto_scale_policy`. Each time the
+/// pool is auto adjusted, it grows by `step` workers if all workers are busy and more than
+/// `scale_up_queue_threshold` jobs per worker are queued up, or shrinks by `step` workers once it
+/// has been idle for at least the `scale_down_idle_threshold`. The pool size always stays between
+/// the `min_workers` and `max_workers` of the config.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::{Arc, RwLock};
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let mut config = Config::new().with_worker_bounds(1, 3);
+/// config.set_auto_scale_policy(AutoScalePolicy {
+///     scale_up_queue_threshold: 1,
+///     scale_down_idle_threshold: Duration::from_millis(50),
+///     step: 1,
+/// });
+///
+/// let mut pool = ThreadPool::new_with_config(1, config);
+///
+/// // back up the queue with jobs that are held until the gate opens
+/// let gate = Arc::new(RwLock::new(()));
+/// let guard = gate.write().unwrap();
+///
+/// for _ in 0..16 {
+///     let gate = Arc::clone(&gate);
+///     pool.exec(move || drop(gate.read()), false).unwrap();
+/// }
+///
+/// // wait for all workers to be stuck at the gate
+/// let wait_busy = |pool: &ThreadPool| {
+///     while pool.stats().active_workers < pool.get_size() {
+///         thread::yield_now();
+///     }
+/// };
+///
+/// wait_busy(&pool);
+/// pool.auto_adjust();
+/// assert_eq!(pool.get_size(), 2);
+///
+/// wait_busy(&pool);
+/// pool.auto_adjust();
+/// assert_eq!(pool.get_size(), 3);
+///
+/// // stay below the max workers
+/// wait_busy(&pool);
+/// pool.auto_adjust();
+/// assert_eq!(pool.get_size(), 3);
+///
+/// drop(guard);
+/// pool.drain();
+///
+/// // the pool shall be idle for long enough before shrinking
+/// pool.auto_adjust();
+/// assert_eq!(pool.get_size(), 3);
+///
+/// thread::sleep(Duration::from_millis(60));
+///
+/// for _ in 0..4 {
+///     pool.auto_adjust();
+/// }
+///
+/// assert_eq!(pool.get_size(), 1);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct AutoScalePolicy {
+    /// Grow the pool once the queued jobs per worker exceed this threshold.
+    pub scale_up_queue_threshold: usize,
+
+    /// Shrink the pool once no job has been running for this long.
+    pub scale_down_idle_threshold: Duration,
+
+    /// The number of workers to add or remove on each adjustment, at least 1.
+    pub step: usize,
+}
+
+impl Default for AutoScalePolicy {
+    fn default() -> Self {
+        AutoScalePolicy {
+            scale_up_queue_threshold: AUTO_EXTEND_TRIGGER_SIZE,
+            scale_down_idle_threshold: Duration::from_secs(30),
+            step: 1,
+        }
+    }
+}
+
+/// Apply the `AutoScalePolicy`, which needs to track since when the pool has been idle.
+pub(crate) struct AutoScaler {
+    policy: AutoScalePolicy,
+    idle_since: Mutex<Option<Instant>>,
+}
+
+impl AutoScaler {
+    pub(crate) fn new(policy: AutoScalePolicy) -> Self {
+        AutoScaler {
+            policy,
+            idle_since: Mutex::new(None),
+        }
+    }
+}
+
+impl ScalingPolicy for AutoScaler {
+    fn desired_size(&self, stats: &PoolStats, current: usize) -> usize {
+        let policy = &self.policy;
+        let step = policy.step.max(1);
+        let pending = stats.queued_normal + stats.queued_priority + stats.queued_low;
+        let mut idle_since = self.idle_since.lock();
+
+        // only grow the pool if all workers are busy, otherwise the idle ones will take the backlog
+        let backed_up = pending > policy.scale_up_queue_threshold.saturating_mul(current)
+            && stats.active_workers >= current;
+
+        let target = if backed_up {
+            idle_since.take();
+            current.saturating_add(step)
+        } else if stats.active_workers == 0 {
+            // the pool keeps shrinking on each adjustment as long as it stays idle; a few queued
+            // messages may just be the termination notices of the workers removed last time.
+            let since = *idle_since.get_or_insert_with(Instant::now);
+
+            if since.elapsed() >= policy.scale_down_idle_threshold {
+                current.saturating_sub(step)
+            } else {
+                current
+            }
+        } else {
+            idle_since.take();
+            current
+        };
+
+        // the bounds of the config are applied by `auto_adjust`, and like the default policy, don't
+        // grow beyond the threshold on our own
+        target.clamp(1, THRESHOLD)
+    }
+}
@@ -0,0 +1,155 @@
+use std::any::Any;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::model::{FnBox, Job};
+use crate::pool::{ExecutionError, ThreadPool};
+use parking_lot::{Condvar, Mutex};
+
+/// The handle to submit the scoped jobs, see `ThreadPool::scope` for details.
+pub struct Scope<'s> {
+    pool: &'s ThreadPool,
+    pending: Arc<Pending>,
+
+    // keep the scope invariant over `'s`, such that the jobs can't borrow anything shorter-lived
+    _marker: PhantomData<&'s mut &'s ()>,
+}
+
+/// The number of scoped jobs that are not done yet, and the panic of the first job that panicked.
+struct Pending {
+    count: AtomicUsize,
+    lock: Mutex<()>,
+    done: Condvar,
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+/// Count the job as done when it's dropped, whether it has been run, has panicked, or has been
+/// discarded without running at all.
+struct PendingGuard(Arc<Pending>);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let _lock = self.0.lock.lock();
+            self.0.done.notify_all();
+        }
+    }
+}
+
+impl<'s> Scope<'s> {
+    pub(crate) fn run<F, R>(pool: &'s ThreadPool, f: F) -> R
+    where
+        F: FnOnce(&Scope<'s>) -> R,
+    {
+        let scope = Scope {
+            pool,
+            pending: Arc::new(Pending {
+                count: AtomicUsize::new(0),
+                lock: Mutex::new(()),
+                done: Condvar::new(),
+                panic: Mutex::new(None),
+            }),
+            _marker: PhantomData,
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+        // the jobs may still borrow from the caller's stack, so wait for all of them even if the
+        // scope body has panicked.
+        scope.wait();
+
+        // the panic of the scope body comes first, then the ones from the jobs
+        let job_panic = scope.pending.panic.lock().take();
+        match (result, job_panic) {
+            (Ok(res), None) => res,
+            (Err(err), _) | (Ok(_), Some(err)) => panic::resume_unwind(err),
+        }
+    }
+
+    /// Same as `exec`, but panics if the pool rejects the job, e.g. when the pool is closing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate threads_pool;
+    ///
+    /// use threads_pool::*;
+    /// use std::num::NonZeroUsize;
+    /// use std::panic::{self, AssertUnwindSafe};
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// let pool = ThreadPool::new(NonZeroUsize::new(4).unwrap());
+    /// let numbers: Vec<u64> = (1..=1000).collect();
+    /// let total = AtomicU64::new(0);
+    ///
+    /// pool.scope(|scope| {
+    ///     for chunk in numbers.chunks(100) {
+    ///         let total = &total;
+    ///         scope.spawn(move || {
+    ///             total.fetch_add(chunk.iter().sum::<u64>(), Ordering::SeqCst);
+    ///         });
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(total.load(Ordering::SeqCst), 500500);
+    ///
+    /// // the panic of a job is re-thrown once all jobs are done
+    /// let done = AtomicU64::new(0);
+    /// let result = panic::catch_unwind(AssertUnwindSafe(|| {
+    ///     pool.scope(|scope| {
+    ///         scope.spawn(|| panic!("bad chunk"));
+    ///
+    ///         for _ in 0..10 {
+    ///             scope.spawn(|| { done.fetch_add(1, Ordering::SeqCst); });
+    ///         }
+    ///     })
+    /// }));
+    ///
+    /// assert!(result.is_err());
+    /// assert_eq!(done.load(Ordering::SeqCst), 10);
+    /// ```
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 's,
+    {
+        if let Err(err) = self.exec(f) {
+            panic!("Unable to submit the scoped job: {}", err);
+        }
+    }
+
+    /// Submit a job that may borrow the data living longer than the scope. The job is queued just
+    /// like `ThreadPool::exec` does, and an error will be returned if the pool rejects the job. If
+    /// the job panics, the panic will be re-thrown by `ThreadPool::scope` after all jobs are done.
+    pub fn exec<F>(&self, f: F) -> Result<(), ExecutionError>
+    where
+        F: FnOnce() + Send + 's,
+    {
+        self.pending.count.fetch_add(1, Ordering::AcqRel);
+        let guard = PendingGuard(Arc::clone(&self.pending));
+
+        let job: Box<dyn FnBox + Send + 's> = Box::new(move || {
+            if let Err(err) = panic::catch_unwind(AssertUnwindSafe(f)) {
+                guard.0.panic.lock().get_or_insert(err);
+            }
+
+            // report back only after the panic is saved
+            drop(guard);
+        });
+
+        // the job can't outlive the scope, since the scope won't return until the job is dropped,
+        // which is when the guard reports back.
+        let job = unsafe { mem::transmute::<Box<dyn FnBox + Send + 's>, Job>(job) };
+
+        self.pool.submit(job, None, false)
+    }
+
+    fn wait(&self) {
+        let mut lock = self.pending.lock.lock();
+        while self.pending.count.load(Ordering::Acquire) > 0 {
+            self.pending.done.wait(&mut lock);
+        }
+    }
+}
@@ -1,160 +1,708 @@
 #![allow(dead_code)]
 
-use std::io::ErrorKind;
+use std::future::Future;
+use std::io::{self, ErrorKind};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
-//use std::mem::MaybeUninit;
 
-use crate::config::{Config, ConfigStatus};
-use crate::debug::is_debug_mode;
-use crate::model::StaticStore;
-use crate::pool::{PoolManager, ThreadPool};
-use parking_lot::{Once, OnceState, ONCE_INIT};
+use crate::config::{BgThreadConfig, Config, ConfigStatus};
+use crate::debug::{is_debug_mode, log, Level};
+use crate::executor::block_on;
+use crate::model::AutoAdjustHandler;
+use crate::metrics::PoolStats;
+use crate::pool::{
+    AlreadyInitialized, BatchError, ExecutionError, PoolManager, PoolState, ThreadPool,
+};
+use crossbeam_channel::{self as channel, Receiver};
+use parking_lot::{Mutex, Once, OnceState, RwLock, ONCE_INIT};
 
 /// Atomic flags
 static ONCE: Once = ONCE_INIT;
 static CLOSING: AtomicBool = AtomicBool::new(false);
+static SPAWN_DETACHED: AtomicBool = AtomicBool::new(false);
 
 /// The actual pool storage
-static mut POOL: StaticStore<Pool> = StaticStore::init();
+static POOL: OnceLock<Pool> = OnceLock::new();
 
 struct Pool {
-    store: ThreadPool,
-    auto_mode: bool,
-    auto_adjust_handler: Option<JoinHandle<()>>,
+    store: RwLock<ThreadPool>,
+    auto_mode: AtomicBool,
+    paused: AtomicBool,
+    auto_adjust_handler: Mutex<Option<AutoAdjustHandler>>,
+    bg_config: BgThreadConfig,
 }
 
 impl Pool {
     #[inline]
-    fn inner() -> Result<&'static mut Pool, ErrorKind> {
+    fn inner() -> Result<&'static Pool, ErrorKind> {
         if !CLOSING.load(Ordering::Acquire) {
-            unsafe { POOL.as_mut() }
+            POOL.get().ok_or(ErrorKind::NotFound)
         } else {
             Err(ErrorKind::PermissionDenied)
         }
     }
 
-    fn take() -> Result<&'static mut Pool, ErrorKind> {
+    /// Check if the pool has been created and not yet closed.
+    #[inline]
+    fn is_some() -> bool {
+        Pool::inner().is_ok()
+    }
+
+    fn take() -> Result<&'static Pool, ErrorKind> {
         if CLOSING.compare_exchange_weak(false, true, Ordering::SeqCst, Ordering::Relaxed)
             == Ok(false)
         {
-            unsafe { POOL.as_mut() }
+            POOL.get().ok_or(ErrorKind::NotFound)
         } else {
             Err(ErrorKind::PermissionDenied)
         }
     }
 
     #[inline]
-    fn toggle_auto_mode(&mut self, enabled: bool) {
-        if self.auto_mode == enabled {
+    fn toggle_auto_mode(&self, enabled: bool) {
+        if self.auto_mode.swap(enabled, Ordering::AcqRel) == enabled {
             return;
         }
 
-        self.auto_mode = enabled;
-        self.store.toggle_auto_scale(enabled);
-    }
-}
-
-impl Drop for Pool {
-    fn drop(&mut self) {
-        if !CLOSING.load(Ordering::Acquire) {
-            // don't double drop
-            close();
-        }
+        self.store.write().toggle_auto_scale(enabled);
     }
 }
 
 impl Default for Pool {
     fn default() -> Self {
         Pool {
-            store: ThreadPool::build(1),
-            auto_mode: false,
-            auto_adjust_handler: None,
+            store: RwLock::new(ThreadPool::build(1)),
+            auto_mode: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            auto_adjust_handler: Mutex::new(None),
+            bg_config: BgThreadConfig::default(),
         }
     }
 }
 
+/// Check if the shared pool has been initialized and not yet closed, such that the jobs submitted
+/// through `run` will be taken by the pool rather than by dedicated threads.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+///
+/// assert!(!shared_mode::is_initialized());
+///
+/// shared_mode::initialize(2).expect("Unable to spawn the pool workers");
+/// assert!(shared_mode::is_initialized());
+///
+/// shared_mode::close();
+/// assert!(!shared_mode::is_initialized());
+/// ```
+#[inline]
+pub fn is_initialized() -> bool {
+    Pool::is_some()
+}
+
+/// Initialize the shared pool with `size` workers and the default configurations. Unlike
+/// `ThreadPool::new`, the size stays a plain `usize` for the ease of use, and a zero size is taken
+/// as one worker.
 #[inline]
-pub fn initialize(size: usize) {
-    init_with_config(size, Config::default());
+pub fn initialize(size: usize) -> io::Result<()> {
+    init_with_config(size, Config::default())
 }
 
-pub fn initialize_with_auto_adjustment(size: usize, period: Option<Duration>) {
+/// Initialize the shared pool, which will be auto adjusted every `period` while keeping its size
+/// between `min_workers` and `max_workers`, if it's set. The auto adjustment stops when the pool
+/// is closed.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::time::Duration;
+///
+/// shared_mode::initialize_with_auto_adjustment(2, Some(Duration::from_secs(1)), 1, Some(4))
+///     .expect("Unable to spawn the pool workers");
+///
+/// shared_mode::run(|| println!("Hello from the auto adjusted pool")).unwrap();
+/// shared_mode::close();
+/// ```
+pub fn initialize_with_auto_adjustment(
+    size: usize,
+    period: Option<Duration>,
+    min_workers: usize,
+    max_workers: Option<usize>,
+) -> io::Result<()> {
     let mut config = Config::default();
-    config.set_refresh_period(period);
-    init_with_config(size, config);
+    config
+        .set_refresh_period(period)
+        .set_min_workers(min_workers)
+        .set_max_workers(max_workers);
+    init_with_config(size, config)
 }
 
-pub fn init_with_config(size: usize, config: Config) {
-    assert_eq!(
-        ONCE.state(),
-        OnceState::New,
-        "The pool has already been initialized..."
-    );
+/// Initialize the shared pool with the given configurations. An error will be returned if the OS
+/// refuses to spawn any worker thread for the pool, or if the pool has been initialized before, in
+/// which case the error wraps `AlreadyInitialized`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::io::ErrorKind;
+///
+/// shared_mode::init_with_config(2, Config::new()).expect("Unable to spawn the pool workers");
+///
+/// // initializing the pool again won't disturb the running pool
+/// let err = shared_mode::initialize(4).unwrap_err();
+/// assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+/// assert!(err.get_ref().unwrap().is::<AlreadyInitialized>());
+/// assert_eq!(shared_mode::stats().unwrap().worker_count, 2);
+///
+/// shared_mode::close();
+/// ```
+pub fn init_with_config(size: usize, config: Config) -> io::Result<()> {
+    init_pool(size, config, false)
+}
+
+/// Initialize the shared pool like `init_with_config`, and let it add workers when it's under
+/// pressure if `auto_scale` is set, even if it's not auto adjusted periodically.
+pub(crate) fn init_pool(size: usize, config: Config, auto_scale: bool) -> io::Result<()> {
+    if ONCE.state() != OnceState::New {
+        return Err(AlreadyInitialized.into());
+    }
 
     let pool_size = match size {
         0 => 1,
         _ => size,
     };
 
+    // build the pool before taking the ONCE, such that a failed attempt leaves it untouched
+    let mut pool = Some(create(pool_size, config, auto_scale)?);
+
+    // if someone else has beaten us to it, the result stays as is
+    let mut result = Err(AlreadyInitialized.into());
     ONCE.call_once(|| {
-        create(pool_size, config);
+        if let Some(pool) = pool.take() {
+            install(pool);
+            result = Ok(());
+        }
     });
+
+    // our pool is closed when dropped, but its auto adjustment shall be stopped first
+    if let Some(pool) = pool {
+        stop_auto_adjustment(&pool);
+    }
+
+    result
 }
 
-pub fn run<F: FnOnce() + Send + 'static>(f: F) {
-    match Pool::inner() {
-        Ok(pool) => {
-            if pool.store.exec(f, false).is_err() && is_debug_mode() {
-                eprintln!("The execution of this job has failed...");
-            }
-        }
-        Err(e) => {
+/// Choose if the jobs submitted through `run`, `run_batch` and `spawn` shall be run on dedicated
+/// threads when the shared pool has been closed or has not been initialized. This is off by
+/// default, and such jobs are rejected instead. Mind that the number of such threads is unbounded.
+/// The setting outlives the pool, and can be made before the pool is initialized.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::mpsc;
+///
+/// // no pool to take the job, and it's dropped
+/// assert!(matches!(shared_mode::run(|| {}), Err(ExecutionError::Uninitialized)));
+///
+/// shared_mode::set_spawn_detached(true);
+/// let (tx, rx) = mpsc::channel();
+/// let res = shared_mode::run(move || tx.send(()).unwrap());
+/// assert!(matches!(res, Err(ExecutionError::PoolGone)));
+/// rx.recv().unwrap();
+///
+/// // a detached thread is never spawned by `try_run`
+/// assert!(matches!(shared_mode::try_run(|| {}), Err(ExecutionError::Uninitialized)));
+/// ```
+pub fn set_spawn_detached(enabled: bool) {
+    SPAWN_DETACHED.store(enabled, Ordering::Release);
+}
+
+/// Stop the shared pool from taking new jobs, e.g. during a maintenance, without closing it. The
+/// jobs submitted from now on are rejected with `ExecutionError::PoolPaused`, and never run on a
+/// dedicated thread, while the workers keep running the jobs taken before the pause. Call `resume`
+/// to take the jobs again. The pause goes with the pool: it's a no-op if the pool is not running,
+/// and it's lifted once the pool is closed.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::mpsc;
+///
+/// shared_mode::initialize(2).expect("Unable to spawn the pool workers");
+///
+/// let (tx, rx) = mpsc::channel();
+/// let done = tx.clone();
+/// shared_mode::run(move || done.send("before").unwrap()).unwrap();
+///
+/// shared_mode::pause();
+/// assert!(shared_mode::is_paused());
+///
+/// let res = shared_mode::run(|| panic!("never run"));
+/// assert!(matches!(res, Err(ExecutionError::PoolPaused)));
+/// assert_eq!(rx.recv().unwrap(), "before");
+///
+/// shared_mode::resume();
+/// shared_mode::run(move || tx.send("after").unwrap()).unwrap();
+/// assert_eq!(rx.recv().unwrap(), "after");
+///
+/// // the closed pool is no longer paused, and tells the jobs that it's gone instead
+/// shared_mode::pause();
+/// shared_mode::close();
+/// assert!(!shared_mode::is_paused());
+/// assert!(matches!(shared_mode::run(|| {}), Err(ExecutionError::PoolClosed)));
+/// ```
+pub fn pause() {
+    if let Ok(pool) = Pool::inner() {
+        pool.paused.store(true, Ordering::Release);
+    }
+}
+
+/// Let the shared pool take the new jobs again after a `pause`.
+pub fn resume() {
+    if let Ok(pool) = Pool::inner() {
+        pool.paused.store(false, Ordering::Release);
+    }
+}
+
+/// Check if the shared pool is running and has been paused.
+pub fn is_paused() -> bool {
+    Pool::inner().is_ok_and(|pool| pool.paused.load(Ordering::Acquire))
+}
+
+/// Submit the job to the shared pool. If the pool has been closed or not yet initialized, the job
+/// will be rejected, or run on a dedicated thread if it's allowed by `set_spawn_detached`, in which
+/// case the `ExecutionError::PoolGone` error will be returned, such that the caller can decide if
+/// the pool shall be restarted. The job is always rejected while the pool is paused.
+pub fn run<F: FnOnce() + Send + 'static>(f: F) -> Result<(), ExecutionError> {
+    match dispatch(f) {
+        Ok(()) => Ok(()),
+        Err((err, Some(f))) if SPAWN_DETACHED.load(Ordering::Acquire) => {
             // This could happen after the pool is closed, just execute the job
             thread::spawn(f);
 
             if is_debug_mode() {
-                eprintln!(
-                    "The pool is in invalid state: {:?}, the thread pool should be restarted...",
-                    e
+                log(
+                    Level::Warn,
+                    format_args!(
+                        "The pool is in invalid state: {:?}, the thread pool should be restarted...",
+                        err
+                    ),
                 );
             }
+
+            Err(ExecutionError::PoolGone)
+        }
+        Err((err, _)) => {
+            if is_debug_mode() {
+                log(Level::Warn, format_args!("The execution of this job has failed: {:?}", err));
+            }
+
+            Err(err)
         }
+    }
+}
+
+/// Submit the job to the shared pool like `run`, but never run it on a dedicated thread. The error
+/// tells why the job is rejected: `ExecutionError::Uninitialized` if the pool has not been
+/// initialized, `ExecutionError::PoolClosed` if it has been closed, or whatever the pool returns
+/// from `ThreadPool::exec`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::mpsc;
+///
+/// assert!(matches!(shared_mode::try_run(|| {}), Err(ExecutionError::Uninitialized)));
+///
+/// shared_mode::initialize(2).expect("Unable to spawn the pool workers");
+/// let (tx, rx) = mpsc::channel();
+/// shared_mode::try_run(move || tx.send(()).unwrap()).unwrap();
+/// rx.recv().unwrap();
+///
+/// shared_mode::close();
+/// assert!(matches!(shared_mode::try_run(|| {}), Err(ExecutionError::PoolClosed)));
+/// ```
+pub fn try_run<F: FnOnce() + Send + 'static>(f: F) -> Result<(), ExecutionError> {
+    dispatch(f).map_err(|(err, _)| err)
+}
+
+/// Submit a batch of jobs to the shared pool at once, see `ThreadPool::exec_batch` for details. If
+/// the pool has been closed or not yet initialized, the jobs will be rejected, or run on a
+/// dedicated thread if it's allowed by `set_spawn_detached`, in which case the
+/// `ExecutionError::PoolGone` error will be returned, with none of the jobs rejected. The jobs are
+/// always rejected while the pool is paused.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::mpsc;
+///
+/// let (tx, rx) = mpsc::channel();
+/// let batch = |tx: &mpsc::Sender<usize>| {
+///     (0..4).map(|i| { let tx = tx.clone(); move || tx.send(i).unwrap() }).collect::<Vec<_>>()
+/// };
+///
+/// // the pool is not running yet, so the jobs are turned away
+/// let err = shared_mode::run_batch(batch(&tx)).unwrap_err();
+/// assert!(matches!(err.error, ExecutionError::Uninitialized));
+/// assert_eq!(err.rejected, 4);
+///
+/// // or run on a dedicated thread, in which case none of them is rejected
+/// shared_mode::set_spawn_detached(true);
+///
+/// let err = shared_mode::run_batch(batch(&tx)).unwrap_err();
+/// assert!(matches!(err.error, ExecutionError::PoolGone));
+/// assert_eq!(err.rejected, 0);
+///
+/// drop(tx);
+/// assert_eq!(rx.iter().sum::<usize>(), 6);
+/// ```
+pub fn run_batch<I, F>(jobs: I) -> Result<(), BatchError>
+where
+    I: IntoIterator<Item = F>,
+    F: FnOnce() + Send + 'static,
+{
+    if is_paused() {
+        return Err(BatchError {
+            error: ExecutionError::PoolPaused,
+            rejected: jobs.into_iter().count(),
+        });
+    }
+
+    let err = match Pool::inner() {
+        Ok(pool) => return pool.store.read().exec_batch(jobs),
+        Err(kind) => gone_error(kind),
     };
+
+    let jobs: Vec<F> = jobs.into_iter().collect();
+
+    if !SPAWN_DETACHED.load(Ordering::Acquire) {
+        return Err(BatchError {
+            error: err,
+            rejected: jobs.len(),
+        });
+    }
+
+    // This could happen after the pool is closed, just execute the jobs
+    thread::spawn(move || jobs.into_iter().for_each(|job| job()));
+
+    if is_debug_mode() {
+        log(
+            Level::Warn,
+            format_args!(
+                "The pool is in invalid state: {:?}, the thread pool should be restarted...",
+                err
+            ),
+        );
+    }
+
+    Err(BatchError {
+        error: ExecutionError::PoolGone,
+        rejected: 0,
+    })
+}
+
+/// Run the future on the shared pool, and the returned receiver will get the output of the future
+/// once it's completed. If the pool has been closed or not yet initialized, the future will be run
+/// to completion on a dedicated thread if it's allowed by `set_spawn_detached`; otherwise it's
+/// dropped, and the receiver will be disconnected. The future is dropped as well if the pool is
+/// paused.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+///
+/// shared_mode::initialize(2).expect("Unable to spawn the pool workers");
+///
+/// let rx = shared_mode::spawn(async { 40 + 2 });
+/// assert_eq!(rx.recv().unwrap(), 42);
+///
+/// shared_mode::close();
+/// ```
+pub fn spawn<F, R>(fut: F) -> Receiver<R>
+where
+    F: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    if is_paused() {
+        return channel::bounded(1).1;
+    }
+
+    match Pool::inner() {
+        Ok(pool) => pool.store.read().spawn_future(fut),
+        Err(e) => {
+            let (tx, rx) = channel::bounded(1);
+            if !SPAWN_DETACHED.load(Ordering::Acquire) {
+                return rx;
+            }
+
+            // This could happen after the pool is closed, just run the future
+            thread::spawn(move || {
+                if let Ok(res) = block_on(fut) {
+                    tx.send(res).unwrap_or_default();
+                }
+            });
+
+            if is_debug_mode() {
+                log(
+                    Level::Warn,
+                    format_args!(
+                        "The pool is in invalid state: {:?}, the thread pool should be restarted...",
+                        e
+                    ),
+                );
+            }
+
+            rx
+        }
+    }
+}
+
+/// Check how busy the shared pool is at the moment, or `None` if the pool is not running.
+pub fn stats() -> Option<PoolStats> {
+    Pool::inner().ok().map(|pool| pool.store.read().stats())
+}
+
+/// The number of workers alive in the shared pool, or 0 if the pool is not running.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+///
+/// assert_eq!(shared_mode::worker_count(), 0);
+///
+/// shared_mode::initialize(3).unwrap();
+/// assert_eq!(shared_mode::worker_count(), 3);
+///
+/// shared_mode::close();
+/// ```
+pub fn worker_count() -> usize {
+    Pool::inner().map(|pool| pool.store.read().worker_count()).unwrap_or(0)
+}
+
+/// The number of jobs waiting in the shared pool's queues, or 0 if the pool is not running.
+pub fn pending_jobs() -> usize {
+    Pool::inner().map(|pool| pool.store.read().pending_jobs()).unwrap_or(0)
 }
 
+/// Block until all jobs submitted to the shared pool so far, at any priority, have been done. It
+/// returns right away if the pool is not running. See `ThreadPool::drain` for details.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// shared_mode::initialize(2).expect("Unable to spawn the pool workers");
+/// let done = Arc::new(AtomicUsize::new(0));
+///
+/// for _ in 0..20 {
+///     let done = Arc::clone(&done);
+///     shared_mode::run(move || {
+///         thread::sleep(Duration::from_millis(5));
+///         done.fetch_add(1, Ordering::SeqCst);
+///     }).unwrap();
+/// }
+///
+/// shared_mode::drain();
+/// assert_eq!(done.load(Ordering::SeqCst), 20);
+///
+/// shared_mode::close();
+/// ```
+pub fn drain() {
+    if let Ok(pool) = Pool::inner() {
+        pool.store.read().drain();
+    }
+}
+
+/// Block until the shared pool is idle, or until the `timeout` has passed, and return `true` if the
+/// pool has become idle. The jobs submitted by the running jobs are waited for too. It returns
+/// `true` right away if the pool is not running. See `ThreadPool::wait_for_idle` for details.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+///
+/// assert!(shared_mode::wait_for_idle(Duration::from_millis(1)));
+/// shared_mode::initialize(2).expect("Unable to spawn the pool workers");
+///
+/// let (tx, rx) = mpsc::channel::<()>();
+/// shared_mode::run(move || {
+///     // the follow-up job is waited for as well
+///     shared_mode::run(move || { let _ = rx.recv(); }).unwrap();
+/// })
+/// .unwrap();
+///
+/// assert!(!shared_mode::wait_for_idle(Duration::from_millis(20)));
+///
+/// drop(tx);
+/// assert!(shared_mode::wait_for_idle(Duration::from_secs(5)));
+///
+/// shared_mode::close();
+/// ```
+pub fn wait_for_idle(timeout: Duration) -> bool {
+    Pool::inner()
+        .map(|pool| pool.store.read().wait_for_idle(timeout))
+        .unwrap_or(true)
+}
+
+/// Close the shared pool and block until the workers have finished all jobs queued so far. From the
+/// moment this is called, the pool takes no new jobs and `run` returns an error, whatever the build
+/// mode, while the jobs queued before that are still run.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::{mpsc, Arc};
+/// use std::thread;
+///
+/// shared_mode::initialize(1).expect("Unable to spawn the pool workers");
+///
+/// // hold the only worker while the rest of the jobs are queued up
+/// let (gate_tx, gate_rx) = mpsc::channel::<()>();
+/// shared_mode::run(move || { let _ = gate_rx.recv(); }).unwrap();
+///
+/// let done = Arc::new(AtomicUsize::new(0));
+/// for _ in 0..8 {
+///     let done = Arc::clone(&done);
+///     shared_mode::run(move || { done.fetch_add(1, Ordering::SeqCst); }).unwrap();
+/// }
+///
+/// let closer = thread::spawn(shared_mode::close);
+/// while shared_mode::is_initialized() {
+///     thread::yield_now();
+/// }
+///
+/// // the pool is closing: new jobs are turned away, but the queued ones are still run
+/// assert!(matches!(shared_mode::run(|| {}), Err(ExecutionError::PoolClosed)));
+///
+/// drop(gate_tx);
+/// closer.join().unwrap();
+/// assert_eq!(done.load(Ordering::SeqCst), 8);
+/// ```
 pub fn close() {
     shut_down(false);
 }
 
+/// Close the shared pool like `close`, but the jobs still waiting in the queues are dropped.
 pub fn force_close() {
     shut_down(true);
 }
 
+/// Close the pool like `close`, but only wait at most `deadline` for the workers to finish their
+/// jobs. If some workers are still running by then, they will be detached and their ids will be
+/// returned, such that a stuck job won't hang the caller forever.
+pub fn close_timeout(deadline: Duration) -> Result<(), Vec<usize>> {
+    match ONCE.state() {
+        OnceState::InProgress => {
+            panic!("The pool can't be closed while it's still being initializing...");
+        }
+        OnceState::Done => match Pool::take() {
+            Ok(pool_inner) => {
+                stop_auto_adjustment(pool_inner);
+                pool_inner.store.write().close_timeout(deadline)
+            }
+            Err(_) => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Resize the shared pool to `size` workers in the background, or create the pool with the default
+/// configurations if it doesn't exist yet. Resizing the pool to 0 closes the pool instead.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+///
+/// // resizing the missing pool brings it up
+/// shared_mode::resize(2).join().unwrap();
+/// assert_eq!(shared_mode::worker_count(), 2);
+///
+/// // shrinking to 0 closes the pool, and won't bring up a new one behind our back
+/// shared_mode::resize(0).join().unwrap();
+/// assert!(!shared_mode::is_initialized());
+///
+/// // jobs are rejected from now on
+/// assert!(shared_mode::run(|| {}).is_err());
+/// ```
 pub fn resize(size: usize) -> JoinHandle<()> {
     thread::spawn(move || {
         if size == 0 {
+            // the pool is being torn down, don't touch it or bring up a new one
             close();
+            return;
         }
 
         if let Ok(pool) = Pool::inner() {
-            pool.store.resize(size);
+            pool.store.write().resize(size);
             return;
         }
 
-        create(size, Config::default());
+        if let Err(err) = init_with_config(size, Config::default()) {
+            if is_debug_mode() {
+                log(Level::Warn, format_args!("Unable to create the pool: {}", err));
+            }
+        }
     })
 }
 
 pub fn update_auto_adjustment_mode(enabled: bool) {
     if let Ok(pool) = Pool::inner() {
-        if pool.auto_mode == enabled {
+        if pool.auto_mode.load(Ordering::Acquire) == enabled {
             return;
         }
 
-        if !enabled && pool.auto_adjust_handler.is_some() {
+        if !enabled {
             stop_auto_adjustment(pool);
         }
 
@@ -169,19 +717,30 @@ pub fn reset_auto_adjustment_period(period: Option<Duration>) {
 
         // initiate the new auto adjustment job if configured
         if let Some(actual_period) = period {
-            pool.toggle_auto_mode(true);
-            pool.auto_adjust_handler = Some(start_auto_adjustment(actual_period));
+            match start_auto_adjustment(actual_period, &pool.bg_config) {
+                Ok(handler) => {
+                    pool.toggle_auto_mode(true);
+                    *pool.auto_adjust_handler.lock() = Some(handler);
+                }
+                Err(err) => log(
+                    Level::Error,
+                    format_args!("Unable to start the auto adjustment: {}", err),
+                ),
+            }
         }
     }
 }
 
 fn trigger_auto_adjustment() {
     if let Ok(pool) = Pool::inner() {
-        pool.store.auto_adjust();
+        pool.store.write().auto_adjust();
     }
 }
 
-fn start_auto_adjustment(period: Duration) -> JoinHandle<()> {
+fn start_auto_adjustment(
+    period: Duration,
+    bg_config: &BgThreadConfig,
+) -> io::Result<AutoAdjustHandler> {
     let one_second = Duration::from_secs(1);
     let actual_period = if period < one_second {
         one_second
@@ -189,58 +748,76 @@ fn start_auto_adjustment(period: Duration) -> JoinHandle<()> {
         period
     };
 
-    thread::spawn(move || {
-        thread::sleep(actual_period);
-
-        loop {
-            trigger_auto_adjustment();
-            thread::sleep(actual_period);
-        }
-    })
+    AutoAdjustHandler::start(actual_period, bg_config, trigger_auto_adjustment)
 }
 
-fn stop_auto_adjustment(pool: &mut Pool) {
-    if let Some(handler) = pool.auto_adjust_handler.take() {
-        handler.join().unwrap_or_else(|e| {
-            eprintln!("Unable to join the thread: {:?}", e);
-        });
+fn stop_auto_adjustment(pool: &Pool) {
+    // release the lock before joining the auto adjustment thread
+    let handler = pool.auto_adjust_handler.lock().take();
 
-        if pool.auto_mode {
-            pool.toggle_auto_mode(false);
-        }
+    if let Some(handler) = handler {
+        handler.stop();
+        pool.toggle_auto_mode(false);
     }
 }
 
-fn create(size: usize, config: Config) {
-    if size == 0 {
-        return;
-    }
+fn create(size: usize, config: Config, auto_scale: bool) -> io::Result<Pool> {
+    let period = config.refresh_period();
+    let bg_config = config.background_thread_config().clone();
+
+    // Make the pool
+    let mut store = ThreadPool::try_new_with_config(size, config)?;
 
-    let (auto_mode, handler) = if let Some(period) = config.refresh_period() {
-        (true, Some(start_auto_adjustment(period)))
+    let (auto_mode, handler) = if let Some(period) = period {
+        (true, Some(start_auto_adjustment(period, &bg_config)?))
     } else {
         (false, None)
     };
 
-    // Make the pool
-    let mut store = ThreadPool::new_with_config(size, config);
-    store.toggle_auto_scale(auto_mode);
-
-    // Put it in the heap so it can outlive this call
-    unsafe {
-        POOL.set(Pool {
-            store,
-            auto_mode,
-            auto_adjust_handler: handler,
-        });
+    store.toggle_auto_scale(auto_mode || auto_scale);
 
-        /*
-                S_POOL.as_mut_ptr().write(Pool {
-                    store,
-                    auto_mode,
-                    auto_adjust_handler: handler,
-                });
-        */
+    Ok(Pool {
+        store: RwLock::new(store),
+        auto_mode: AtomicBool::new(auto_mode),
+        paused: AtomicBool::new(false),
+        auto_adjust_handler: Mutex::new(handler),
+        bg_config,
+    })
+}
+
+fn install(pool: Pool) {
+    // Put it in the static store so it can outlive this call
+    let created = POOL.set(pool);
+
+    // the store can only be set once, discard the new pool if it's been set before
+    if let Err(pool) = created {
+        stop_auto_adjustment(&pool);
+
+        if is_debug_mode() {
+            log(Level::Warn, format_args!("The pool has already been initialized..."));
+        }
+    }
+}
+
+/// Submit the job to the shared pool, and hand the job back with the error if the pool is not
+/// running, such that the caller can run it elsewhere.
+fn dispatch<F: FnOnce() + Send + 'static>(f: F) -> Result<(), (ExecutionError, Option<F>)> {
+    // the paused pool keeps the job out without touching the job queues
+    if is_paused() {
+        return Err((ExecutionError::PoolPaused, None));
+    }
+
+    match Pool::inner() {
+        Ok(pool) => pool.store.read().exec(f, false).map_err(|err| (err, None)),
+        Err(kind) => Err((gone_error(kind), Some(f))),
+    }
+}
+
+/// Tell if the pool is not running because it's been closed, or because it's never been created.
+fn gone_error(kind: ErrorKind) -> ExecutionError {
+    match kind {
+        ErrorKind::PermissionDenied => ExecutionError::PoolClosed,
+        _ => ExecutionError::Uninitialized,
     }
 }
 
@@ -251,10 +828,14 @@ fn shut_down(forced: bool) {
         }
         OnceState::Done => {
             if let Ok(pool_inner) = Pool::take() {
+                // the pool is out of reach from now on, no need to keep adjusting it
+                stop_auto_adjustment(pool_inner);
+
+                let mut store = pool_inner.store.write();
                 if !forced {
-                    pool_inner.store.close();
+                    store.close();
                 } else {
-                    pool_inner.store.force_close();
+                    store.force_close();
                 }
             }
         }
@@ -0,0 +1,255 @@
+//! Best-effort stack capture of the worker threads, for diagnosing stuck workers without attaching a
+//! debugger. This is strictly opt-in behind the `stack-capture` feature, and only supported on Linux.
+//! In the debug mode, the job watchdog and the close timeout also report the stacks of the workers
+//! they find stuck, a few at a time.
+//!
+//! Safety constraints: the capture interrupts the worker thread with a dedicated real-time signal
+//! (`SIGRTMIN + 5`), so the host application must not use that signal for anything else. The signal
+//! handler only unwinds the stack and writes the raw instruction pointers into the preallocated
+//! per-worker slot, i.e. no allocation or locking; symbolization happens on the caller's thread.
+//! Unwinding from a signal handler is still best-effort: a worker interrupted inside the unwinder or
+//! the dynamic loader may produce a truncated trace.
+
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// The max number of frames to capture from the worker's stack.
+const MAX_FRAMES: usize = 64;
+
+/// The stacks are expensive to capture and bulky to log, so the diagnostics report at most
+/// `REPORT_LIMIT` of them in every `REPORT_WINDOW`.
+const REPORT_LIMIT: usize = 4;
+const REPORT_WINDOW: Duration = Duration::from_secs(10);
+
+/// The start of the current report window, and the number of stacks reported in it.
+static REPORTS: OnceLock<Mutex<(Instant, usize)>> = OnceLock::new();
+
+/// The per-worker slot where the signal handler writes the captured frames.
+#[doc(hidden)]
+pub(crate) struct StackSlot {
+    tid: AtomicI32,
+    busy: AtomicBool,
+    done: AtomicBool,
+    len: AtomicUsize,
+    frames: [AtomicUsize; MAX_FRAMES],
+}
+
+impl StackSlot {
+    pub(crate) fn new() -> Self {
+        StackSlot {
+            tid: AtomicI32::new(0),
+            busy: AtomicBool::new(false),
+            done: AtomicBool::new(false),
+            len: AtomicUsize::new(0),
+            frames: [(); MAX_FRAMES].map(|_| AtomicUsize::new(0)),
+        }
+    }
+
+    /// Record the OS thread id of the worker, must be called from the worker thread.
+    pub(crate) fn register(&self) {
+        #[cfg(target_os = "linux")]
+        self.tid.store(imp::gettid(), Ordering::Release);
+    }
+
+    /// Mark if the worker is running a job, only busy workers can be captured.
+    pub(crate) fn set_busy(&self, busy: bool) {
+        self.busy.store(busy, Ordering::Release);
+    }
+}
+
+/// Enumeration to indicate why the stack of a worker can't be captured.
+#[derive(Debug)]
+pub enum CaptureError {
+    /// No worker in the pool has the given id.
+    UnknownWorker,
+
+    /// The worker is not running any job at the moment, so there's nothing to look at.
+    WorkerIdle,
+
+    /// The worker didn't respond to the capture signal in time.
+    Timeout,
+
+    /// The capture signal can't be installed or delivered to the worker thread.
+    SignalFailed,
+
+    /// Stack capture is not supported on this platform.
+    Unsupported,
+}
+
+/// Capture the stack of the worker owning the slot, and symbolize the frames.
+pub(crate) fn capture(slot: &StackSlot) -> Result<Vec<String>, CaptureError> {
+    if slot.tid.load(Ordering::Acquire) == 0 || !slot.busy.load(Ordering::Acquire) {
+        return Err(CaptureError::WorkerIdle);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        imp::capture(slot)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Err(CaptureError::Unsupported)
+    }
+}
+
+/// Capture the stack of the worker owning the slot for a diagnostics report, e.g. of an overdue
+/// job, and format the frames one per line. Return `None` if the capture fails, or too many stacks
+/// have been reported lately.
+pub(crate) fn report(slot: &StackSlot) -> Option<String> {
+    {
+        let mut reports = REPORTS
+            .get_or_init(|| Mutex::new((Instant::now(), 0)))
+            .lock();
+
+        if reports.0.elapsed() >= REPORT_WINDOW {
+            *reports = (Instant::now(), 0);
+        }
+
+        if reports.1 >= REPORT_LIMIT {
+            return None;
+        }
+
+        reports.1 += 1;
+    }
+
+    let frames = capture(slot).ok()?;
+    Some(
+        frames
+            .iter()
+            .map(|frame| format!("    at {}", frame))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::c_void;
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+    use std::sync::OnceLock;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{CaptureError, StackSlot, MAX_FRAMES};
+    use parking_lot::{Mutex, Once, ONCE_INIT};
+
+    const SIGNAL_OFFSET: libc::c_int = 5;
+    const CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+    static INSTALL: Once = ONCE_INIT;
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+    /// Only one capture can be in flight, and the handler will only write to the targeted slot.
+    static CAPTURE_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    static TARGET: AtomicPtr<StackSlot> = AtomicPtr::new(ptr::null_mut());
+
+    pub(super) fn gettid() -> i32 {
+        unsafe { libc::syscall(libc::SYS_gettid) as i32 }
+    }
+
+    fn signal() -> libc::c_int {
+        libc::SIGRTMIN() + SIGNAL_OFFSET
+    }
+
+    extern "C" fn handler(_: libc::c_int) {
+        let target = TARGET.load(Ordering::Acquire);
+        if target.is_null() {
+            return;
+        }
+
+        // the slot is kept alive by the worker thread we're running on
+        let slot = unsafe { &*target };
+        if slot.tid.load(Ordering::Acquire) != gettid() {
+            return;
+        }
+
+        let mut len = 0;
+        unsafe {
+            backtrace::trace_unsynchronized(|frame| {
+                slot.frames[len].store(frame.ip() as usize, Ordering::Relaxed);
+                len += 1;
+                len < MAX_FRAMES
+            });
+        }
+
+        slot.len.store(len, Ordering::Relaxed);
+        slot.done.store(true, Ordering::Release);
+    }
+
+    fn install() -> bool {
+        INSTALL.call_once(|| unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(libc::c_int) as usize;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(signal(), &action, ptr::null_mut()) == 0 {
+                INSTALLED.store(true, Ordering::Release);
+            }
+        });
+
+        INSTALLED.load(Ordering::Acquire)
+    }
+
+    pub(super) fn capture(slot: &StackSlot) -> Result<Vec<String>, CaptureError> {
+        if !install() {
+            return Err(CaptureError::SignalFailed);
+        }
+
+        let _guard = CAPTURE_LOCK.get_or_init(|| Mutex::new(())).lock();
+
+        slot.done.store(false, Ordering::Release);
+        TARGET.store(slot as *const StackSlot as *mut StackSlot, Ordering::Release);
+
+        let sent = unsafe {
+            libc::syscall(
+                libc::SYS_tgkill,
+                libc::getpid(),
+                slot.tid.load(Ordering::Acquire),
+                signal(),
+            )
+        };
+
+        let deadline = Instant::now() + CAPTURE_TIMEOUT;
+        while sent == 0 && !slot.done.load(Ordering::Acquire) && Instant::now() < deadline {
+            thread::sleep(Duration::from_micros(100));
+        }
+
+        TARGET.store(ptr::null_mut(), Ordering::Release);
+
+        if sent != 0 {
+            return Err(CaptureError::SignalFailed);
+        }
+
+        if !slot.done.load(Ordering::Acquire) {
+            return Err(CaptureError::Timeout);
+        }
+
+        // symbolize the raw frames off the worker thread
+        let len = slot.len.load(Ordering::Relaxed);
+        let mut frames = Vec::with_capacity(len);
+
+        for ip in slot.frames[..len].iter() {
+            let ip = ip.load(Ordering::Relaxed);
+            let mut resolved = false;
+
+            backtrace::resolve(ip as *mut c_void, |symbol| {
+                if let Some(name) = symbol.name() {
+                    frames.push(name.to_string());
+                    resolved = true;
+                }
+            });
+
+            if !resolved {
+                frames.push(format!("{:#x}", ip));
+            }
+        }
+
+        Ok(frames)
+    }
+}
@@ -0,0 +1,140 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::model::hook_status;
+
+type StateInit = Arc<dyn Fn(usize) -> Box<dyn Any> + Send + Sync>;
+type StateTeardown = Arc<dyn Fn(usize, Box<dyn Any>) + Send + Sync>;
+
+thread_local! {
+    /// The state of the worker running on this thread, if the pool has a worker init hook.
+    static WORKER_STATE: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+}
+
+/// Run the closure with the state of the current worker, as created by the worker init hook of the
+/// pool. `None` will be returned if the current thread is not a pool worker, or if the worker has no
+/// state of the type `T`.
+///
+/// The closure shall not call `with_worker_state` again, otherwise it will panic.
+///
+/// # Examples
+///
+/// ```
+/// extern crate threads_pool;
+///
+/// use threads_pool::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static INITS: AtomicUsize = AtomicUsize::new(0);
+/// static TEARDOWNS: AtomicUsize = AtomicUsize::new(0);
+///
+/// // the expensive per-thread resource
+/// struct Connection {
+///     worker_id: usize,
+/// }
+///
+/// let mut config = Config::new();
+/// config
+///     .set_worker_init(|id| {
+///         INITS.fetch_add(1, Ordering::SeqCst);
+///         Connection { worker_id: id }
+///     })
+///     .set_worker_teardown(|_id, _conn: Connection| {
+///         TEARDOWNS.fetch_add(1, Ordering::SeqCst);
+///     });
+///
+/// let mut pool = ThreadPool::new_with_config(2, config);
+///
+/// // the jobs can use the state of the worker running them
+/// let id = pool.sync_block(|| with_worker_state(|conn: &Connection| conn.worker_id)).unwrap();
+/// assert!(id.is_some());
+/// assert!(with_worker_state(|conn: &Connection| conn.worker_id).is_none());
+///
+/// // each worker has its state created and torn down exactly once
+/// pool.resize(4);
+/// pool.resize(1);
+/// assert_eq!(INITS.load(Ordering::SeqCst), 4);
+/// assert_eq!(TEARDOWNS.load(Ordering::SeqCst), 3);
+///
+/// pool.close();
+/// assert_eq!(TEARDOWNS.load(Ordering::SeqCst), 4);
+/// ```
+pub fn with_worker_state<T, R, F>(f: F) -> Option<R>
+where
+    T: 'static,
+    F: FnOnce(&T) -> R,
+{
+    WORKER_STATE.with(|state| {
+        state
+            .borrow()
+            .as_ref()
+            .and_then(|state| state.downcast_ref::<T>())
+            .map(f)
+    })
+}
+
+/// The hooks to create the state of each worker when it starts, and to tear it down when it quits.
+#[derive(Clone, Default)]
+pub(crate) struct WorkerStateHooks {
+    init: Option<StateInit>,
+    teardown: Option<StateTeardown>,
+}
+
+impl fmt::Debug for WorkerStateHooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WorkerStateHooks")
+            .field("init", &hook_status(&self.init))
+            .field("teardown", &hook_status(&self.teardown))
+            .finish()
+    }
+}
+
+impl WorkerStateHooks {
+    pub(crate) fn set_init<T, F>(&mut self, init: F)
+    where
+        T: 'static,
+        F: Fn(usize) -> T + Send + Sync + 'static,
+    {
+        self.init = Some(Arc::new(move |id| Box::new(init(id)) as Box<dyn Any>));
+    }
+
+    pub(crate) fn set_teardown<T, F>(&mut self, teardown: F)
+    where
+        T: 'static,
+        F: Fn(usize, T) + Send + Sync + 'static,
+    {
+        self.teardown = Some(Arc::new(move |id, state: Box<dyn Any>| {
+            if let Ok(state) = state.downcast::<T>() {
+                teardown(id, *state);
+            }
+        }));
+    }
+
+    /// Create the state of the worker, must be called from the worker thread. The state will be torn
+    /// down when the returned guard is dropped, i.e. whenever the worker quits.
+    pub(crate) fn install(self, id: usize) -> WorkerStateGuard {
+        if let Some(init) = self.init.as_ref() {
+            let state = init(id);
+            WORKER_STATE.with(|slot| slot.borrow_mut().replace(state));
+        }
+
+        WorkerStateGuard { id, hooks: self }
+    }
+}
+
+pub(crate) struct WorkerStateGuard {
+    id: usize,
+    hooks: WorkerStateHooks,
+}
+
+impl Drop for WorkerStateGuard {
+    fn drop(&mut self) {
+        let state = WORKER_STATE.with(|slot| slot.borrow_mut().take());
+
+        if let (Some(state), Some(teardown)) = (state, self.hooks.teardown.as_ref()) {
+            teardown(self.id, state);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::config::BgThreadConfig;
+use crate::debug::{log, Level};
+use crate::manager::JobBehaviors;
+use crate::metrics::PoolMetrics;
+use crate::model::{Job, Message};
+use crossbeam_channel::{SendTimeoutError, Sender};
+use parking_lot::{Condvar, Mutex};
+
+/// How long the timer waits for the space in a full job queue before checking if it's stopped.
+const SEND_RETRY: Duration = Duration::from_millis(10);
+
+/// The handle of a recurring task, which can be used to cancel the task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TaskId(u64);
+
+/// A job that runs every `period`. Only one run of the task can be queued or running at a time,
+/// the ticks coming in the meantime are skipped.
+struct RecurringTask {
+    job: Mutex<Box<dyn Fn() + Send>>,
+    period: Duration,
+    pending: AtomicBool,
+}
+
+/// Clear the pending mark of the recurring task once its run is done, even if it has panicked.
+struct PendingGuard(Arc<RecurringTask>);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.pending.store(false, atomic::Ordering::Release);
+    }
+}
+
+enum TimedJob {
+    Once(Job),
+    Recurring(TaskId),
+}
+
+/// A check run every `period` on the timer thread itself rather than by the workers, such that it
+/// keeps running even if all the workers are stuck.
+struct TimerCheck {
+    due: Instant,
+    period: Duration,
+    run: Arc<dyn Fn() + Send + Sync>,
+}
+
+/// A job waiting for its time to come. The sequence number keeps the jobs due at the same time in
+/// the order they're scheduled.
+struct DelayedJob {
+    due: Instant,
+    seq: u64,
+    job: TimedJob,
+}
+
+impl PartialEq for DelayedJob {
+    fn eq(&self, other: &Self) -> bool {
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl Eq for DelayedJob {}
+
+impl PartialOrd for DelayedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DelayedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+#[derive(Default)]
+struct TimerQueue {
+    jobs: BinaryHeap<Reverse<DelayedJob>>,
+    tasks: HashMap<TaskId, Arc<RecurringTask>>,
+    checks: Vec<TimerCheck>,
+    next_seq: u64,
+    next_task: u64,
+    stopped: bool,
+}
+
+impl TimerQueue {
+    fn push(&mut self, job: TimedJob, due: Instant) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.jobs.push(Reverse(DelayedJob { due, seq, job }));
+    }
+
+    /// Take the job that is due, and schedule the next tick if it's a recurring task. `None` is
+    /// returned if the recurring task has been cancelled, or its last run is not done yet.
+    fn take_due(&mut self, next: DelayedJob, now: Instant) -> Option<Job> {
+        let id = match next.job {
+            TimedJob::Once(job) => return Some(job),
+            TimedJob::Recurring(id) => id,
+        };
+
+        let task = Arc::clone(self.tasks.get(&id)?);
+
+        // keep the ticks on the beat, but never try to catch up on the missed ones
+        let mut due = next.due + task.period;
+        if due <= now {
+            due = now + task.period;
+        }
+
+        self.push(TimedJob::Recurring(id), due);
+
+        if task.pending.swap(true, atomic::Ordering::AcqRel) {
+            return None;
+        }
+
+        Some(Box::new(move || {
+            let guard = PendingGuard(task);
+            (*guard.0.job.lock())();
+        }))
+    }
+
+    /// Take the first job that is due by `now`, skipping the ticks of the cancelled or still
+    /// pending recurring tasks.
+    fn pop_due(&mut self, now: Instant) -> Option<Job> {
+        while self.jobs.peek().is_some_and(|Reverse(next)| next.due <= now) {
+            let Reverse(next) = self.jobs.pop()?;
+            if let Some(job) = self.take_due(next, now) {
+                return Some(job);
+            }
+        }
+
+        None
+    }
+
+    /// Take the checks that are due by `now`, and schedule their next runs.
+    fn take_checks(&mut self, now: Instant) -> Vec<Arc<dyn Fn() + Send + Sync>> {
+        self.checks
+            .iter_mut()
+            .filter(|check| check.due <= now)
+            .map(|check| {
+                check.due = now + check.period;
+                Arc::clone(&check.run)
+            })
+            .collect()
+    }
+
+    /// The time the timer shall wake up at, where the jobs don't count while the timer is still
+    /// holding a due job that the job queue has no space for.
+    fn next_wake(&self, holding: bool) -> Option<Instant> {
+        let job = self
+            .jobs
+            .peek()
+            .filter(|_| !holding)
+            .map(|Reverse(next)| next.due);
+
+        self.checks
+            .iter()
+            .map(|check| check.due)
+            .chain(job)
+            .min()
+    }
+}
+
+/// The background thread to hold the delayed jobs, and push them onto the normal job queue once
+/// they're due. The jobs still waiting when the timer is stopped are dropped. The thread also runs
+/// the pool's periodic checks, e.g. the job watchdog, so the pool needs no extra thread for them.
+pub(crate) struct JobTimer {
+    queue: Arc<(Mutex<TimerQueue>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl JobTimer {
+    pub(crate) fn start(
+        chan: Sender<Message>,
+        behaviors: Option<JobBehaviors>,
+        metrics: Arc<PoolMetrics>,
+        bg_config: &BgThreadConfig,
+    ) -> io::Result<JobTimer> {
+        let queue = Arc::new((Mutex::new(TimerQueue::default()), Condvar::new()));
+        let thread_queue = Arc::clone(&queue);
+
+        let handle = bg_config.spawn("pool-timer", move || {
+            let (lock, cvar) = &*thread_queue;
+
+            // the due job waiting for the space in the job queue, and the number of jobs it holds
+            let mut holding: Option<(Message, usize)> = None;
+
+            loop {
+                let now = Instant::now();
+                let (checks, wake) = {
+                    let mut queue = lock.lock();
+                    if queue.stopped {
+                        return;
+                    }
+
+                    let checks = queue.take_checks(now);
+                    if holding.is_none() {
+                        holding = queue.pop_due(now).map(|job| {
+                            let message = Message::SingleJob(job);
+                            match behaviors.as_ref() {
+                                Some(behaviors) => behaviors.instrument(message),
+                                None => (message, 1),
+                            }
+                        });
+                    }
+
+                    let wake = queue.next_wake(holding.is_some());
+                    if checks.is_empty() && holding.is_none() {
+                        match wake {
+                            Some(at) => {
+                                cvar.wait_until(&mut queue, at);
+                            }
+                            None => cvar.wait(&mut queue),
+                        }
+
+                        continue;
+                    }
+
+                    (checks, wake)
+                };
+
+                // run the checks without the lock, such that they can't hold up the scheduling
+                for check in checks {
+                    check();
+                }
+
+                let (message, count) = match holding.take() {
+                    Some(held) => held,
+                    None => continue,
+                };
+
+                // don't block on a full job queue for long, the timer may be stopped or have the
+                // checks to run in the meantime
+                let timeout = wake
+                    .map(|at| at.saturating_duration_since(Instant::now()))
+                    .map_or(SEND_RETRY, |wait| wait.min(SEND_RETRY));
+
+                match chan.send_timeout(message, timeout) {
+                    Ok(()) => {
+                        metrics.record_queue_depth(chan.len());
+                        if let Some(behaviors) = behaviors.as_ref() {
+                            behaviors.enqueued(count, chan.len());
+                        }
+                    }
+                    Err(SendTimeoutError::Timeout(message)) => holding = Some((message, count)),
+                    // the pool is gone if the queue is disconnected, and so are the rest of the
+                    // jobs
+                    Err(SendTimeoutError::Disconnected(_)) => return,
+                }
+            }
+        })?;
+
+        Ok(JobTimer {
+            queue,
+            handle: Some(handle),
+        })
+    }
+
+    /// Hold the job until the `due` time, then push it onto the job queue.
+    pub(crate) fn schedule(&self, job: Job, due: Instant) {
+        let (lock, cvar) = &*self.queue;
+        let mut queue = lock.lock();
+        queue.push(TimedJob::Once(job), due);
+
+        // the new job may be due before the one the timer is waiting for
+        cvar.notify_all();
+    }
+
+    /// Run the job every `period`, starting one `period` from now.
+    pub(crate) fn schedule_recurring(
+        &self,
+        job: Box<dyn Fn() + Send>,
+        period: Duration,
+    ) -> TaskId {
+        let (lock, cvar) = &*self.queue;
+        let mut queue = lock.lock();
+
+        let id = TaskId(queue.next_task);
+        queue.next_task += 1;
+
+        let task = RecurringTask {
+            job: Mutex::new(job),
+            period,
+            pending: AtomicBool::new(false),
+        };
+
+        queue.tasks.insert(id, Arc::new(task));
+        queue.push(TimedJob::Recurring(id), Instant::now() + period);
+        cvar.notify_all();
+
+        id
+    }
+
+    /// Stop the recurring task, and return `false` if there's no such task. A run of the task that
+    /// has been queued already is not affected.
+    pub(crate) fn cancel_recurring(&self, id: TaskId) -> bool {
+        let (lock, _) = &*self.queue;
+        let mut queue = lock.lock();
+
+        // the next tick will find the task gone and drop itself
+        queue.tasks.remove(&id).is_some()
+    }
+
+    /// Run the check on the timer thread every `period`, starting one `period` from now, until the
+    /// timer is stopped.
+    pub(crate) fn schedule_check(&self, check: Arc<dyn Fn() + Send + Sync>, period: Duration) {
+        let (lock, cvar) = &*self.queue;
+        lock.lock().checks.push(TimerCheck {
+            due: Instant::now() + period,
+            period,
+            run: check,
+        });
+
+        cvar.notify_all();
+    }
+
+    /// Drop the delayed jobs and the recurring tasks, while the checks keep running until the timer
+    /// is stopped.
+    pub(crate) fn clear(&self) {
+        let mut queue = self.queue.0.lock();
+        queue.jobs.clear();
+        queue.tasks.clear();
+    }
+
+    pub(crate) fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.clear();
+
+            let (lock, cvar) = &*self.queue;
+            lock.lock().stopped = true;
+            cvar.notify_all();
+
+            handle.join().unwrap_or_else(|err| {
+                log(Level::Error, format_args!("Unable to join the timer thread: {:?}", err));
+            });
+        }
+    }
+}
+
+impl Drop for JobTimer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,233 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "stack-capture")]
+use crate::debug::{is_debug_mode, log, Level};
+use crate::metrics::PoolMetrics;
+use crate::model::JobTimeout;
+#[cfg(feature = "stack-capture")]
+use crate::stack::{self, StackSlot};
+use parking_lot::RwLock;
+
+/// The bit marking the running job as already reported, such that each overdue job is only
+/// reported once.
+const REPORTED: u64 = 1 << 63;
+
+/// The bit marking the worker as idle, in which case the clock tells since when.
+const IDLE: u64 = 1 << 62;
+
+/// The bounds of how often the watchdog checks the running jobs.
+const MIN_SCAN_PERIOD: Duration = Duration::from_millis(1);
+const MAX_SCAN_PERIOD: Duration = Duration::from_secs(1);
+
+/// The clocks of the workers, i.e. when each worker has started its running job, or since when it
+/// has been idle.
+#[derive(Clone)]
+pub(crate) struct JobClocks {
+    epoch: Instant,
+    inner: Arc<RwLock<Vec<IdClock>>>,
+    #[cfg(feature = "stack-capture")]
+    stacks: Arc<RwLock<Vec<IdStack>>>,
+}
+
+/// The worker id and the start time of its running job, or of its idle period.
+type IdClock = (usize, Arc<AtomicU64>);
+
+/// The worker id and the slot to capture its stack into.
+#[cfg(feature = "stack-capture")]
+type IdStack = (usize, Arc<StackSlot>);
+
+/// The clock of a worker, which stays in the registry until it's dropped.
+pub(crate) struct JobClock {
+    registry: JobClocks,
+    id: usize,
+    start: Arc<AtomicU64>,
+}
+
+impl JobClocks {
+    pub(crate) fn new() -> Self {
+        JobClocks {
+            epoch: Instant::now(),
+            inner: Arc::new(RwLock::new(Vec::new())),
+            #[cfg(feature = "stack-capture")]
+            stacks: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    pub(crate) fn register(&self, id: usize) -> JobClock {
+        let start = Arc::new(AtomicU64::new(IDLE | (self.millis() + 1)));
+        self.inner.write().push((id, Arc::clone(&start)));
+
+        JobClock {
+            registry: self.clone(),
+            id,
+            start,
+        }
+    }
+
+    /// The stack slot of the worker, such that the watchdog can capture where an overdue job is at.
+    #[cfg(feature = "stack-capture")]
+    fn stack_slot(&self, id: usize) -> Option<Arc<StackSlot>> {
+        self.stacks
+            .read()
+            .iter()
+            .find(|(slot_id, _)| *slot_id == id)
+            .map(|(_, slot)| Arc::clone(slot))
+    }
+
+    /// Find the workers that have been running their jobs for longer than the `timeout`, and which
+    /// haven't been reported for the same job yet.
+    fn overdue(&self, timeout: Duration) -> Vec<(usize, Duration)> {
+        let now = self.millis();
+        let timeout = timeout.as_millis() as u64;
+
+        self.inner
+            .read()
+            .iter()
+            .filter_map(|(id, start)| {
+                let since = start.load(Ordering::Acquire);
+                if since & (IDLE | REPORTED) > 0 {
+                    return None;
+                }
+
+                // the clock starts at 1, such that 0 never passes as a valid time
+                let elapsed = now.saturating_sub(since - 1);
+                if elapsed < timeout {
+                    return None;
+                }
+
+                // the worker may have moved on to the next job in the meantime
+                start
+                    .compare_exchange(since, since | REPORTED, Ordering::AcqRel, Ordering::Relaxed)
+                    .ok()
+                    .map(|_| (*id, Duration::from_millis(elapsed)))
+            })
+            .collect()
+    }
+
+    /// The average time the workers have been idle for, where the busy workers count as not idle.
+    pub(crate) fn average_idle(&self) -> Duration {
+        let now = self.millis();
+        let clocks = self.inner.read();
+        if clocks.is_empty() {
+            return Duration::from_millis(0);
+        }
+
+        let total: u64 = clocks
+            .iter()
+            .map(|(_, start)| {
+                let since = start.load(Ordering::Acquire);
+                if since & IDLE == 0 {
+                    return 0;
+                }
+
+                now.saturating_sub((since & !IDLE) - 1)
+            })
+            .sum();
+
+        Duration::from_millis(total / clocks.len() as u64)
+    }
+
+    fn millis(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+}
+
+impl JobClock {
+    /// Mark the worker as running a job from now on.
+    pub(crate) fn start(&self) {
+        self.start.store(self.registry.millis() + 1, Ordering::Release);
+    }
+
+    /// Mark the worker as done with its job, and idle from now on.
+    pub(crate) fn stop(&self) {
+        self.start
+            .store(IDLE | (self.registry.millis() + 1), Ordering::Release);
+    }
+
+    /// Let the watchdog capture the worker's stack through the slot when its job is overdue.
+    #[cfg(feature = "stack-capture")]
+    pub(crate) fn attach_stack(&self, slot: Arc<StackSlot>) {
+        self.registry.stacks.write().push((self.id, slot));
+    }
+}
+
+impl Drop for JobClock {
+    fn drop(&mut self) {
+        self.registry.inner.write().retain(|(id, _)| *id != self.id);
+
+        #[cfg(feature = "stack-capture")]
+        self.registry
+            .stacks
+            .write()
+            .retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// The check to report the jobs running for longer than the job timeout, which the pool's timer
+/// thread runs periodically. Since the jobs can't be stopped halfway, the overdue workers are
+/// reported to the `on_job_timeout` callback and counted as stuck, such that the pool can replace
+/// them if it's allowed to auto scale.
+pub(crate) struct JobWatchdog {
+    clocks: JobClocks,
+    timeout: Duration,
+    on_timeout: Option<JobTimeout>,
+    metrics: Arc<PoolMetrics>,
+    stuck: Arc<AtomicUsize>,
+}
+
+impl JobWatchdog {
+    pub(crate) fn new(
+        clocks: JobClocks,
+        timeout: Duration,
+        on_timeout: Option<JobTimeout>,
+        (metrics, stuck): (Arc<PoolMetrics>, Arc<AtomicUsize>),
+    ) -> JobWatchdog {
+        JobWatchdog {
+            clocks,
+            timeout,
+            on_timeout,
+            metrics,
+            stuck,
+        }
+    }
+
+    /// How often the running jobs shall be checked.
+    pub(crate) fn period(&self) -> Duration {
+        (self.timeout / 4).max(MIN_SCAN_PERIOD).min(MAX_SCAN_PERIOD)
+    }
+
+    /// Report the workers that have become overdue since the last check.
+    pub(crate) fn check(&self) {
+        for (id, elapsed) in self.clocks.overdue(self.timeout) {
+            self.metrics.job_timed_out();
+            self.stuck.fetch_add(1, Ordering::AcqRel);
+
+            #[cfg(feature = "stack-capture")]
+            report_stack(&self.clocks, id, elapsed);
+
+            if let Some(on_timeout) = self.on_timeout.as_ref() {
+                on_timeout(id, elapsed);
+            }
+        }
+    }
+}
+
+/// Log where the worker's overdue job is at in the debug mode, which is rate limited.
+#[cfg(feature = "stack-capture")]
+fn report_stack(clocks: &JobClocks, id: usize, elapsed: Duration) {
+    if !is_debug_mode() {
+        return;
+    }
+
+    if let Some(frames) = clocks.stack_slot(id).and_then(|slot| stack::report(&slot)) {
+        log(
+            Level::Warn,
+            format_args!(
+                "Worker {} has been running the job for {:?} at:\n{}",
+                id, elapsed, frames
+            ),
+        );
+    }
+}
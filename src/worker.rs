@@ -1,24 +1,50 @@
 #![allow(dead_code)]
 
 //use std::future::Future;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc, Weak,
 };
 use std::thread;
-use std::time::{Duration, SystemTime};
-
-use crate::debug::is_debug_mode;
-use crate::manager::{IdleThreshold, StatusBehaviorDefinitions, StatusBehaviors};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::affinity;
+use crate::config::{JobOrdering, ParkingProfile};
+use crate::debug::{is_debug_mode, log, Level};
+use crate::manager::{
+    FetchRoles, IdleTaskSlot, IdleThreshold, SiblingQueues, StatusBehaviorDefinitions,
+    StatusBehaviors, StealRegistry, WorkerEvent,
+};
+use crate::metrics::PoolMetrics;
+#[cfg(feature = "stack-capture")]
+use crate::stack::StackSlot;
 use crate::model::*;
 use crate::pool::PoolStatus;
+use crate::state::WorkerStateHooks;
+use crate::watchdog::{JobClock, JobClocks};
 use crossbeam_channel as channel;
+use crossbeam_deque::Worker as LocalQueue;
+use parking_lot::RwLock;
+
+/// How often to check if a retiring worker has quit, when we can't wait on it forever.
+const RETIRE_POLL_PERIOD: Duration = Duration::from_millis(1);
+
+/// The max number of extra jobs a worker will move from the shared normal queue to its local queue
+/// at a time, which can then be stolen by the idle siblings.
+const LOCAL_BATCH: usize = 2;
 
-const TIMEOUT: Duration = Duration::from_micros(16);
-const LONG_TIMEOUT: Duration = Duration::from_micros(96);
-const LOT_COUNTS: usize = 3;
-const LONG_PARKING_ROUNDS: u8 = 8;
-const SHORT_PARKING_ROUNDS: u8 = 2;
+/// The max number of higher priority jobs a worker will run in a row while low priority jobs are
+/// waiting, before it picks up a low priority job.
+const LOW_PRIORITY_CAP: u8 = 8;
+
+/// The number of rounds in a row a worker can come back empty handed from the job queues while the
+/// pool has a backlog, before it's considered starved.
+const STARVATION_LIMIT: usize = 4;
 
 /*
 struct FutWorker {
@@ -64,43 +90,166 @@ impl FutWorker {
 }
 */
 
-thread_local!();
+thread_local! {
+    /// The id of the worker running on this thread, or 0 if the thread is not a pool worker.
+    static WORKER_ID: Cell<usize> = const { Cell::new(0) };
+
+    /// The name of the pool the worker on this thread belongs to, if the pool has a name.
+    static WORKER_POOL: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// The id of the worker running on the current thread, or 0 if the thread is not a pool worker.
+pub(crate) fn current_worker_id() -> usize {
+    WORKER_ID.with(Cell::get)
+}
+
+/// Tell which worker is running on the current thread for the log messages, like `io/worker-16`,
+/// or `None` if the thread is not a pool worker.
+pub(crate) fn current_worker_context() -> Option<String> {
+    let id = current_worker_id();
+    if id == 0 {
+        return None;
+    }
+
+    WORKER_POOL.with(|pool| match pool.borrow().as_ref() {
+        Some(pool) => Some(format!("{}/worker-{}", pool, id)),
+        None => Some(format!("worker-{}", id)),
+    })
+}
 
 pub(crate) struct Worker {
     id: usize,
     thread: Option<thread::JoinHandle<()>>,
     stat: Weak<AtomicUsize>,
+    inbox: InboxSender,
     before_drop: Option<WorkerUpdate>,
     after_drop: Option<WorkerUpdate>,
+    #[cfg(feature = "stack-capture")]
+    stack_slot: Arc<StackSlot>,
 }
 
 struct WorkStatus(i8, Option<Job>);
 
+/// The sending end of the jobs pinned to a worker. The worker shuts the inbox before it runs its
+/// last jobs and quits, such that a job can't be pinned to it afterwards and get lost.
+struct InboxSender {
+    jobs: channel::Sender<Job>,
+    open: Arc<RwLock<bool>>,
+}
+
+impl InboxSender {
+    fn send(&self, job: Job) -> Result<(), Job> {
+        // hold the gate until the job is in, so the worker can't shut the inbox in the meantime
+        let open = self.open.read();
+        if !*open {
+            return Err(job);
+        }
+
+        self.jobs.send(job).map_err(|err| err.into_inner())
+    }
+}
+
+/// Shut the inbox of the worker, see `InboxSender`.
+fn shut_inbox(open: &RwLock<bool>) {
+    *open.write() = false;
+}
+
+/// The channel to report the worker's lifecycle events to, if any.
+type EventSink<'a> = Option<&'a channel::Sender<WorkerEvent>>;
+
+/// The settings a worker is spawned with.
+pub(crate) struct WorkerConfig {
+    pub(crate) name: Option<String>,
+    pub(crate) pool_name: Option<String>,
+    pub(crate) stack_size: usize,
+    pub(crate) privileged: bool,
+    pub(crate) parking: ParkingProfile,
+    pub(crate) ordering: JobOrdering,
+    pub(crate) on_panic: Option<WorkerPanic>,
+    pub(crate) events: Option<channel::Sender<WorkerEvent>>,
+    pub(crate) on_starved: Option<WorkerStarved>,
+    pub(crate) start_latch: Option<StartLatch>,
+    pub(crate) state_hooks: WorkerStateHooks,
+    pub(crate) core: Option<usize>,
+    #[cfg(feature = "stack-capture")]
+    pub(crate) stack_slot: Arc<StackSlot>,
+}
+
+impl fmt::Debug for WorkerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WorkerConfig")
+            .field("name", &self.name)
+            .field("pool_name", &self.pool_name)
+            .field("stack_size", &self.stack_size)
+            .field("privileged", &self.privileged)
+            .field("parking", &self.parking)
+            .field("ordering", &self.ordering)
+            .field("on_panic", &hook_status(&self.on_panic))
+            .field("events", &hook_status(&self.events))
+            .field("on_starved", &hook_status(&self.on_starved))
+            .field("start_latch", &hook_status(&self.start_latch))
+            .field("state_hooks", &self.state_hooks)
+            .field("core", &self.core)
+            .finish()
+    }
+}
+
+/// The states shared by all workers of the pool.
+pub(crate) struct WorkerShared {
+    pub(crate) status: PoolStatus,
+    pub(crate) idle_threshold: IdleThreshold,
+    pub(crate) idle_task: IdleTaskSlot,
+    pub(crate) roles: FetchRoles,
+    pub(crate) stealers: StealRegistry,
+    pub(crate) siblings: SiblingQueues,
+    pub(crate) clocks: JobClocks,
+    pub(crate) metrics: Arc<PoolMetrics>,
+}
+
 impl Worker {
-    /// Create and spawn the worker, this will dispatch the worker to listen to work queue immediately
+    /// Create and spawn the worker, this will dispatch the worker to listen to work queue immediately.
+    /// An error will be returned if the OS refuses to spawn the worker thread.
     pub(crate) fn new(
-        name: Option<String>,
         my_id: usize,
-        stack_size: usize,
-        privileged: bool,
-        rx_pair: (channel::Receiver<Message>, channel::Receiver<Message>),
-        shared_info: (PoolStatus, IdleThreshold), // (idle_threshold, pool_status)
+        config: WorkerConfig,
+        rx_queues: JobQueues,
+        shared_info: WorkerShared,
         behavior_definition: &StatusBehaviors,
-    ) -> Worker {
+    ) -> io::Result<Worker> {
         behavior_definition.before_start(my_id);
 
-        let (worker, stat) =
-            Self::spawn_worker(name, my_id, stack_size, privileged, rx_pair, shared_info);
+        #[cfg(feature = "stack-capture")]
+        let stack_slot = Arc::clone(&config.stack_slot);
+
+        // keep the effective settings around to tell what has gone wrong
+        let settings = if is_debug_mode() {
+            Some(format!("{:?}", config))
+        } else {
+            None
+        };
+
+        let (worker, stat, inbox) = Self::spawn_worker(my_id, config, rx_queues, shared_info)
+            .inspect_err(|err| {
+                if let Some(settings) = settings {
+                    log(
+                        Level::Warn,
+                        format_args!("Unable to spawn worker {} with {}: {}", my_id, settings, err),
+                    );
+                }
+            })?;
 
         behavior_definition.after_start(my_id);
 
-        Worker {
+        Ok(Worker {
             id: my_id,
             thread: Some(worker),
             stat,
+            inbox,
             before_drop: behavior_definition.before_drop_clone(),
             after_drop: behavior_definition.after_drop_clone(),
-        }
+            #[cfg(feature = "stack-capture")]
+            stack_slot,
+        })
     }
 
     /// Get the worker id
@@ -108,22 +257,77 @@ impl Worker {
         self.id
     }
 
+    #[cfg(feature = "stack-capture")]
+    pub(crate) fn stack_slot(&self) -> &Arc<StackSlot> {
+        &self.stack_slot
+    }
+
     /// Calling `retire` on a worker will block the thread until the worker has done its work, or wake
     /// up from hibernation. This could block the caller for an undetermined amount of time.
     pub(crate) fn retire(&mut self) {
+        self.signal_quit();
+
         if let Some(handle) = self.thread.take() {
+            // make sure the work is done
+            handle.join().unwrap_or_else(|err| {
+                log(
+                    Level::Error,
+                    format_args!("Unable to drop worker: {}, error: {:?}", self.id, err),
+                );
+            });
+        }
+    }
+
+    /// Same as `retire`, but only wait for the worker to finish its work until the `deadline`. If the
+    /// worker is still running by then, its thread will be detached and `false` will be returned.
+    pub(crate) fn retire_timeout(&mut self, deadline: Instant) -> bool {
+        self.signal_quit();
+
+        if let Some(handle) = self.thread.take() {
+            while !handle.is_finished() {
+                if Instant::now() >= deadline {
+                    // dropping the handle will detach the thread, which can then finish on its own
+                    return false;
+                }
+
+                thread::sleep(RETIRE_POLL_PERIOD);
+            }
+
+            handle.join().unwrap_or_else(|err| {
+                log(
+                    Level::Error,
+                    format_args!("Unable to drop worker: {}, error: {:?}", self.id, err),
+                );
+            });
+        }
+
+        true
+    }
+
+    /// Tell the worker to quit its loop and wake it up if it's parked, without waiting for it.
+    /// Signal all workers first before joining any of them, such that they wind down in parallel.
+    pub(crate) fn signal_quit(&self) {
+        if let Some(handle) = self.thread.as_ref() {
             if let Some(stat) = self.stat.upgrade() {
                 stat.store(1, Ordering::SeqCst);
             }
 
-            // make sure we can wake up and quit
+            // the unpark token is kept if the worker is not parked yet, so it can't miss the signal
             handle.thread().unpark();
+        }
+    }
 
-            // make sure the work is done
-            handle.join().unwrap_or_else(|err| {
-                eprintln!("Unable to drop worker: {}, error: {:?}", self.id, err);
-            });
+    /// Hand the job to this worker alone, and wake the worker up in case it's parked. The job is
+    /// returned if the worker has quit or is quitting.
+    pub(crate) fn pin_job(&self, job: Job) -> Result<(), Job> {
+        if !self.is_alive() {
+            return Err(job);
         }
+
+        self.inbox.send(job)?;
+        self.wake_up();
+
+        Ok(())
     }
 
     /// If the worker has been put to sleep (i.e. in `park` mode), wake it up. This API will not check
@@ -140,21 +344,45 @@ impl Worker {
             return stat.load(Ordering::Acquire) == 2usize;
         }
 
-        false
+        // the stat is gone with the worker thread, which has quit for whatever reason
+        true
+    }
+
+    /// Check if the worker thread is still running, i.e. it has neither quit nor retired itself.
+    pub(crate) fn is_alive(&self) -> bool {
+        match self.thread.as_ref() {
+            Some(handle) => !handle.is_finished() && !self.is_terminated(),
+            None => false,
+        }
     }
 
     fn spawn_worker(
-        name: Option<String>,
         my_id: usize,
-        stack_size: usize,
-        privileged: bool,
-        rx_pair: (channel::Receiver<Message>, channel::Receiver<Message>),
-        shared_info: (PoolStatus, IdleThreshold),
-    ) -> (thread::JoinHandle<()>, Weak<AtomicUsize>) {
+        config: WorkerConfig,
+        rx_queues: JobQueues,
+        shared_info: WorkerShared,
+    ) -> io::Result<(thread::JoinHandle<()>, Weak<AtomicUsize>, InboxSender)> {
+        let WorkerConfig {
+            name,
+            pool_name,
+            stack_size,
+            privileged,
+            parking,
+            ordering,
+            on_panic,
+            events,
+            on_starved,
+            start_latch,
+            state_hooks,
+            core,
+            #[cfg(feature = "stack-capture")]
+            stack_slot,
+        } = config;
+
         let mut builder = thread::Builder::new();
 
-        if name.is_some() {
-            builder = builder.name(name.unwrap_or_else(|| format!("worker-{}", my_id)));
+        if let Some(name) = name {
+            builder = builder.name(name);
         }
 
         if stack_size > 0 {
@@ -164,11 +392,63 @@ impl Worker {
         let worker_stat = Arc::new(AtomicUsize::new(0));
         let stat_clone = Arc::downgrade(&worker_stat);
 
+        // the local job queue, which will be visible to the siblings as soon as we're spawned, and
+        // removed from the registry when we quit.
+        let lifo = ordering == JobOrdering::Lifo;
+        let local = if lifo {
+            LocalQueue::new_lifo()
+        } else {
+            LocalQueue::new_fifo()
+        };
+        let registration = shared_info.stealers.register(my_id, local.stealer());
+        let clock = shared_info.clocks.register(my_id);
+
+        #[cfg(feature = "stack-capture")]
+        clock.attach_stack(Arc::clone(&stack_slot));
+
+        // the jobs pinned to this worker, which no sibling can take
+        let (inbox_tx, inbox) = channel::unbounded();
+        let inbox_open = Arc::new(RwLock::new(true));
+        let inbox_tx = InboxSender {
+            jobs: inbox_tx,
+            open: Arc::clone(&inbox_open),
+        };
+
         let handle = builder
             .spawn(move || {
+                WORKER_ID.with(|id| id.set(my_id));
+                WORKER_POOL.with(|pool| *pool.borrow_mut() = pool_name);
+
+                #[cfg(feature = "stack-capture")]
+                stack_slot.register();
+
+                // pin to the CPU core before doing anything else
+                if let Some(core) = core {
+                    if !affinity::pin_current(core) && is_debug_mode() {
+                        log(
+                            Level::Warn,
+                            format_args!("Unable to pin worker {} to the CPU core {}", my_id, core),
+                        );
+                    }
+                }
+
+                // create the worker state, which will be torn down however we quit
+                let _state = state_hooks.install(my_id);
+
+                // the worker is ready, report to the spawner if it's waiting for us
+                if let Some(latch) = start_latch {
+                    latch.arrive();
+                }
+
+                let events = events.as_ref();
+                report(events, WorkerEvent::Started(my_id));
+
                 let mut idle_stat: Option<u8>;
                 let mut status: u8;
                 let mut pri_work_count: u8 = 0;
+                let mut upper_work_count: u8 = 0;
+                let mut empty_rounds: usize = 0;
+                let mut idle_rounds: u32 = 0;
 
                 let mut since = if privileged {
                     None
@@ -176,75 +456,220 @@ impl Worker {
                     Some(SystemTime::now())
                 };
 
-                // unpack the shared info triple
-                let (pool_status, idle_threshold) = shared_info;
-                let (pri_wait, norm_wait) = match my_id % LOT_COUNTS {
-                    0 => (true, false),
-                    1 => (false, true),
-                    _ => (false, false),
-                };
+                // the last time we've done a job, used to decide if we can donate idle cycles
+                let mut last_active = Instant::now();
+
+                // unpack the shared info
+                let WorkerShared {
+                    status: pool_status,
+                    idle_threshold,
+                    idle_task,
+                    roles,
+                    stealers,
+                    siblings,
+                    metrics,
+                    ..
+                } = shared_info;
+
+                let _registration = registration;
+                let mut seed = my_id;
+                let taker = JobTaker::new(&metrics);
 
                 // main worker loop
                 loop {
                     // get ready to take new work from the channel
                     if worker_stat.load(Ordering::SeqCst) == 1usize {
+                        // finish the jobs taken from the shared queues before quitting
+                        let stats = (&*metrics, &clock);
+                        let queues = (&local, &inbox, &*inbox_open);
+                        Worker::drain_local(queues, &mut since, (my_id, on_panic, events), stats);
+                        report(events, WorkerEvent::Stopped(my_id));
                         return;
                     }
 
                     // get the pool status code
+                    // check the flags by the bits, the workers may have set the sleep flag on top
                     status = pool_status.load();
-                    if status == FLAG_FORCE_CLOSE
-                        || ((status == FLAG_CLOSING || status == FLAG_REST)
-                            && rx_pair.0.is_empty()
-                            && rx_pair.1.is_empty())
+                    let force_close = status & FLAG_FORCE_CLOSE > 0;
+                    if force_close
+                        || ((pool_status.closing() || status & FLAG_REST > 0)
+                            && rx_queues.0.is_empty()
+                            && rx_queues.1.is_empty()
+                            && rx_queues.2.is_empty()
+                            && local.is_empty()
+                            && inbox.is_empty())
                     {
                         // if shutting down, check if we can abandon all work by checking forced
-                        // close flag, or when all work have been processed.
+                        // close flag, or when all work have been processed. The jobs pinned to us
+                        // in the meantime are run, unless the pool is force closed.
+                        if force_close {
+                            shut_inbox(&inbox_open);
+                        } else {
+                            let queues = (&local, &inbox, &*inbox_open);
+                            let stats = (&*metrics, &clock);
+                            let behavior = (my_id, on_panic, events);
+                            Worker::drain_local(queues, &mut since, behavior, stats);
+                        }
+
                         worker_stat.store(1, Ordering::SeqCst);
+                        metrics.notify_idle();
+                        report(events, WorkerEvent::Stopped(my_id));
                         return;
                     }
 
-                    // wait for work loop
-                    let work = match Worker::check_queues(
-                        &rx_pair.0,
-                        &rx_pair.1,
-                        pri_wait,
-                        norm_wait,
-                        &mut pri_work_count,
+                    // the roles shift as the pool is resized
+                    let (pri_wait, norm_wait) = roles.role(my_id);
+                    let pri_cap = roles.pri_cap();
+
+                    // take the work from the local queue first, then from the shared queues, and
+                    // then steal from the siblings before parking on the shared queues. The local
+                    // queue is only refilled on the deque backend, but its remainder is always
+                    // taken first.
+                    let deque = stealers.is_enabled();
+                    let visible = !inbox.is_empty()
+                        || !local.is_empty()
+                        || !rx_queues.0.is_empty()
+                        || !rx_queues.1.is_empty()
+                        || !rx_queues.2.is_empty()
+                        || (deque && !stealers.all_empty());
+
+                    // only count the take when there's something to take, or an idle pool would be
+                    // seen busy every now and then
+                    let stolen = if !visible {
+                        None
+                    } else {
+                        taker.take(|| {
+                            inbox
+                                .try_recv()
+                                .ok()
+                                .or_else(|| local.pop())
+                                .or_else(|| {
+                                    Worker::take_shared(
+                                        &rx_queues,
+                                        (&local, if deque || lifo { LOCAL_BATCH } else { 0 }, lifo),
+                                        (&mut pri_work_count, &mut upper_work_count, pri_cap),
+                                    )
+                                })
+                                .or_else(|| {
+                                    if !deque {
+                                        return None;
+                                    }
+
+                                    stealers.steal_into(my_id, next_seed(&mut seed), &local)
+                                })
+                                .ok_or(())
+                        })
+                        .ok()
+                    };
+
+                    let work = match stolen {
+                        Some(job) => Some(job),
+                        None => match Worker::check_queues(
+                            &rx_queues,
+                            (pri_wait, norm_wait),
+                            (&parking, &taker),
+                            (&mut pri_work_count, pri_cap),
+                        ) {
+                            // if the channels are disconnected, return
+                            WorkStatus(-1, _) => {
+                                Worker::drain_local(
+                                    (&local, &inbox, &*inbox_open),
+                                    &mut since,
+                                    (my_id, on_panic, events),
+                                    (&metrics, &clock),
+                                );
+                                worker_stat.store(1, Ordering::SeqCst);
+                                report(events, WorkerEvent::Stopped(my_id));
+                                return;
+                            }
+                            WorkStatus(_, job) => job,
+                        },
+                    };
+
+                    // still nothing to do, help out the sibling pools if we're allowed to
+                    let work = work.or_else(|| siblings.steal(next_seed(&mut seed)));
+
+                    // if we keep coming back empty handed while the pool has a backlog, check all
+                    // queues regardless of the preferences and the anti-starvation caps.
+                    let work = if Worker::is_starved(
+                        &rx_queues,
+                        work.is_some(),
+                        &mut empty_rounds,
+                        (my_id, on_starved),
                     ) {
-                        // if the channels are disconnected, return
-                        WorkStatus(-1, _) => {
-                            worker_stat.store(1, Ordering::SeqCst);
-                            return;
-                        }
-                        WorkStatus(_, job) => job,
+                        pri_work_count = 0;
+                        upper_work_count = 0;
+                        taker.take(|| Worker::sweep(&rx_queues).ok_or(())).ok()
+                    } else {
+                        work
                     };
 
+                    let has_work = work.is_some();
+                    if has_work {
+                        // the time since the last job is done counts towards the pool's idle time
+                        metrics.record_idle(last_active.elapsed());
+                    }
+
+                    #[cfg(feature = "stack-capture")]
+                    stack_slot.set_busy(has_work);
+
                     // if there's a job, get it done first, and calc the idle period since last actual job
-                    idle_stat =
-                        // if we have work, do them now
-                        Worker::handle_work(
-                            work,
-                            &mut since
-                        )
-                        .or_else(|| {
-                            // if we don't have the work, calculate the idle period
-                            Worker::calc_idle(&since)
-                        })
-                        .and_then(|idle| {
-                            // if idled longer than the expected worker life for unprivileged workers,
-                            // then we're done now -- self-purging.
-                            let stat_code = idle_threshold.idle_stat(idle.as_secs() as u64);
-
-                            if stat_code > 0 {
-                                // mark self as a voluntary retiree
-                                worker_stat.store(stat_code as usize, Ordering::SeqCst);
-                                return Some(stat_code);
-                            }
+                    let behavior = (my_id, on_panic, events);
+                    let stats = (&*metrics, &clock, Some(&taker));
+                    Worker::handle_work(work, &mut since, behavior, stats);
+
+                    // the message taken may carry no job, e.g. a terminate message
+                    taker.release();
+
+                    idle_stat = Worker::calc_idle(&since).and_then(|idle| {
+                        // if idled longer than the expected worker life for unprivileged workers,
+                        // then we're done now -- self-purging.
+                        let stat_code = idle_threshold.idle_stat(idle.as_millis() as u64);
+
+                        if stat_code > 0 {
+                            // mark self as a voluntary retiree
+                            worker_stat.store(stat_code as usize, Ordering::SeqCst);
+                            return Some(stat_code);
+                        }
 
-                            None
+                        None
+                    });
+
+                    #[cfg(feature = "stack-capture")]
+                    stack_slot.set_busy(false);
+
+                    if has_work {
+                        last_active = Instant::now();
+                        idle_rounds = 0;
+                    } else {
+                        // donate the idle cycles to the background task, which shall yield as soon
+                        // as new jobs arrive, or we're told to quit.
+                        let donated = idle_task.donate(last_active.elapsed(), &|| {
+                            !rx_queues.0.is_empty()
+                                || !rx_queues.1.is_empty()
+                                || !rx_queues.2.is_empty()
+                                || worker_stat.load(Ordering::Acquire) == 1usize
+                                || pool_status.load()
+                                    & (FLAG_CLOSING | FLAG_FORCE_CLOSE | FLAG_HIBERNATING | FLAG_REST)
+                                    > 0
                         });
 
+                        // a panicking idle task is isolated like a panicking job
+                        if let Err(err) = donated {
+                            Worker::caught_panic(err, "the idle task", (my_id, on_panic, events));
+                        }
+
+                        // been idle for a while, stop polling and block on the queues instead
+                        idle_rounds = idle_rounds.saturating_add(1);
+                        if idle_rounds >= parking.idle_rounds {
+                            if metrics.active_workers() == 0 {
+                                metrics.notify_idle();
+                            }
+
+                            Worker::idle_park(&rx_queues, &inbox, parking.idle_timeout);
+                        }
+                    }
+
                     /*
                     // if not done and it's a target kill, handle it now
                     done = done
@@ -281,7 +706,18 @@ impl Worker {
                             pool_status.toggle_flag(FLAG_SLEEP_WORKERS, true);
                             thread::park();
                         },
-                        Some(2) => return,
+                        Some(2) => {
+                            // run the jobs pinned to us while we're retiring
+                            let behavior = (my_id, on_panic, events);
+                            let stats = (&*metrics, &clock);
+                            let queues = (&local, &inbox, &*inbox_open);
+                            Worker::drain_local(queues, &mut since, behavior, stats);
+
+                            metrics.worker_retired();
+                            metrics.notify_idle();
+                            report(events, WorkerEvent::Purged(my_id));
+                            return;
+                        },
                         _ => {}
                     }
 
@@ -289,36 +725,165 @@ impl Worker {
                         thread::park();
                     }
                 }
-            })
-            .unwrap();
+            })?;
+
+        Ok((handle, stat_clone, inbox_tx))
+    }
+
+    /// Take a job from the shared queues without waiting. When taking from the normal queue, also
+    /// move a share of the backlog to the local queue, such that the idle siblings can steal them.
+    /// The priority jobs are taken one at a time to keep their ordering.
+    fn take_shared(
+        (pri_chan, norm_chan, low_chan): &JobQueues,
+        (local, batch, lifo): (&LocalQueue<Job>, usize, bool),
+        (pri_work_count, upper_work_count, pri_cap): (&mut u8, &mut u8, u8),
+    ) -> Option<Job> {
+        // in the lifo ordering, take a batch of the backlog along with the job, and run the newest
+        // job of the batch first. The batch is capped, such that no queue is drained wholesale.
+        let newest = |chan: &channel::Receiver<Message>, job: Option<Job>| {
+            if !lifo {
+                return job;
+            }
+
+            local.push(job?);
+            Worker::move_to_local(chan, local, batch);
+            local.pop()
+        };
+
+        // don't starve the low priority jobs if we've done a run of higher priority jobs in a row
+        if *upper_work_count >= LOW_PRIORITY_CAP || (pri_chan.is_empty() && norm_chan.is_empty()) {
+            if let Ok(message) = low_chan.try_recv() {
+                *upper_work_count = 0;
+                return newest(low_chan, Worker::unpack_message(message).0);
+            }
+        }
+
+        // only count the higher priority jobs taken while the low priority jobs are waiting
+        let count_upper_work = |count: &mut u8| {
+            *count = if low_chan.is_empty() {
+                0
+            } else {
+                count.saturating_add(1).min(LOW_PRIORITY_CAP)
+            };
+        };
+
+        // don't starve the normal jobs if we've done a few priority jobs in a row
+        if *pri_work_count < pri_cap || norm_chan.is_empty() {
+            if let Ok(message) = pri_chan.try_recv() {
+                *pri_work_count = pri_work_count.saturating_add(1).min(pri_cap);
+                count_upper_work(upper_work_count);
+                return newest(pri_chan, Worker::unpack_message(message).0);
+            }
+        }
+
+        let job = Worker::unpack_message(norm_chan.try_recv().ok()?).0;
+        *pri_work_count = 0;
+        count_upper_work(upper_work_count);
+
+        if lifo {
+            return newest(norm_chan, job);
+        }
 
-        (handle, stat_clone)
+        Worker::move_to_local(norm_chan, local, (norm_chan.len() / 4).min(batch));
+        job
+    }
+
+    /// Move up to `count` jobs from the shared queue to the local queue. The batch stops short at a
+    /// terminate message, such that the worker gets back to check if it shall quit right away.
+    fn move_to_local(chan: &channel::Receiver<Message>, local: &LocalQueue<Job>, count: usize) {
+        for _ in 0..count {
+            match chan.try_recv().map(Worker::unpack_message) {
+                Ok((Some(extra), _)) => local.push(extra),
+                _ => break,
+            }
+        }
+    }
+
+    /// Check the invariant that an idle worker shall not keep missing the jobs waiting in the queues.
+    /// The violation is reported in the debug mode and to the `on_starved_worker` observer.
+    fn is_starved(
+        (pri_chan, norm_chan, low_chan): &JobQueues,
+        found: bool,
+        empty_rounds: &mut usize,
+        (my_id, on_starved): (usize, Option<WorkerStarved>),
+    ) -> bool {
+        if found || (pri_chan.is_empty() && norm_chan.is_empty() && low_chan.is_empty()) {
+            *empty_rounds = 0;
+            return false;
+        }
+
+        *empty_rounds += 1;
+        if *empty_rounds < STARVATION_LIMIT {
+            return false;
+        }
+
+        if is_debug_mode() {
+            log(
+                Level::Warn,
+                format_args!(
+                    "Worker {} has missed the queued jobs for {} rounds in a row",
+                    my_id,
+                    empty_rounds
+                ),
+            );
+        }
+
+        if let Some(behavior) = on_starved {
+            behavior(my_id, *empty_rounds);
+        }
+
+        *empty_rounds = 0;
+        true
+    }
+
+    /// Take a job from whichever queue has one, in the order of priority.
+    fn sweep((pri_chan, norm_chan, low_chan): &JobQueues) -> Option<Job> {
+        pri_chan
+            .try_recv()
+            .or_else(|_| norm_chan.try_recv())
+            .or_else(|_| low_chan.try_recv())
+            .ok()
+            .and_then(|message| Worker::unpack_message(message).0)
+    }
+
+    /// Run the remaining jobs in the local queue and the inbox, such that they won't be lost when we
+    /// quit. The inbox is shut first, so no more jobs can be pinned to us.
+    fn drain_local(
+        (local, inbox, inbox_open): (&LocalQueue<Job>, &channel::Receiver<Job>, &RwLock<bool>),
+        since: &mut Option<SystemTime>,
+        behavior: (usize, Option<WorkerPanic>, EventSink<'_>),
+        stats: (&PoolMetrics, &JobClock),
+    ) {
+        shut_inbox(inbox_open);
+
+        while let Some(job) = local.pop().or_else(|| inbox.try_recv().ok()) {
+            Worker::handle_work(Some(job), since, behavior, (stats.0, stats.1, None));
+        }
     }
 
     fn check_queues(
-        pri_chan: &channel::Receiver<Message>,
-        norm_chan: &channel::Receiver<Message>,
-        pri_wait: bool,
-        norm_wait: bool,
-        pri_work_count: &mut u8,
+        (pri_chan, norm_chan, low_chan): &JobQueues,
+        (pri_wait, norm_wait): (bool, bool),
+        (parking, taker): (&ParkingProfile, &JobTaker),
+        (pri_work_count, pri_cap): (&mut u8, u8),
     ) -> WorkStatus {
-        // wait for work loop, 1/3 of workers will long-park for priority work, and 1/3 of workers
-        // will long-park for normal work, the remainder 1/3 workers will be fluid and constantly
-        // query both queues -- whichever yield a task, then it will execute that task.
+        // wait for work loop, per the fetch strategy some workers will long-park for priority work,
+        // and some will long-park for normal work, the others will be fluid and constantly query
+        // both queues -- whichever yield a task, then it will execute that task.
         if *pri_work_count < 255 {
-            // 1/3 of the workers is designated to wait longer for prioritised jobs
+            // the workers favoring the priority jobs wait longer for them
             let norm_full = norm_chan.is_full();
 
-            match Worker::fetch_work(pri_chan, norm_full && !pri_wait) {
+            match Worker::fetch_work(pri_chan, norm_full && !pri_wait, pri_wait, (parking, taker)) {
                 Ok(message) => {
                     // message is the only place that can update the "done" field
                     let (job, _) = Worker::unpack_message(message);
 
-                    if *pri_work_count < 4 {
+                    if *pri_work_count < pri_cap {
                         // only add if we're below the continuous pri-work cap
                         *pri_work_count += 1;
                     } else if norm_full {
-                        // if we've done 4 or more priority work in a row, check if
+                        // if we've done the capped priority work in a row, check if
                         // we should skip if the normal channel is full and maybe
                         // blocking, by setting the special number
                         *pri_work_count = 255;
@@ -336,14 +901,15 @@ impl Worker {
                 }
             };
         } else {
-            // if the worker has performed 4 consecutive prioritized work and the normal
+            // if the worker has performed the capped consecutive prioritized work and the normal
             // channel is full, we skip the priority work once to pick up a normal work
             // such that it won't be blocked forever; meanwhile, reset the counter.
             *pri_work_count = 0;
         }
 
-        // 1/3 of the workers is designated to wait longer for normal jobs
-        match Worker::fetch_work(norm_chan, pri_chan.is_full() && !norm_wait) {
+        // the workers favoring the normal jobs wait longer for them
+        let can_skip = pri_chan.is_full() && !norm_wait;
+        match Worker::fetch_work(norm_chan, can_skip, norm_wait, (parking, taker)) {
             Ok(message) => {
                 // message is the only place that can update the "done" field
                 let (job, _) = Worker::unpack_message(message);
@@ -356,65 +922,152 @@ impl Worker {
                 return WorkStatus(-1, None);
             }
             Err(channel::RecvTimeoutError::Timeout) => {
-                // nothing to receive yet
+                // nothing to receive yet, last chance for the low priority jobs
+                if !low_chan.is_empty() {
+                    if let Ok(message) = taker.take(|| low_chan.try_recv()) {
+                        return WorkStatus(0, Worker::unpack_message(message).0);
+                    }
+                }
             }
         };
 
         WorkStatus(0, None)
     }
 
+    /// Block until any of the queues has a job, or the `timeout` has passed. The job is left in the
+    /// queue, such that it's taken in the order of the queue preferences in the next round.
+    fn idle_park(
+        (pri_chan, norm_chan, low_chan): &JobQueues,
+        inbox: &channel::Receiver<Job>,
+        timeout: Duration,
+    ) {
+        let mut select = channel::Select::new();
+        select.recv(pri_chan);
+        select.recv(norm_chan);
+        select.recv(low_chan);
+        select.recv(inbox);
+
+        // timing out is fine, we only need to come back and check the pool status every now and then
+        let _ = select.ready_timeout(timeout);
+    }
+
     fn fetch_work(
         main_chan: &channel::Receiver<Message>,
         can_skip: bool,
+        long_park: bool,
+        (parking, taker): (&ParkingProfile, &JobTaker),
     ) -> Result<Message, channel::RecvTimeoutError> {
         let mut wait = 0;
         let rounds = if can_skip {
-            SHORT_PARKING_ROUNDS
+            parking.short_rounds
         } else {
-            LONG_PARKING_ROUNDS
+            parking.long_rounds
         };
 
         loop {
             wait += 1;
 
-            match main_chan.try_recv() {
-                Ok(work) => return Ok(work),
-                Err(channel::TryRecvError::Disconnected) => {
-                    return Err(channel::RecvTimeoutError::Disconnected)
-                }
-                Err(channel::TryRecvError::Empty) => {
-                    if can_skip {
-                        // if there're normal work in queue, break to fetch the normal work
-                        return Err(channel::RecvTimeoutError::Timeout);
-                    }
+            // the disconnected queue is empty as well, and is found out once done with the rounds
+            if !main_chan.is_empty() {
+                if let Ok(work) = taker.take(|| main_chan.try_recv()) {
+                    return Ok(work);
                 }
             }
 
-            if wait > rounds {
+            if can_skip {
+                // if there're normal work in queue, break to fetch the normal work
                 return Err(channel::RecvTimeoutError::Timeout);
             }
+
+            if wait > rounds {
+                // done with the spinning rounds, park on the queue for a short while before moving
+                // on if the profile asks for it, designated workers will park longer on their
+                // queue.
+                let timeout = if long_park {
+                    parking.long_timeout
+                } else {
+                    parking.timeout
+                };
+
+                // wait for the job without taking it, such that the take is counted from the start
+                let mut select = channel::Select::new();
+                select.recv(main_chan);
+
+                let ready = match timeout {
+                    Some(timeout) => select.ready_timeout(timeout).is_ok(),
+                    None => select.try_ready().is_ok(),
+                };
+
+                if !ready {
+                    return Err(channel::RecvTimeoutError::Timeout);
+                }
+
+                return taker.take(|| main_chan.try_recv()).map_err(|err| match err {
+                    channel::TryRecvError::Disconnected => channel::RecvTimeoutError::Disconnected,
+                    channel::TryRecvError::Empty => channel::RecvTimeoutError::Timeout,
+                });
+            }
         }
     }
 
-    fn handle_work(work: Option<Job>, since: &mut Option<SystemTime>) -> Option<Duration> {
+    fn handle_work(
+        work: Option<Job>,
+        since: &mut Option<SystemTime>,
+        (my_id, on_panic, events): (usize, Option<WorkerPanic>, EventSink<'_>),
+        (metrics, clock, taker): (&PoolMetrics, &JobClock, Option<&JobTaker>),
+    ) {
         if let Some(w) = work {
-            w.call_box();
+            let start = Instant::now();
+            metrics.job_started();
+            clock.start();
+
+            // the job counts as running from now on, no longer as being taken
+            if let Some(taker) = taker {
+                taker.release();
+            }
+
+            // isolate the panic in the job, such that the worker can live on to take new jobs
+            let result = panic::catch_unwind(AssertUnwindSafe(|| w.call_box()));
+            clock.stop();
+            metrics.job_finished();
+
+            if let Err(err) = result {
+                Worker::caught_panic(err, "the job", (my_id, on_panic, events));
+            } else {
+                metrics.record(start.elapsed());
+            }
+
+            // just done a job, so the idle period starts over
+            if since.is_some() {
+                since.replace(SystemTime::now());
+            }
+        }
+    }
+
+    /// Report a panic caught on the worker to the debug log, the panic hook and the event sink.
+    fn caught_panic(
+        err: Box<dyn Any + Send>,
+        source: &str,
+        (my_id, on_panic, events): (usize, Option<WorkerPanic>, EventSink<'_>),
+    ) {
+        if is_debug_mode() {
+            log(
+                Level::Warn,
+                format_args!("Worker {} has caught a panic from {}", my_id, source),
+            );
         }
 
-        let mut idle = None;
-        if since.is_some() {
-            idle = Worker::calc_idle(&since);
-            since.replace(SystemTime::now());
+        if let Some(behavior) = on_panic {
+            behavior(my_id, err.as_ref());
         }
 
-        idle
+        report(events, WorkerEvent::PanicCaught(my_id));
     }
 
     fn unpack_message(message: Message) -> (Option<Job>, Option<Vec<usize>>) {
         match message {
-            Message::SingleJob(job) => (Some(job), None),
-            Message::ChainedJobs(_) => unreachable!(),
             Message::Terminate(target) => (None, Some(target)),
+            message => (message.into_job(), None),
         }
     }
 
@@ -429,6 +1082,67 @@ impl Worker {
     }
 }
 
+/// Counts the worker as taking a job in the metrics, from right before a dequeue until the job is
+/// started, see `PoolMetrics::take_started`.
+struct JobTaker<'a> {
+    metrics: &'a PoolMetrics,
+    held: Cell<bool>,
+}
+
+impl<'a> JobTaker<'a> {
+    fn new(metrics: &'a PoolMetrics) -> Self {
+        JobTaker {
+            metrics,
+            held: Cell::new(false),
+        }
+    }
+
+    /// Run the dequeue, and keep the worker counted as taking a job if the dequeue yields anything.
+    fn take<T, E>(&self, dequeue: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let counted = !self.held.get();
+        if counted {
+            self.metrics.take_started();
+        }
+
+        let taken = dequeue();
+        if taken.is_ok() {
+            self.held.set(true);
+        } else if counted {
+            self.metrics.take_finished();
+        }
+
+        taken
+    }
+
+    /// The job taken has been started, or there's no job to start after all.
+    fn release(&self) {
+        if self.held.replace(false) {
+            self.metrics.take_finished();
+        }
+    }
+}
+
+impl<'a> Drop for JobTaker<'a> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Report the event to the sink without blocking, the event is dropped if the sink can't take it.
+fn report(events: EventSink, event: WorkerEvent) {
+    if let Some(sink) = events {
+        let _ = sink.try_send(event);
+    }
+}
+
+/// A cheap xorshift to pick the sibling to steal from.
+fn next_seed(seed: &mut usize) -> usize {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed
+}
+
 impl Drop for Worker {
     fn drop(&mut self) {
         if let Some(behavior) = self.before_drop {
@@ -436,7 +1150,7 @@ impl Drop for Worker {
         }
 
         if is_debug_mode() {
-            println!("Dropping worker {}", self.id);
+            log(Level::Info, format_args!("Dropping worker {}", self.id));
         }
 
         self.retire();